@@ -10,6 +10,7 @@
 //! the unsafe code guidelines).
 //!
 //! TODO: Would like to use std-lib here.
+use half::f16;
 use std::{mem, slice};
 
 macro_rules! integral_slice_as_bytes{($int:ty, $const:ident $(,$mut:ident)*) => {
@@ -30,5 +31,24 @@ integral_slice_as_bytes!(u32, u32_as_ne_bytes, u32_as_ne_mut_bytes);
 integral_slice_as_bytes!(i32, i32_as_ne_bytes, i32_as_ne_mut_bytes);
 integral_slice_as_bytes!(u64, u64_as_ne_bytes, u64_as_ne_mut_bytes);
 integral_slice_as_bytes!(i64, i64_as_ne_bytes, i64_as_ne_mut_bytes);
+integral_slice_as_bytes!(f16, f16_as_ne_bytes, f16_as_ne_mut_bytes);
 integral_slice_as_bytes!(f32, f32_as_ne_bytes, f32_as_ne_mut_bytes);
 integral_slice_as_bytes!(f64, f64_as_ne_bytes, f64_as_ne_mut_bytes);
+integral_slice_as_bytes!([i16; 2], complex_i16_as_ne_bytes);
+integral_slice_as_bytes!([i32; 2], complex_i32_as_ne_bytes);
+integral_slice_as_bytes!([f32; 2], complex_f32_as_ne_bytes);
+integral_slice_as_bytes!([f64; 2], complex_f64_as_ne_bytes);
+
+macro_rules! complex_slice_as_parts {
+    ($part:ty, $mut:ident) => {
+        pub(crate) fn $mut(slice: &mut [[$part; 2]]) -> &mut [$part] {
+            // An array has the layout of its consecutive elements.
+            unsafe { slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut $part, slice.len() * 2) }
+        }
+    };
+}
+
+complex_slice_as_parts!(i16, complex_i16_as_mut_parts);
+complex_slice_as_parts!(i32, complex_i32_as_mut_parts);
+complex_slice_as_parts!(f32, complex_f32_as_mut_parts);
+complex_slice_as_parts!(f64, complex_f64_as_mut_parts);
@@ -105,15 +105,31 @@ impl Value {
     pub fn into_f32(self) -> TiffResult<f32> {
         match self {
             Float(val) => Ok(val),
+            Rational(numerator, denominator) => Ok(numerator as f32 / denominator as f32),
+            RationalBig(numerator, denominator) => Ok(numerator as f32 / denominator as f32),
+            SRational(numerator, denominator) => Ok(numerator as f32 / denominator as f32),
+            SRationalBig(numerator, denominator) => Ok(numerator as f32 / denominator as f32),
             val => Err(TiffError::FormatError(
                 TiffFormatError::SignedIntegerExpected(val),
             )),
         }
     }
 
+    /// Converts any numeric value to a double, which may round integers of more than 53 bits.
     pub fn into_f64(self) -> TiffResult<f64> {
         match self {
             Double(val) => Ok(val),
+            Float(val) => Ok(val.into()),
+            Byte(val) => Ok(val.into()),
+            Short(val) => Ok(val.into()),
+            Unsigned(val) => Ok(val.into()),
+            UnsignedBig(val) => Ok(val as f64),
+            Signed(val) => Ok(val.into()),
+            SignedBig(val) => Ok(val as f64),
+            Rational(numerator, denominator) => Ok(f64::from(numerator) / f64::from(denominator)),
+            RationalBig(numerator, denominator) => Ok(numerator as f64 / denominator as f64),
+            SRational(numerator, denominator) => Ok(f64::from(numerator) / f64::from(denominator)),
+            SRationalBig(numerator, denominator) => Ok(numerator as f64 / denominator as f64),
             val => Err(TiffError::FormatError(
                 TiffFormatError::SignedIntegerExpected(val),
             )),
@@ -227,6 +243,9 @@ impl Value {
                 Ok(new_vec)
             }
             Float(val) => Ok(vec![val]),
+            val @ (Rational(..) | RationalBig(..) | SRational(..) | SRationalBig(..)) => {
+                Ok(vec![val.into_f32()?])
+            }
             val => Err(TiffError::FormatError(
                 TiffFormatError::UnsignedIntegerExpected(val),
             )),
@@ -242,10 +261,7 @@ impl Value {
                 }
                 Ok(new_vec)
             }
-            Double(val) => Ok(vec![val]),
-            val => Err(TiffError::FormatError(
-                TiffFormatError::UnsignedIntegerExpected(val),
-            )),
+            val => Ok(vec![val.into_f64()?]),
         }
     }
 
@@ -332,6 +348,11 @@ impl Entry {
         }
     }
 
+    /// Returns the type, count and offset/value field, as stored in the file.
+    pub(crate) fn raw(&self) -> (Type, u64, [u8; 8]) {
+        (self.type_, self.count, self.offset)
+    }
+
     /// Returns a mem_reader for the offset/value field
     fn r(&self, byte_order: ByteOrder) -> SmartReader<io::Cursor<Vec<u8>>> {
         SmartReader::wrap(io::Cursor::new(self.offset.to_vec()), byte_order)
@@ -668,3 +689,10 @@ fn offset_to_sbytes(n: usize, entry: &Entry) -> TiffResult<Value> {
 
 /// Type representing an Image File Directory
 pub type Directory = HashMap<Tag, Entry>;
+
+/// A tag with its type, count and values in native byte order.
+pub(crate) type NativeTag = (Tag, Type, u64, Vec<u8>);
+
+/// An entry of a directory as it is stored: its tag, type, count and the bytes of its value or
+/// offset, in native byte order.
+pub(crate) type RawEntry = (u16, u16, u64, Vec<u8>);
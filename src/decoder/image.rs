@@ -1,12 +1,24 @@
 use super::ifd::{Directory, Value};
-use super::stream::{ByteOrder, DeflateReader, JpegReader, LZWReader, PackBitsReader};
+use super::lab::Lab;
+use super::old_jpeg::OldJpeg;
+#[cfg(feature = "zstd")]
+use super::stream::ZstdReader;
+use super::stream::{
+    ByteOrder, DeflateReader, JpegReader, LZWReader, PackBitsReader, ReverseBitsReader,
+};
 use super::tag_reader::TagReader;
-use super::{fp_predict_f32, fp_predict_f64, DecodingBuffer, Limits};
+use super::ycbcr::YCbCr;
+use super::{fp_predict_f16, fp_predict_f32, fp_predict_f64, DecodingBuffer, Limits};
 use super::{stream::SmartReader, ChunkType};
-use crate::tags::{CompressionMethod, PhotometricInterpretation, Predictor, SampleFormat, Tag};
+use crate::lerc;
+use crate::tags::{
+    CompressionMethod, ExtraSample, FillOrder, LercCompression, PhotometricInterpretation,
+    PlanarConfiguration, Predictor, SampleFormat, Tag,
+};
 use crate::{ColorType, TiffError, TiffFormatError, TiffResult, TiffUnsupportedError, UsageError};
 use std::convert::{TryFrom, TryInto};
 use std::io::{self, Cursor, Read, Seek};
+use std::ops::Range;
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -57,24 +69,39 @@ impl TileAttributes {
     }
 }
 
+/// The conversions that are applied to the samples of a chunk while expanding it.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Conversions {
+    pub unassociate_alpha: bool,
+    pub ycbcr_to_rgb: bool,
+    pub lab_to_srgb: bool,
+    pub xyz_to_rgb: bool,
+}
+
 #[derive(Debug)]
 pub(crate) struct Image {
     pub ifd: Option<Directory>,
     pub width: u32,
     pub height: u32,
     pub bits_per_sample: Vec<u8>,
-    #[allow(unused)]
     pub samples: u8,
     pub sample_format: Vec<SampleFormat>,
+    pub extra_samples: Vec<ExtraSample>,
     pub photometric_interpretation: PhotometricInterpretation,
     pub compression_method: CompressionMethod,
+    pub fill_order: FillOrder,
+    pub planar_config: PlanarConfiguration,
     pub predictor: Predictor,
     pub jpeg_tables: Option<Arc<Vec<u8>>>,
+    pub old_jpeg: Option<Result<OldJpeg, TiffFormatError>>,
+    pub lerc_compression: LercCompression,
     pub chunk_type: ChunkType,
     pub strip_decoder: Option<StripDecodeState>,
     pub tile_attributes: Option<TileAttributes>,
     pub chunk_offsets: Vec<u64>,
     pub chunk_bytes: Vec<u64>,
+    pub ycbcr: Option<YCbCr>,
+    pub lab: Option<Lab>,
 }
 
 impl Image {
@@ -131,6 +158,19 @@ impl Image {
             None
         };
 
+        // The first of the `LercParameters` is the LERC version, which blobs also state.
+        let lerc_compression = match tag_reader.find_tag_uint_vec::<u16>(Tag::LercParameters)? {
+            Some(params) if compression_method == CompressionMethod::Lerc => params
+                .get(1)
+                .map_or(Some(LercCompression::None), |&n| {
+                    LercCompression::from_u16(n)
+                })
+                .ok_or(TiffError::FormatError(
+                    TiffFormatError::InvalidTagValueType(Tag::LercParameters),
+                ))?,
+            _ => LercCompression::None,
+        };
+
         let samples = tag_reader
             .find_tag(Tag::SamplesPerPixel)?
             .map(Value::into_u16)
@@ -139,27 +179,67 @@ impl Image {
             .try_into()?;
 
         let sample_format = match tag_reader.find_tag_uint_vec(Tag::SampleFormat)? {
-            Some(vals) => {
-                let sample_format: Vec<_> = vals
-                    .into_iter()
-                    .map(SampleFormat::from_u16_exhaustive)
-                    .collect();
-
-                // TODO: for now, only homogenous formats across samples are supported.
-                if !sample_format.windows(2).all(|s| s[0] == s[1]) {
-                    return Err(TiffUnsupportedError::UnsupportedSampleFormat(sample_format).into());
-                }
-
-                sample_format
-            }
+            Some(vals) => vals
+                .into_iter()
+                .map(SampleFormat::from_u16_exhaustive)
+                .collect(),
             None => vec![SampleFormat::Uint],
         };
 
         let bits_per_sample = match samples {
-            1 | 3 | 4 => tag_reader
+            0 => return Err(TiffUnsupportedError::UnsupportedSampleDepth(samples).into()),
+            _ => tag_reader
                 .find_tag_uint_vec(Tag::BitsPerSample)?
                 .unwrap_or_else(|| vec![1]),
-            _ => return Err(TiffUnsupportedError::UnsupportedSampleDepth(samples).into()),
+        };
+
+        // SGI log compression stores luminance in its own encoding, which is decoded to floats.
+        let (bits_per_sample, sample_format) =
+            match (photometric_interpretation, compression_method) {
+                (
+                    PhotometricInterpretation::LogL | PhotometricInterpretation::LogLuv,
+                    CompressionMethod::SGILog | CompressionMethod::SGILog24,
+                ) => (vec![32; usize::from(samples)], vec![SampleFormat::IEEEFP]),
+                _ => (bits_per_sample, sample_format),
+            };
+
+        let extra_samples: Vec<_> = tag_reader
+            .find_tag_uint_vec(Tag::ExtraSamples)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(ExtraSample::from_u16)
+            .collect::<Option<_>>()
+            .ok_or(TiffFormatError::InvalidTagValueType(Tag::ExtraSamples))?;
+        if extra_samples.len() >= usize::from(samples) {
+            return Err(TiffFormatError::InvalidTagValueType(Tag::ExtraSamples).into());
+        }
+
+        let fill_order = tag_reader
+            .find_tag(Tag::FillOrder)?
+            .map(Value::into_u16)
+            .transpose()?
+            .map(|f| {
+                FillOrder::from_u16(f).ok_or(TiffError::FormatError(
+                    TiffFormatError::InvalidTagValueType(Tag::FillOrder),
+                ))
+            })
+            .transpose()?
+            .unwrap_or(FillOrder::MsbToLsb);
+
+        let planar_config = tag_reader
+            .find_tag(Tag::PlanarConfiguration)?
+            .map(Value::into_u16)
+            .transpose()?
+            .map(|p| {
+                PlanarConfiguration::from_u16(p)
+                    .ok_or(TiffUnsupportedError::UnsupportedPlanarConfig(None))
+            })
+            .transpose()?
+            .unwrap_or(PlanarConfiguration::Chunky);
+        // Each plane holds one sample of every pixel and is stored in its own set of chunks.
+        let planes = match planar_config {
+            PlanarConfiguration::Chunky => 1,
+            PlanarConfiguration::Planar => u64::from(samples),
         };
 
         let predictor = tag_reader
@@ -173,6 +253,13 @@ impl Image {
             .transpose()?
             .unwrap_or(Predictor::None);
 
+        let ycbcr = if photometric_interpretation == PhotometricInterpretation::YCbCr {
+            Some(YCbCr::from_tags(&mut tag_reader)?)
+        } else {
+            None
+        };
+        let lab = Lab::from_tags(&mut tag_reader, photometric_interpretation)?;
+
         let chunk_type;
         let chunk_offsets;
         let chunk_bytes;
@@ -205,8 +292,8 @@ impl Image {
 
                 if chunk_offsets.len() != chunk_bytes.len()
                     || rows_per_strip == 0
-                    || u32::try_from(chunk_offsets.len())?
-                        != height.saturating_sub(1) / rows_per_strip + 1
+                    || u64::try_from(chunk_offsets.len())?
+                        != u64::from(height.saturating_sub(1) / rows_per_strip + 1) * planes
                 {
                     return Err(TiffError::FormatError(
                         TiffFormatError::InconsistentSizesEncountered,
@@ -245,7 +332,8 @@ impl Image {
 
                 let tile = tile_attributes.as_ref().unwrap();
                 if chunk_offsets.len() != chunk_bytes.len()
-                    || chunk_offsets.len() != tile.tiles_down() * tile.tiles_across()
+                    || u64::try_from(chunk_offsets.len())?
+                        != u64::try_from(tile.tiles_down() * tile.tiles_across())? * planes
                 {
                     return Err(TiffError::FormatError(
                         TiffFormatError::InconsistentSizesEncountered,
@@ -259,6 +347,47 @@ impl Image {
             }
         };
 
+        let old_jpeg = if compression_method == CompressionMethod::JPEG {
+            let chunk_ranges = chunk_offsets
+                .iter()
+                .copied()
+                .zip(chunk_bytes.iter().copied());
+            // Malformed tables only concern the image data, so they are reported for its chunks.
+            match OldJpeg::from_tags(
+                &mut tag_reader,
+                photometric_interpretation,
+                (width, height),
+                samples,
+                chunk_ranges,
+            ) {
+                Ok(old_jpeg) => Some(Ok(old_jpeg)),
+                Err(TiffError::LimitsExceeded) => return Err(TiffError::LimitsExceeded),
+                Err(TiffError::FormatError(err)) => Some(Err(err)),
+                Err(err) => Some(Err(TiffFormatError::Format(format!(
+                    "Old-style JPEG tables can not be read: {}",
+                    err
+                )))),
+            }
+        } else {
+            None
+        };
+
+        // A stream of the whole image replaces the strips, which usually point into it.
+        let (chunk_offsets, chunk_bytes, strip_decoder) = match &old_jpeg {
+            Some(Ok(OldJpeg {
+                whole_image: Some((offset, length)),
+                ..
+            })) if chunk_type == ChunkType::Strip
+                && planar_config == PlanarConfiguration::Chunky =>
+            {
+                let strip_decoder = StripDecodeState {
+                    rows_per_strip: height,
+                };
+                (vec![*offset], vec![*length], Some(strip_decoder))
+            }
+            _ => (chunk_offsets, chunk_bytes, strip_decoder),
+        };
+
         Ok(Image {
             ifd: Some(ifd),
             width,
@@ -266,78 +395,151 @@ impl Image {
             bits_per_sample,
             samples,
             sample_format,
+            extra_samples,
             photometric_interpretation,
             compression_method,
+            fill_order,
+            planar_config,
             jpeg_tables,
+            old_jpeg,
+            lerc_compression,
             predictor,
             chunk_type,
             strip_decoder,
             tile_attributes,
             chunk_offsets,
             chunk_bytes,
+            ycbcr,
+            lab,
         })
     }
 
     pub(crate) fn colortype(&self) -> TiffResult<ColorType> {
-        match self.photometric_interpretation {
+        // Any samples beyond the color channels are extra samples, see `ExtraSamples`. All of
+        // them are kept in the decoded pixels.
+        let multiband = |bits: &[u8]| match bits {
+            [n, rest @ ..] if rest.iter().all(|b| b == n) => Some(ColorType::Multiband {
+                bit_depth: *n,
+                num_samples: bits.len() as u16,
+            }),
+            _ => None,
+        };
+
+        let color_type = match self.photometric_interpretation {
             PhotometricInterpretation::RGB => match self.bits_per_sample[..] {
-                [r, g, b] if [r, r] == [g, b] => Ok(ColorType::RGB(r)),
-                [r, g, b, a] if [r, r, r] == [g, b, a] => Ok(ColorType::RGBA(r)),
-                // FIXME: We should _ignore_ other components. In particular:
-                // > Beware of extra components. Some TIFF files may have more components per pixel
-                // than you think. A Baseline TIFF reader must skip over them gracefully,using the
-                // values of the SamplesPerPixel and BitsPerSample fields.
-                // > -- TIFF 6.0 Specification, Section 7, Additional Baseline requirements.
-                _ => Err(TiffError::UnsupportedError(
-                    TiffUnsupportedError::InterpretationWithBits(
-                        self.photometric_interpretation,
-                        self.bits_per_sample.clone(),
-                    ),
-                )),
+                [r, g, b] if [r, r] == [g, b] => Some(ColorType::RGB(r)),
+                [r, g, b, a] if [r, r, r] == [g, b, a] => Some(ColorType::RGBA(r)),
+                ref bits if bits.len() > 4 => multiband(bits),
+                _ => None,
             },
             PhotometricInterpretation::CMYK => match self.bits_per_sample[..] {
-                [c, m, y, k] if [c, c, c] == [m, y, k] => Ok(ColorType::CMYK(c)),
-                _ => Err(TiffError::UnsupportedError(
-                    TiffUnsupportedError::InterpretationWithBits(
-                        self.photometric_interpretation,
-                        self.bits_per_sample.clone(),
-                    ),
-                )),
+                [c, m, y, k] if [c, c, c] == [m, y, k] => Some(ColorType::CMYK(c)),
+                ref bits if bits.len() > 4 => multiband(bits),
+                _ => None,
             },
             PhotometricInterpretation::YCbCr => match self.bits_per_sample[..] {
-                [y, cb, cr] if [y, y] == [cb, cr] => Ok(ColorType::YCbCr(y)),
-                _ => Err(TiffError::UnsupportedError(
-                    TiffUnsupportedError::InterpretationWithBits(
-                        self.photometric_interpretation,
-                        self.bits_per_sample.clone(),
-                    ),
-                )),
+                [y, cb, cr] if [y, y] == [cb, cr] => Some(ColorType::YCbCr(y)),
+                _ => None,
             },
-            PhotometricInterpretation::BlackIsZero | PhotometricInterpretation::WhiteIsZero
-                if self.bits_per_sample.len() == 1 =>
-            {
-                Ok(ColorType::Gray(self.bits_per_sample[0]))
+            PhotometricInterpretation::LogL => match self.bits_per_sample[..] {
+                [32] => Some(ColorType::Gray(32)),
+                _ => None,
+            },
+            PhotometricInterpretation::LogLuv => match self.bits_per_sample[..] {
+                [32, 32, 32] => Some(ColorType::XYZ(32)),
+                _ => None,
+            },
+            PhotometricInterpretation::CIELab
+            | PhotometricInterpretation::ICCLab
+            | PhotometricInterpretation::ITULab => match self.bits_per_sample[..] {
+                // Only L* is stored for images without color.
+                [n] if n == 8 || n == 16 => Some(ColorType::Gray(n)),
+                [l, a, b] if [l, l] == [a, b] && (l == 8 || l == 16) => Some(ColorType::Lab(l)),
+                _ => None,
+            },
+            PhotometricInterpretation::BlackIsZero | PhotometricInterpretation::WhiteIsZero => {
+                match self.bits_per_sample[..] {
+                    [n] => Some(ColorType::Gray(n)),
+                    [n, a] if n == a => Some(ColorType::GrayA(n)),
+                    ref bits if bits.len() > 2 => multiband(bits),
+                    _ => None,
+                }
             }
 
             // TODO: this is bad we should not fail at this point
-            _ => Err(TiffError::UnsupportedError(
-                TiffUnsupportedError::InterpretationWithBits(
-                    self.photometric_interpretation,
-                    self.bits_per_sample.clone(),
-                ),
-            )),
+            _ => None,
+        };
+
+        color_type.ok_or_else(|| {
+            TiffError::UnsupportedError(TiffUnsupportedError::InterpretationWithBits(
+                self.photometric_interpretation,
+                self.bits_per_sample.clone(),
+            ))
+        })
+    }
+
+    /// The number of color channels of a pixel, and the index of its premultiplied alpha channel
+    /// if there is one.
+    pub(crate) fn associated_alpha(&self) -> Option<(usize, usize)> {
+        let colors = self
+            .bits_per_sample
+            .len()
+            .saturating_sub(self.extra_samples.len());
+        self.extra_samples
+            .iter()
+            .position(|&extra| extra == ExtraSample::AssociatedAlpha)
+            .map(|index| (colors, colors + index))
+    }
+
+    /// The bit depth of an image whose L*a*b* samples can be converted to sRGB.
+    pub(crate) fn srgb_conversion(&self) -> Option<u8> {
+        match self.colortype() {
+            Ok(ColorType::Lab(n)) if self.planar_config == PlanarConfiguration::Chunky => Some(n),
+            _ => None,
+        }
+    }
+
+    /// The encoding of an image whose 8-bit YCbCr samples can be converted to RGB.
+    pub(crate) fn rgb_conversion(&self) -> Option<&YCbCr> {
+        match self.colortype() {
+            Ok(ColorType::YCbCr(8)) if self.planar_config == PlanarConfiguration::Chunky => {
+                self.ycbcr.as_ref()
+            }
+            _ => None,
         }
     }
 
     fn create_reader<'r, R: 'r + Read>(
+        &self,
         reader: R,
-        photometric_interpretation: PhotometricInterpretation,
-        compression_method: CompressionMethod,
+        chunk_index: u32,
         compressed_length: u64,
-        jpeg_tables: Option<Arc<Vec<u8>>>,
+        byte_order: ByteOrder,
     ) -> TiffResult<Box<dyn Read + 'r>> {
-        Ok(match compression_method {
-            CompressionMethod::None => Box::new(reader),
+        let compression_method = self.compression_method;
+        let jpeg_tables = self.jpeg_tables.clone();
+
+        // The fill order describes the bits of the raw data and is undone before any decompression.
+        // Like libtiff, we do not apply it to codecs that have their own byte-oriented framing.
+        let reader: Box<dyn Read + 'r> = match (self.fill_order, compression_method) {
+            (
+                FillOrder::LsbToMsb,
+                CompressionMethod::JPEG
+                | CompressionMethod::ModernJPEG
+                | CompressionMethod::Deflate
+                | CompressionMethod::OldDeflate
+                | CompressionMethod::ZSTD
+                | CompressionMethod::WebP
+                | CompressionMethod::Lerc
+                | CompressionMethod::SGILog
+                | CompressionMethod::SGILog24,
+            ) => Box::new(reader),
+            (FillOrder::LsbToMsb, _) => Box::new(ReverseBitsReader::new(reader)),
+            _ => Box::new(reader),
+        };
+
+        let reader: Box<dyn Read + 'r> = match compression_method {
+            CompressionMethod::None => reader,
             CompressionMethod::LZW => {
                 Box::new(LZWReader::new(reader, usize::try_from(compressed_length)?))
             }
@@ -345,6 +547,88 @@ impl Image {
             CompressionMethod::Deflate | CompressionMethod::OldDeflate => {
                 Box::new(DeflateReader::new(reader))
             }
+            #[cfg(feature = "zstd")]
+            CompressionMethod::ZSTD => Box::new(ZstdReader::new(reader)?),
+            CompressionMethod::Lerc => {
+                let mut blob = Vec::new();
+                reader.take(compressed_length).read_to_end(&mut blob)?;
+                let blob = match self.lerc_compression {
+                    LercCompression::None => blob,
+                    LercCompression::Deflate => {
+                        let mut inflated = Vec::new();
+                        DeflateReader::new(&blob[..]).read_to_end(&mut inflated)?;
+                        inflated
+                    }
+                    #[cfg(feature = "zstd")]
+                    LercCompression::Zstd => zstd::stream::decode_all(&blob[..])?,
+                    #[cfg(not(feature = "zstd"))]
+                    LercCompression::Zstd => {
+                        return Err(TiffError::UnsupportedError(
+                            TiffUnsupportedError::UnsupportedCompressionMethod(
+                                CompressionMethod::ZSTD,
+                            ),
+                        ))
+                    }
+                };
+
+                // Blobs hold at most the samples of a full chunk, which bounds the decoded size.
+                let chunk_dims = self.chunk_dimensions()?;
+                let max_values = (chunk_dims.0 as usize)
+                    .checked_mul(chunk_dims.1 as usize)
+                    .and_then(|pixels| pixels.checked_mul(self.samples_per_chunk()))
+                    .ok_or(TiffError::LimitsExceeded)?;
+                let mut raster = lerc::decode(&blob, max_values)?;
+                // Like libtiff, the mask of 8-bit blobs stands for a trailing alpha sample, and
+                // masked out floating point samples are NaN.
+                let alpha = self.extra_samples.last() == Some(&ExtraSample::UnassociatedAlpha);
+                if raster.data_type == lerc::DataType::Byte
+                    && alpha
+                    && raster.depth + 1 == self.samples_per_chunk()
+                {
+                    raster.mask_to_alpha(f64::from(u8::MAX));
+                } else if matches!(
+                    raster.data_type,
+                    lerc::DataType::Float | lerc::DataType::Double
+                ) {
+                    raster.mask_with_nan();
+                }
+                let big_endian = matches!(byte_order, ByteOrder::BigEndian);
+
+                Box::new(Cursor::new(raster.into_bytes(big_endian)))
+            }
+            #[cfg(feature = "webp")]
+            CompressionMethod::WebP => {
+                let samples = self.samples_per_chunk();
+                if self.bits_per_sample.iter().any(|&bits| bits != 8) || !(3..=4).contains(&samples)
+                {
+                    return Err(TiffError::UnsupportedError(
+                        TiffUnsupportedError::UnsupportedColorType(self.colortype()?),
+                    ));
+                }
+
+                let mut data = Vec::new();
+                reader.take(compressed_length).read_to_end(&mut data)?;
+                let image = webp::Decoder::new(&data).decode().ok_or_else(|| {
+                    TiffError::FormatError(TiffFormatError::Format(
+                        "WebP data of a chunk could not be decoded".to_string(),
+                    ))
+                })?;
+
+                // Encoders may drop an opaque alpha channel, or add one to an RGB image.
+                let pixels = match (image.is_alpha(), samples) {
+                    (true, 3) => image
+                        .chunks_exact(4)
+                        .flat_map(|pixel| pixel[..3].iter().copied())
+                        .collect(),
+                    (false, 4) => image
+                        .chunks_exact(3)
+                        .flat_map(|pixel| pixel.iter().copied().chain(Some(u8::MAX)))
+                        .collect(),
+                    _ => image.to_vec(),
+                };
+
+                Box::new(Cursor::new(pixels))
+            }
             CompressionMethod::ModernJPEG => {
                 if jpeg_tables.is_some() && compressed_length < 2 {
                     return Err(TiffError::FormatError(
@@ -353,37 +637,49 @@ impl Image {
                 }
 
                 let jpeg_reader = JpegReader::new(reader, compressed_length, jpeg_tables)?;
-                let mut decoder = jpeg::Decoder::new(jpeg_reader);
+                let data = self.decode_jpeg(jpeg_reader)?;
 
-                match photometric_interpretation {
-                    PhotometricInterpretation::RGB => {
-                        decoder.set_color_transform(jpeg::ColorTransform::RGB)
-                    }
-                    PhotometricInterpretation::WhiteIsZero => {
-                        decoder.set_color_transform(jpeg::ColorTransform::None)
-                    }
-                    PhotometricInterpretation::BlackIsZero => {
-                        decoder.set_color_transform(jpeg::ColorTransform::None)
-                    }
-                    PhotometricInterpretation::TransparencyMask => {
-                        decoder.set_color_transform(jpeg::ColorTransform::None)
-                    }
-                    PhotometricInterpretation::CMYK => {
-                        decoder.set_color_transform(jpeg::ColorTransform::CMYK)
-                    }
-                    PhotometricInterpretation::YCbCr => {
-                        decoder.set_color_transform(jpeg::ColorTransform::YCbCr)
-                    }
-                    photometric_interpretation => {
+                Box::new(Cursor::new(data))
+            }
+            CompressionMethod::SGILog | CompressionMethod::SGILog24 => {
+                if self.planar_config != PlanarConfiguration::Chunky {
+                    return Err(TiffUnsupportedError::UnsupportedPlanarConfig(Some(
+                        self.planar_config,
+                    ))
+                    .into());
+                }
+
+                let width = usize::try_from(self.chunk_dimensions()?.0)?;
+                let rows = usize::try_from(self.chunk_data_dimensions(chunk_index)?.1)?;
+                let data = super::logluv::decode(
+                    reader.take(compressed_length),
+                    self.photometric_interpretation,
+                    compression_method,
+                    (width, rows),
+                    byte_order,
+                )?;
+
+                Box::new(Cursor::new(data))
+            }
+            CompressionMethod::JPEG => {
+                let old_jpeg = match &self.old_jpeg {
+                    Some(Ok(old_jpeg)) => old_jpeg,
+                    Some(Err(err)) => return Err(err.clone().into()),
+                    None => {
                         return Err(TiffError::UnsupportedError(
-                            TiffUnsupportedError::UnsupportedInterpretation(
-                                photometric_interpretation,
-                            ),
-                        ));
+                            TiffUnsupportedError::UnsupportedCompressionMethod(compression_method),
+                        ))
                     }
-                }
+                };
 
-                let data = decoder.decode()?;
+                let mut data = Vec::new();
+                reader.take(compressed_length).read_to_end(&mut data)?;
+                let stream = old_jpeg.chunk_stream(
+                    &data,
+                    self.chunk_data_dimensions(chunk_index)?,
+                    self.chunk_samples(chunk_index)?,
+                )?;
+                let data = self.decode_jpeg(&stream[..])?;
 
                 Box::new(Cursor::new(data))
             }
@@ -392,7 +688,70 @@ impl Image {
                     TiffUnsupportedError::UnsupportedCompressionMethod(method),
                 ))
             }
-        })
+        };
+
+        // JPEG subsamples the chroma of YCbCr images itself, other compressions store data units.
+        match (&self.ycbcr, compression_method) {
+            (_, CompressionMethod::JPEG | CompressionMethod::ModernJPEG) => Ok(reader),
+            (Some(ycbcr), _) if ycbcr.subsampling != (1, 1) => {
+                if self.planar_config != PlanarConfiguration::Chunky {
+                    return Err(TiffUnsupportedError::UnsupportedPlanarConfig(Some(
+                        self.planar_config,
+                    ))
+                    .into());
+                } else if self.bits_per_sample.iter().any(|&bits| bits != 8) {
+                    return Err(TiffUnsupportedError::InterpretationWithBits(
+                        self.photometric_interpretation,
+                        self.bits_per_sample.clone(),
+                    )
+                    .into());
+                } else if self.predictor != Predictor::None {
+                    return Err(TiffUnsupportedError::UnsupportedPredictor(
+                        self.predictor,
+                        self.sample_format.clone(),
+                    )
+                    .into());
+                }
+
+                let width = usize::try_from(self.chunk_dimensions()?.0)?;
+                let rows = usize::try_from(self.chunk_data_dimensions(chunk_index)?.1)?;
+                let samples = ycbcr.upsample(reader, width, rows)?;
+                Ok(Box::new(Cursor::new(samples)))
+            }
+            _ => Ok(reader),
+        }
+    }
+
+    fn decode_jpeg<R: Read>(&self, reader: R) -> TiffResult<Vec<u8>> {
+        let mut decoder = jpeg::Decoder::new(reader);
+
+        match self.photometric_interpretation {
+            PhotometricInterpretation::RGB => {
+                decoder.set_color_transform(jpeg::ColorTransform::RGB)
+            }
+            PhotometricInterpretation::WhiteIsZero => {
+                decoder.set_color_transform(jpeg::ColorTransform::None)
+            }
+            PhotometricInterpretation::BlackIsZero => {
+                decoder.set_color_transform(jpeg::ColorTransform::None)
+            }
+            PhotometricInterpretation::TransparencyMask => {
+                decoder.set_color_transform(jpeg::ColorTransform::None)
+            }
+            PhotometricInterpretation::CMYK => {
+                decoder.set_color_transform(jpeg::ColorTransform::CMYK)
+            }
+            PhotometricInterpretation::YCbCr => {
+                decoder.set_color_transform(jpeg::ColorTransform::YCbCr)
+            }
+            photometric_interpretation => {
+                return Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedInterpretation(photometric_interpretation),
+                ));
+            }
+        }
+
+        Ok(decoder.decode()?)
     }
 
     pub(crate) fn chunk_file_range(&self, chunk: u32) -> TiffResult<(u64, u64)> {
@@ -429,8 +788,30 @@ impl Image {
         }
     }
 
+    /// Splits a chunk index into the plane the chunk belongs to and its index within that plane.
+    pub(crate) fn plane_chunk_index(&self, chunk_index: u32) -> TiffResult<(usize, u32)> {
+        match self.planar_config {
+            PlanarConfiguration::Chunky => Ok((0, chunk_index)),
+            PlanarConfiguration::Planar => {
+                let chunks_per_plane =
+                    u32::try_from(self.chunk_offsets.len() / usize::from(self.samples.max(1)))?;
+                if chunks_per_plane == 0 || chunk_index as usize >= self.chunk_offsets.len() {
+                    return Err(TiffError::UsageError(UsageError::InvalidChunkIndex(
+                        chunk_index,
+                    )));
+                }
+
+                Ok((
+                    (chunk_index / chunks_per_plane) as usize,
+                    chunk_index % chunks_per_plane,
+                ))
+            }
+        }
+    }
+
     pub(crate) fn chunk_data_dimensions(&self, chunk_index: u32) -> TiffResult<(u32, u32)> {
         let dims = self.chunk_dimensions()?;
+        let (_, chunk_index) = self.plane_chunk_index(chunk_index)?;
 
         match self.chunk_type {
             ChunkType::Strip => {
@@ -458,24 +839,131 @@ impl Image {
         }
     }
 
-    pub(crate) fn expand_chunk(
+    /// The number of buffer elements that hold a row of `width` pixels.
+    ///
+    /// Samples with less than 8 bits are not expanded. Such rows are packed into bytes, and each row
+    /// starts on a byte boundary, as it does in the file.
+    pub(crate) fn row_len(&self, width: usize) -> usize {
+        self.row_len_with_samples(width, self.bits_per_sample.len())
+    }
+
+    /// The number of buffer elements that hold a row of `width` pixels within a single chunk.
+    ///
+    /// This differs from `row_len` for planar images, whose chunks hold one sample per pixel.
+    pub(crate) fn chunk_row_len(&self, width: usize) -> usize {
+        self.row_len_with_samples(width, self.samples_per_chunk())
+    }
+
+    /// The number of values that make up each sample, which is two for the parts of complex
+    /// samples.
+    pub(crate) fn sample_parts(&self) -> usize {
+        match self.sample_format.first() {
+            Some(SampleFormat::ComplexInt) | Some(SampleFormat::ComplexIEEEFP) => 2,
+            _ => 1,
+        }
+    }
+
+    /// The number of samples of each pixel that are stored within a chunk.
+    pub(crate) fn samples_per_chunk(&self) -> usize {
+        match self.planar_config {
+            PlanarConfiguration::Chunky => self.bits_per_sample.len(),
+            PlanarConfiguration::Planar => 1,
+        }
+    }
+
+    /// The format of each sample of a pixel. A single `SampleFormat` value applies to all samples.
+    pub(crate) fn sample_formats(&self) -> Vec<SampleFormat> {
+        let default = self
+            .sample_format
+            .first()
+            .cloned()
+            .unwrap_or(SampleFormat::Uint);
+        (0..self.bits_per_sample.len())
+            .map(|i| self.sample_format.get(i).cloned().unwrap_or(default))
+            .collect()
+    }
+
+    /// The range of samples of each pixel that are stored within a chunk.
+    pub(crate) fn chunk_samples(&self, chunk_index: u32) -> TiffResult<Range<usize>> {
+        match self.planar_config {
+            PlanarConfiguration::Chunky => Ok(0..self.bits_per_sample.len()),
+            PlanarConfiguration::Planar => {
+                let (plane, _) = self.plane_chunk_index(chunk_index)?;
+                Ok(plane..plane + 1)
+            }
+        }
+    }
+
+    /// Decompresses the rows of a chunk without interpreting its samples.
+    ///
+    /// Each row holds the samples of the full chunk width as a stream of bits, and is padded to a
+    /// byte boundary.
+    pub(crate) fn read_chunk_bytes(
         &self,
         reader: impl Read,
+        chunk_index: u32,
+        byte_order: ByteOrder,
+        limits: &Limits,
+    ) -> TiffResult<Vec<u8>> {
+        let (_, compressed_bytes) = self.chunk_file_range(chunk_index)?;
+        if compressed_bytes > limits.intermediate_buffer_size as u64 {
+            return Err(TiffError::LimitsExceeded);
+        }
+
+        let chunk_dims = self.chunk_dimensions()?;
+        let data_dims = self.chunk_data_dimensions(chunk_index)?;
+        let pixel_bits: usize = self.bits_per_sample[self.chunk_samples(chunk_index)?]
+            .iter()
+            .map(|&bits| usize::from(bits))
+            .sum();
+        let len = (chunk_dims.0 as usize)
+            .checked_mul(pixel_bits)
+            .map(|bits| (bits + 7) / 8)
+            .and_then(|row| row.checked_mul(data_dims.1 as usize))
+            .ok_or(TiffError::LimitsExceeded)?;
+        if len > limits.decoding_buffer_size {
+            return Err(TiffError::LimitsExceeded);
+        }
+
+        let mut reader = self.create_reader(reader, chunk_index, compressed_bytes, byte_order)?;
+        let mut data = vec![0; len];
+        reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn row_len_with_samples(&self, width: usize, samples: usize) -> usize {
+        match self.bits_per_sample.iter().cloned().max() {
+            Some(bits) if bits < 8 => (width * samples * usize::from(bits) + 7) / 8,
+            _ => width * samples * self.sample_parts(),
+        }
+    }
+
+    pub(crate) fn expand_chunk<R: Read>(
+        &self,
+        reader: &mut SmartReader<R>,
         mut buffer: DecodingBuffer,
         output_width: usize,
-        byte_order: ByteOrder,
         chunk_index: u32,
         limits: &Limits,
+        conversions: Conversions,
     ) -> TiffResult<()> {
-        // Validate that the provided buffer is of the expected type.
+        // Validate that the provided buffer is of the expected type. 24-bit floats are widened to
+        // `f32` while decoding, and the parts of complex samples are stored as separate elements.
         let color_type = self.colortype()?;
+        let parts = self.sample_parts();
+        let widened = matches!(buffer, DecodingBuffer::F32(_))
+            && self.bits_per_sample.iter().all(|&bits| bits == 24);
         match (color_type, &buffer) {
             (ColorType::RGB(n), _)
             | (ColorType::RGBA(n), _)
             | (ColorType::CMYK(n), _)
             | (ColorType::YCbCr(n), _)
+            | (ColorType::Lab(n), _)
+            | (ColorType::XYZ(n), _)
             | (ColorType::Gray(n), _)
-                if usize::from(n) == buffer.byte_len() * 8 => {}
+            | (ColorType::GrayA(n), _)
+            | (ColorType::Multiband { bit_depth: n, .. }, _)
+                if usize::from(n) == buffer.byte_len() * 8 * parts || widened => {}
             (ColorType::Gray(n), DecodingBuffer::U8(_)) if n < 8 => match self.predictor {
                 Predictor::None => {}
                 Predictor::Horizontal => {
@@ -498,13 +986,15 @@ impl Image {
 
         // Validate that the predictor is supported for the sample type.
         match (self.predictor, &buffer) {
-            (Predictor::Horizontal, DecodingBuffer::F32(_))
+            (Predictor::Horizontal, DecodingBuffer::F16(_))
+            | (Predictor::Horizontal, DecodingBuffer::F32(_))
             | (Predictor::Horizontal, DecodingBuffer::F64(_)) => {
                 return Err(TiffError::UnsupportedError(
                     TiffUnsupportedError::HorizontalPredictor(color_type),
                 ));
             }
-            (Predictor::FloatingPoint, DecodingBuffer::F32(_))
+            (Predictor::FloatingPoint, DecodingBuffer::F16(_))
+            | (Predictor::FloatingPoint, DecodingBuffer::F32(_))
             | (Predictor::FloatingPoint, DecodingBuffer::F64(_)) => {}
             (Predictor::FloatingPoint, _) => {
                 return Err(TiffError::UnsupportedError(
@@ -525,76 +1015,161 @@ impl Image {
         }
 
         let byte_len = buffer.byte_len();
-        let compression_method = self.compression_method;
         let photometric_interpretation = self.photometric_interpretation;
         let predictor = self.predictor;
-        let samples = self.bits_per_sample.len();
+        let samples = self.samples_per_chunk() * parts;
+        let (plane, _) = self.plane_chunk_index(chunk_index)?;
+
+        // Only the gray channel is inverted, which is the first plane of a planar image.
+        let invert_colors =
+            photometric_interpretation == PhotometricInterpretation::WhiteIsZero && plane == 0;
+        let color_type = match self.planar_config {
+            PlanarConfiguration::Chunky => color_type,
+            PlanarConfiguration::Planar => ColorType::Gray(self.bits_per_sample[0]),
+        };
 
         let chunk_dims = self.chunk_dimensions()?;
         let data_dims = self.chunk_data_dimensions(chunk_index)?;
 
-        let padding_right = chunk_dims.0 - data_dims.0;
+        let output_row_len = self.chunk_row_len(output_width);
+        let data_row_len = self.chunk_row_len(data_dims.0 as usize);
+        let padding_right = self.chunk_row_len(chunk_dims.0 as usize) - data_row_len;
 
-        let jpeg_tables = self.jpeg_tables.clone();
-        let mut reader = Self::create_reader(
-            reader,
-            photometric_interpretation,
-            compression_method,
-            *compressed_bytes,
-            jpeg_tables,
-        )?;
+        let byte_order = reader.byte_order;
+        let mut reader = self.create_reader(reader, chunk_index, *compressed_bytes, byte_order)?;
+
+        if widened {
+            let mut encoded = vec![0u8; (data_row_len + padding_right) * 3];
+
+            for row in 0..data_dims.1 as usize {
+                let row_start = row * output_row_len;
+                let row_end = row_start + data_row_len;
 
-        if output_width == data_dims.0 as usize && padding_right == 0 {
-            let total_samples = data_dims.0 as usize * data_dims.1 as usize * samples;
+                reader.read_exact(&mut encoded)?;
+                if let DecodingBuffer::F32(buf) = buffer.subrange(row_start..row_end) {
+                    super::expand_f24(&mut encoded, buf, samples, byte_order, predictor);
+                }
+                if invert_colors {
+                    super::invert_colors(
+                        &mut buffer.subrange(row_start..row_end),
+                        color_type,
+                        samples,
+                    );
+                }
+            }
+        } else if output_width == data_dims.0 as usize && padding_right == 0 {
+            let total_samples = data_row_len * data_dims.1 as usize;
             let tile = &mut buffer.as_bytes_mut()[..total_samples * byte_len];
             reader.read_exact(tile)?;
 
             for row in 0..data_dims.1 as usize {
-                let row_start = row as usize * output_width as usize * samples;
-                let row_end = (row + 1) * output_width as usize * samples;
+                let row_start = row * output_row_len;
+                let row_end = (row + 1) * output_row_len;
                 let row = buffer.subrange(row_start..row_end);
                 super::fix_endianness_and_predict(row, samples, byte_order, predictor);
             }
-            if photometric_interpretation == PhotometricInterpretation::WhiteIsZero {
-                super::invert_colors(&mut buffer.subrange(0..total_samples), color_type);
+            if invert_colors {
+                super::invert_colors(&mut buffer.subrange(0..total_samples), color_type, samples);
             }
         } else if padding_right > 0 && self.predictor == Predictor::FloatingPoint {
             // The floating point predictor shuffles the padding bytes into the encoded output, so
             // this case is handled specially when needed.
-            let mut encoded = vec![0u8; chunk_dims.0 as usize * samples * byte_len];
+            let mut encoded = vec![0u8; (data_row_len + padding_right) * byte_len];
 
             for row in 0..data_dims.1 as usize {
-                let row_start = row * output_width as usize * samples;
-                let row_end = row_start + data_dims.0 as usize * samples;
+                let row_start = row * output_row_len;
+                let row_end = row_start + data_row_len;
 
                 reader.read_exact(&mut encoded)?;
                 match buffer.subrange(row_start..row_end) {
+                    DecodingBuffer::F16(buf) => fp_predict_f16(&mut encoded, buf, samples),
                     DecodingBuffer::F32(buf) => fp_predict_f32(&mut encoded, buf, samples),
                     DecodingBuffer::F64(buf) => fp_predict_f64(&mut encoded, buf, samples),
                     _ => unreachable!(),
                 }
-                if photometric_interpretation == PhotometricInterpretation::WhiteIsZero {
-                    super::invert_colors(&mut buffer.subrange(row_start..row_end), color_type);
+                if invert_colors {
+                    super::invert_colors(
+                        &mut buffer.subrange(row_start..row_end),
+                        color_type,
+                        samples,
+                    );
                 }
             }
         } else {
             for row in 0..data_dims.1 as usize {
-                let row_start = row * output_width as usize * samples;
-                let row_end = row_start + data_dims.0 as usize * samples;
+                let row_start = row * output_row_len;
+                let row_end = row_start + data_row_len;
 
                 let row = &mut buffer.as_bytes_mut()[(row_start * byte_len)..(row_end * byte_len)];
                 reader.read_exact(row)?;
 
                 // Skip horizontal padding
                 if padding_right > 0 {
-                    let len = u64::try_from(padding_right as usize * samples * byte_len)?;
+                    let len = u64::try_from(padding_right * byte_len)?;
                     io::copy(&mut reader.by_ref().take(len), &mut io::sink())?;
                 }
 
                 let mut row = buffer.subrange(row_start..row_end);
                 super::fix_endianness_and_predict(row.copy(), samples, byte_order, predictor);
-                if photometric_interpretation == PhotometricInterpretation::WhiteIsZero {
-                    super::invert_colors(&mut row, color_type);
+                if invert_colors {
+                    super::invert_colors(&mut row, color_type, samples);
+                }
+            }
+        }
+
+        // The alpha of a planar image is in another chunk, the caller has to unassociate it.
+        let unassociate_alpha =
+            conversions.unassociate_alpha && self.planar_config == PlanarConfiguration::Chunky;
+        if let Some((colors, alpha)) = self.associated_alpha().filter(|_| unassociate_alpha) {
+            for row in 0..data_dims.1 as usize {
+                let row_start = row * output_row_len;
+                let mut row = buffer.subrange(row_start..row_start + data_row_len);
+                super::unassociate_alpha(&mut row, samples, colors, alpha);
+            }
+        }
+
+        if let Some(lab) = &self.lab {
+            // Each plane of a planar image holds a single component.
+            let (samples, channel) = match self.planar_config {
+                PlanarConfiguration::Chunky => (samples, 0),
+                PlanarConfiguration::Planar => (1, plane),
+            };
+            for row in 0..data_dims.1 as usize {
+                let row_start = row * output_row_len;
+                let mut row = buffer.subrange(row_start..row_start + data_row_len);
+                lab.normalize(&mut row, samples, channel);
+                if conversions.lab_to_srgb && self.srgb_conversion().is_some() {
+                    Lab::to_srgb(&mut row, samples);
+                }
+            }
+        }
+
+        if conversions.xyz_to_rgb && color_type == ColorType::XYZ(32) {
+            for row in 0..data_dims.1 as usize {
+                let row_start = row * output_row_len;
+                if let DecodingBuffer::F32(row) =
+                    buffer.subrange(row_start..row_start + data_row_len)
+                {
+                    super::logluv::xyz_to_rgb(row);
+                }
+            }
+        }
+
+        // JPEG already converts YCbCr to RGB while decoding.
+        let ycbcr = self.rgb_conversion().filter(|_| {
+            conversions.ycbcr_to_rgb
+                && !matches!(
+                    self.compression_method,
+                    CompressionMethod::JPEG | CompressionMethod::ModernJPEG
+                )
+        });
+        if let Some(ycbcr) = ycbcr {
+            for row in 0..data_dims.1 as usize {
+                let row_start = row * output_row_len;
+                if let DecodingBuffer::U8(row) =
+                    buffer.subrange(row_start..row_start + data_row_len)
+                {
+                    ycbcr.to_rgb(row);
                 }
             }
         }
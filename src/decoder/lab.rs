@@ -0,0 +1,152 @@
+//! Support for CIE L*a*b* images and their conversion to sRGB.
+//!
+//! TIFF knows three encodings of L*a*b* samples, which are all decoded to the one of ICC
+//! profiles: L* spans the range of the samples for 0 to 100, while a* and b* are offset by half
+//! the range, such that 128 in 8-bit samples and 32768 in 16-bit samples is neutral.
+use std::convert::TryFrom;
+use std::io::{Read, Seek};
+
+use super::tag_reader::TagReader;
+use super::DecodingBuffer;
+use crate::tags::{PhotometricInterpretation, Tag};
+use crate::{TiffFormatError, TiffResult};
+
+/// The reference white of ICC profiles, D50, in XYZ.
+const WHITE: [f32; 3] = [0.9642, 1.0, 0.8249];
+
+/// The conversion from XYZ relative to D50 to linear sRGB, with Bradford chromatic adaptation.
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.133_856, -1.616_867, -0.490_615],
+    [-0.978_768, 1.916_142, 0.033_454],
+    [0.071_945, -0.228_991, 1.405_243],
+];
+
+#[derive(Debug)]
+enum Encoding {
+    /// a* and b* are signed integers, as in `CIELab`.
+    Signed,
+    /// a* and b* are offset by half the range, as in `ICCLab`.
+    Unsigned,
+    /// The samples span the given minimum and maximum of each component, as in `ITULab`.
+    Ranges([f32; 6]),
+}
+
+/// The encoding of the samples of an L*a*b* image.
+#[derive(Debug)]
+pub(crate) struct Lab {
+    encoding: Encoding,
+}
+
+impl Lab {
+    pub(crate) fn from_tags<R: Read + Seek>(
+        tag_reader: &mut TagReader<R>,
+        photometric_interpretation: PhotometricInterpretation,
+    ) -> TiffResult<Option<Self>> {
+        let encoding = match photometric_interpretation {
+            PhotometricInterpretation::CIELab => Encoding::Signed,
+            PhotometricInterpretation::ICCLab => Encoding::Unsigned,
+            PhotometricInterpretation::ITULab => match tag_reader.find_tag(Tag::Decode)? {
+                Some(value) => match value.into_f32_vec()?[..] {
+                    [l_min, l_max, a_min, a_max, b_min, b_max] => {
+                        Encoding::Ranges([l_min, l_max, a_min, a_max, b_min, b_max])
+                    }
+                    _ => return Err(TiffFormatError::InvalidTagValueType(Tag::Decode).into()),
+                },
+                // The default ranges of ITU-T T.42.
+                None => Encoding::Ranges([0.0, 100.0, -85.0, 85.0, -75.0, 125.0]),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(Lab { encoding }))
+    }
+
+    /// Converts the samples in `buffer` to the ICC encoding, where pixels have `samples` samples
+    /// and the first of them is the component `channel`.
+    pub(crate) fn normalize(&self, buffer: &mut DecodingBuffer, samples: usize, channel: usize) {
+        match buffer {
+            DecodingBuffer::U8(buffer) => self.normalize_samples(buffer, samples, channel),
+            DecodingBuffer::U16(buffer) => self.normalize_samples(buffer, samples, channel),
+            _ => {}
+        }
+    }
+
+    fn normalize_samples<T>(&self, buffer: &mut [T], samples: usize, channel: usize)
+    where
+        T: Copy + Into<u32> + TryFrom<u32>,
+    {
+        let bits = 8 * std::mem::size_of::<T>() as u32;
+        let max = ((1u64 << bits) - 1) as f32;
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            let component = channel + i % samples;
+            let value: u32 = (*sample).into();
+            let value = match self.encoding {
+                Encoding::Unsigned => continue,
+                Encoding::Signed if component == 0 => continue,
+                Encoding::Signed if component < 3 => value ^ 1 << (bits - 1),
+                Encoding::Ranges(ranges) if component < 3 => {
+                    let (min, range_max) = (ranges[2 * component], ranges[2 * component + 1]);
+                    let decoded = min + value as f32 * (range_max - min) / max;
+                    let encoded = match component {
+                        0 => decoded / 100.0 * max,
+                        _ => (decoded + 128.0) / 256.0 * (max + 1.0),
+                    };
+                    encoded.round().clamp(0.0, max) as u32
+                }
+                _ => continue,
+            };
+            if let Ok(value) = T::try_from(value) {
+                *sample = value;
+            }
+        }
+    }
+
+    /// Converts pixels of L*a*b* in the ICC encoding to sRGB in place.
+    pub(crate) fn to_srgb(buffer: &mut DecodingBuffer, samples: usize) {
+        match buffer {
+            DecodingBuffer::U8(buffer) => Self::samples_to_srgb(buffer, samples),
+            DecodingBuffer::U16(buffer) => Self::samples_to_srgb(buffer, samples),
+            _ => {}
+        }
+    }
+
+    fn samples_to_srgb<T>(buffer: &mut [T], samples: usize)
+    where
+        T: Copy + Into<u32> + TryFrom<u32>,
+    {
+        let bits = 8 * std::mem::size_of::<T>() as u32;
+        let max = ((1u64 << bits) - 1) as f32;
+        let scale = (max + 1.0) / 256.0;
+
+        let inverse = |t: f32| {
+            if t > 6.0 / 29.0 {
+                t * t * t
+            } else {
+                3.0 * (6.0 / 29.0) * (6.0 / 29.0) * (t - 4.0 / 29.0)
+            }
+        };
+        let gamma = |c: f32| {
+            if c <= 0.003_130_8 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        };
+
+        for pixel in buffer.chunks_exact_mut(samples) {
+            let [l, a, b] = [0, 1, 2].map(|i| pixel[i].into() as f32);
+            let l = l / max * 100.0;
+            let (a, b) = (a / scale - 128.0, b / scale - 128.0);
+
+            let fy = (l + 16.0) / 116.0;
+            let xyz = [fy + a / 500.0, fy, fy - b / 200.0];
+            let xyz = [0, 1, 2].map(|i| WHITE[i] * inverse(xyz[i]));
+            for (sample, row) in pixel.iter_mut().zip(&XYZ_TO_SRGB) {
+                let linear = row[0] * xyz[0] + row[1] * xyz[1] + row[2] * xyz[2];
+                let encoded = (gamma(linear.clamp(0.0, 1.0)) * max).round() as u32;
+                if let Ok(value) = T::try_from(encoded) {
+                    *sample = value;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Support for the SGI LogL and LogLuv encodings of high dynamic range images.
+//!
+//! Both are only defined together with the SGI log compressions. Luminance is stored
+//! logarithmically and chroma as a CIE (u', v') coordinate, and pixels are decoded to 32-bit
+//! floats of luminance Y, or CIE XYZ respectively.
+use std::f64::consts::LN_2;
+use std::io::{self, Read};
+
+use super::stream::ByteOrder;
+use crate::tags::{CompressionMethod, PhotometricInterpretation};
+use crate::{TiffError, TiffFormatError, TiffResult, TiffUnsupportedError};
+
+/// The size of the (u', v') cells of 24-bit LogLuv.
+const UV_SQSIZ: f64 = 0.0035;
+/// The v' of the first row of cells.
+const UV_VSTART: f64 = 0.016940;
+/// The number of cells, larger codes are invalid.
+const UV_CODES: u16 = 16289;
+/// The neutral chroma, which replaces invalid codes.
+const UV_NEUTRAL: (f64, f64) = (4.0 / 19.0, 9.0 / 19.0);
+
+/// The u' of the first cell and the code of the first cell in each row of cells of 24-bit
+/// LogLuv, which cover the gamut of visible colors. These are the same as the ones of libtiff.
+#[rustfmt::skip]
+const UV_ROWS: [(f32, u16); 163] = [
+    (0.247663, 0), (0.243779, 4), (0.241684, 10), (0.237874, 17), (0.235906, 26), (0.232153, 36),
+    (0.228352, 48), (0.226259, 62), (0.222371, 77), (0.220410, 94), (0.214710, 112),
+    (0.212714, 133), (0.210721, 155), (0.204976, 178), (0.202986, 204), (0.199245, 231),
+    (0.195525, 260), (0.193560, 291), (0.189878, 323), (0.186216, 357), (0.186216, 393),
+    (0.182592, 429), (0.179003, 467), (0.175466, 507), (0.172001, 549), (0.172001, 593),
+    (0.168612, 637), (0.168612, 683), (0.163575, 729), (0.158642, 778), (0.158642, 830),
+    (0.158642, 882), (0.153815, 934), (0.153815, 989), (0.149097, 1044), (0.149097, 1102),
+    (0.142746, 1160), (0.142746, 1222), (0.142746, 1284), (0.138270, 1346), (0.138270, 1411),
+    (0.138270, 1476), (0.132166, 1541), (0.132166, 1610), (0.126204, 1679), (0.126204, 1752),
+    (0.126204, 1825), (0.120381, 1898), (0.120381, 1975), (0.120381, 2052), (0.120381, 2129),
+    (0.112962, 2206), (0.112962, 2288), (0.112962, 2370), (0.107450, 2452), (0.107450, 2538),
+    (0.107450, 2624), (0.107450, 2710), (0.100343, 2796), (0.100343, 2887), (0.100343, 2978),
+    (0.095126, 3069), (0.095126, 3164), (0.095126, 3259), (0.095126, 3354), (0.088276, 3449),
+    (0.088276, 3549), (0.088276, 3649), (0.088276, 3749), (0.081523, 3849), (0.081523, 3954),
+    (0.081523, 4059), (0.081523, 4164), (0.074861, 4269), (0.074861, 4379), (0.074861, 4489),
+    (0.074861, 4599), (0.068290, 4709), (0.068290, 4824), (0.068290, 4939), (0.068290, 5054),
+    (0.063573, 5169), (0.063573, 5288), (0.063573, 5407), (0.063573, 5526), (0.057219, 5645),
+    (0.057219, 5769), (0.057219, 5893), (0.057219, 6017), (0.050985, 6141), (0.050985, 6270),
+    (0.050985, 6399), (0.050985, 6528), (0.050985, 6657), (0.044859, 6786), (0.044859, 6920),
+    (0.044859, 7054), (0.044859, 7188), (0.040571, 7322), (0.040571, 7460), (0.040571, 7598),
+    (0.040571, 7736), (0.036339, 7874), (0.036339, 8016), (0.036339, 8158), (0.036339, 8300),
+    (0.032139, 8442), (0.032139, 8588), (0.032139, 8734), (0.032139, 8880), (0.027947, 9026),
+    (0.027947, 9176), (0.027947, 9326), (0.023739, 9476), (0.023739, 9630), (0.023739, 9784),
+    (0.023739, 9938), (0.019504, 10092), (0.019504, 10250), (0.019504, 10408), (0.016976, 10566),
+    (0.016976, 10727), (0.016976, 10888), (0.016976, 11049), (0.012639, 11210), (0.012639, 11375),
+    (0.012639, 11540), (0.009991, 11705), (0.009991, 11873), (0.009991, 12041), (0.009016, 12209),
+    (0.009016, 12379), (0.009016, 12549), (0.006217, 12719), (0.006217, 12892), (0.005097, 13065),
+    (0.005097, 13240), (0.005097, 13415), (0.003909, 13590), (0.003909, 13767), (0.002340, 13944),
+    (0.002389, 14121), (0.001068, 14291), (0.001653, 14455), (0.000717, 14612), (0.001614, 14762),
+    (0.000270, 14905), (0.000484, 15041), (0.001103, 15170), (0.001242, 15293), (0.001188, 15408),
+    (0.001011, 15517), (0.000709, 15620), (0.000301, 15717), (0.002416, 15806), (0.003251, 15888),
+    (0.003246, 15964), (0.004141, 16033), (0.005963, 16095), (0.008839, 16150), (0.010490, 16197),
+    (0.016994, 16237), (0.023659, 16268),
+];
+
+/// The conversion from XYZ to linear RGB with ITU-R BT.709 primaries and an equal energy white
+/// point, as used by libtiff.
+const XYZ_TO_RGB: [[f32; 3]; 3] = [
+    [2.690, -1.276, -0.414],
+    [-1.022, 1.978, 0.044],
+    [0.061, -0.224, 1.163],
+];
+
+/// Decodes `rows` rows of `width` pixels of a chunk with SGI log compression to floats in the
+/// given byte order.
+pub(crate) fn decode(
+    mut reader: impl Read,
+    photometric_interpretation: PhotometricInterpretation,
+    compression_method: CompressionMethod,
+    (width, rows): (usize, usize),
+    byte_order: ByteOrder,
+) -> TiffResult<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut data = &data[..];
+
+    let mut samples = Vec::with_capacity(width * rows * 3);
+    let mut pixels = vec![0u32; width];
+    for _ in 0..rows {
+        match (photometric_interpretation, compression_method) {
+            (PhotometricInterpretation::LogL, CompressionMethod::SGILog) => {
+                decode_runs(&mut data, &mut pixels, &[8, 0])?;
+                samples.extend(pixels.iter().map(|&p| log_l16_to_y(p as u16) as f32));
+            }
+            (PhotometricInterpretation::LogLuv, CompressionMethod::SGILog) => {
+                decode_runs(&mut data, &mut pixels, &[24, 16, 8, 0])?;
+                samples.extend(pixels.iter().flat_map(|&p| log_luv32_to_xyz(p)));
+            }
+            (PhotometricInterpretation::LogLuv, CompressionMethod::SGILog24) => {
+                if data.len() < width * 3 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                let (row, rest) = data.split_at(width * 3);
+                data = rest;
+                samples.extend(row.chunks_exact(3).flat_map(|p| {
+                    log_luv24_to_xyz(u32::from(p[0]) << 16 | u32::from(p[1]) << 8 | u32::from(p[2]))
+                }));
+            }
+            (PhotometricInterpretation::LogL | PhotometricInterpretation::LogLuv, method) => {
+                return Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedCompressionMethod(method),
+                ))
+            }
+            (photometric_interpretation, _) => {
+                return Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedInterpretation(photometric_interpretation),
+                ))
+            }
+        }
+    }
+
+    Ok(samples
+        .into_iter()
+        .flat_map(|sample| match byte_order {
+            ByteOrder::LittleEndian => sample.to_le_bytes(),
+            ByteOrder::BigEndian => sample.to_be_bytes(),
+        })
+        .collect())
+}
+
+/// Converts linear XYZ samples to RGB in place.
+pub(crate) fn xyz_to_rgb(samples: &mut [f32]) {
+    for pixel in samples.chunks_exact_mut(3) {
+        let xyz = [pixel[0], pixel[1], pixel[2]];
+        for (sample, row) in pixel.iter_mut().zip(&XYZ_TO_RGB) {
+            *sample = row[0] * xyz[0] + row[1] * xyz[1] + row[2] * xyz[2];
+        }
+    }
+}
+
+/// Decodes a row that is run length encoded separately for each byte of the pixels, from the
+/// most significant one at the first shift to the least significant one.
+fn decode_runs(data: &mut &[u8], pixels: &mut [u32], shifts: &[u32]) -> TiffResult<()> {
+    let mut next = || -> TiffResult<u32> {
+        let (&byte, rest) = data
+            .split_first()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        *data = rest;
+        Ok(u32::from(byte))
+    };
+
+    pixels.iter_mut().for_each(|pixel| *pixel = 0);
+    for &shift in shifts {
+        let mut i = 0;
+        while i < pixels.len() {
+            let code = next()?;
+            // A code of 128 and above repeats the next byte, while others precede literal bytes.
+            let (count, run) = match code {
+                128..=255 => (code as usize - 126, Some(next()?)),
+                _ => (code as usize, None),
+            };
+            let run_pixels = pixels.get_mut(i..i + count).ok_or_else(|| {
+                TiffError::FormatError(TiffFormatError::Format(
+                    "SGI log run exceeds the row".to_string(),
+                ))
+            })?;
+            for pixel in run_pixels {
+                let byte = match run {
+                    Some(byte) => byte,
+                    None => next()?,
+                };
+                *pixel |= byte << shift;
+            }
+            i += count;
+        }
+    }
+    Ok(())
+}
+
+fn log_l16_to_y(p16: u16) -> f64 {
+    let le = p16 & 0x7fff;
+    if le == 0 {
+        return 0.0;
+    }
+    let y = (LN_2 / 256.0 * (f64::from(le) + 0.5) - LN_2 * 64.0).exp();
+    if p16 & 0x8000 != 0 {
+        -y
+    } else {
+        y
+    }
+}
+
+fn log_l10_to_y(p10: u32) -> f64 {
+    if p10 == 0 {
+        return 0.0;
+    }
+    (LN_2 / 64.0 * (f64::from(p10) + 0.5) - LN_2 * 12.0).exp()
+}
+
+fn uv_to_xyz(luminance: f64, (u, v): (f64, f64)) -> [f32; 3] {
+    if luminance <= 0.0 {
+        return [0.0; 3];
+    }
+    let s = 1.0 / (6.0 * u - 16.0 * v + 12.0);
+    let (x, y) = (9.0 * u * s, 4.0 * v * s);
+    [
+        (x / y * luminance) as f32,
+        luminance as f32,
+        ((1.0 - x - y) / y * luminance) as f32,
+    ]
+}
+
+fn log_luv32_to_xyz(p: u32) -> [f32; 3] {
+    let luminance = log_l16_to_y((p >> 16) as u16);
+    let u = 1.0 / 410.0 * (f64::from((p >> 8) & 0xff) + 0.5);
+    let v = 1.0 / 410.0 * (f64::from(p & 0xff) + 0.5);
+    uv_to_xyz(luminance, (u, v))
+}
+
+fn log_luv24_to_xyz(p: u32) -> [f32; 3] {
+    let luminance = log_l10_to_y((p >> 14) & 0x3ff);
+    let code = (p & 0x3fff) as u16;
+    let uv = if code < UV_CODES {
+        // The row of cells is the last one that starts at or before the code.
+        let row = UV_ROWS.partition_point(|&(_, first)| first <= code) - 1;
+        let (u_start, first) = UV_ROWS[row];
+        let u = f64::from(u_start) + (f64::from(code - first) + 0.5) * UV_SQSIZ;
+        (u, UV_VSTART + (row as f64 + 0.5) * UV_SQSIZ)
+    } else {
+        UV_NEUTRAL
+    };
+    uv_to_xyz(luminance, uv)
+}
@@ -3,22 +3,31 @@ use std::convert::TryFrom;
 use std::io::{self, Read, Seek};
 use std::ops::Range;
 
+use half::f16;
+
+use crate::progress::{Progress, ProgressCallback};
 use crate::{
     bytecast, ColorType, TiffError, TiffFormatError, TiffResult, TiffUnsupportedError, UsageError,
 };
 
-use self::ifd::Directory;
-use self::image::Image;
+use self::ifd::{Directory, NativeTag, RawEntry};
+use self::image::{Conversions, Image};
 use crate::tags::{
-    CompressionMethod, PhotometricInterpretation, Predictor, SampleFormat, Tag, Type,
+    CompressionMethod, ExtraSample, FillOrder, LercCompression, PhotometricInterpretation,
+    PlanarConfiguration, Predictor, SampleFormat, Tag, Type,
 };
 
-use self::stream::{ByteOrder, EndianReader, SmartReader};
+pub use self::stream::ByteOrder;
+use self::stream::{EndianReader, SmartReader};
 
 pub mod ifd;
 mod image;
+mod lab;
+mod logluv;
+mod old_jpeg;
 mod stream;
 mod tag_reader;
+mod ycbcr;
 
 /// Result of a decoding process
 #[derive(Debug)]
@@ -31,6 +40,8 @@ pub enum DecodingResult {
     U32(Vec<u32>),
     /// A vector of 64 bit unsigned ints
     U64(Vec<u64>),
+    /// A vector of 16 bit IEEE floats
+    F16(Vec<f16>),
     /// A vector of 32 bit IEEE floats
     F32(Vec<f32>),
     /// A vector of 64 bit IEEE floats
@@ -43,9 +54,70 @@ pub enum DecodingResult {
     I32(Vec<i32>),
     /// A vector of 64 bit signed ints
     I64(Vec<i64>),
+    /// A vector of complex 16 bit signed ints, each a pair of its real and imaginary part
+    ComplexI16(Vec<[i16; 2]>),
+    /// A vector of complex 32 bit signed ints, each a pair of its real and imaginary part
+    ComplexI32(Vec<[i32; 2]>),
+    /// A vector of complex 32 bit IEEE floats, each a pair of its real and imaginary part
+    ComplexF32(Vec<[f32; 2]>),
+    /// A vector of complex 64 bit IEEE floats, each a pair of its real and imaginary part
+    ComplexF64(Vec<[f64; 2]>),
 }
 
 impl DecodingResult {
+    /// Allocates a buffer of `size` elements of the smallest type that holds the `sample`.
+    fn new_for_sample(
+        sample: SampleDescriptor,
+        size: usize,
+        limits: &Limits,
+    ) -> TiffResult<DecodingResult> {
+        match sample.format {
+            SampleFormat::Uint => match sample.bits {
+                n if n <= 8 => DecodingResult::new_u8(size, limits),
+                n if n <= 16 => DecodingResult::new_u16(size, limits),
+                n if n <= 32 => DecodingResult::new_u32(size, limits),
+                n if n <= 64 => DecodingResult::new_u64(size, limits),
+                n => Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
+                )),
+            },
+            // There is no native type for 24-bit floats, they are widened to `f32`.
+            SampleFormat::IEEEFP => match sample.bits {
+                16 => DecodingResult::new_f16(size, limits),
+                24 | 32 => DecodingResult::new_f32(size, limits),
+                64 => DecodingResult::new_f64(size, limits),
+                n => Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
+                )),
+            },
+            SampleFormat::Int => match sample.bits {
+                n if n <= 8 => DecodingResult::new_i8(size, limits),
+                n if n <= 16 => DecodingResult::new_i16(size, limits),
+                n if n <= 32 => DecodingResult::new_i32(size, limits),
+                n if n <= 64 => DecodingResult::new_i64(size, limits),
+                n => Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
+                )),
+            },
+            // The bits of a complex sample cover both of its parts.
+            SampleFormat::ComplexInt => match sample.bits {
+                32 => DecodingResult::new_complex_i16(size, limits),
+                64 => DecodingResult::new_complex_i32(size, limits),
+                n => Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
+                )),
+            },
+            SampleFormat::ComplexIEEEFP => match sample.bits {
+                64 => DecodingResult::new_complex_f32(size, limits),
+                128 => DecodingResult::new_complex_f64(size, limits),
+                n => Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
+                )),
+            },
+            format => Err(TiffUnsupportedError::UnsupportedSampleFormat(vec![format]).into()),
+        }
+    }
+
     fn new_u8(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
         if size > limits.decoding_buffer_size {
             Err(TiffError::LimitsExceeded)
@@ -86,6 +158,14 @@ impl DecodingResult {
         }
     }
 
+    fn new_f16(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
+        if size > limits.decoding_buffer_size / std::mem::size_of::<f16>() {
+            Err(TiffError::LimitsExceeded)
+        } else {
+            Ok(DecodingResult::F16(vec![f16::ZERO; size]))
+        }
+    }
+
     fn new_f64(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
         if size > limits.decoding_buffer_size / std::mem::size_of::<f64>() {
             Err(TiffError::LimitsExceeded)
@@ -126,18 +206,65 @@ impl DecodingResult {
         }
     }
 
+    fn new_complex_i16(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
+        if size > limits.decoding_buffer_size / std::mem::size_of::<[i16; 2]>() {
+            Err(TiffError::LimitsExceeded)
+        } else {
+            Ok(DecodingResult::ComplexI16(vec![[0; 2]; size]))
+        }
+    }
+
+    fn new_complex_i32(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
+        if size > limits.decoding_buffer_size / std::mem::size_of::<[i32; 2]>() {
+            Err(TiffError::LimitsExceeded)
+        } else {
+            Ok(DecodingResult::ComplexI32(vec![[0; 2]; size]))
+        }
+    }
+
+    fn new_complex_f32(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
+        if size > limits.decoding_buffer_size / std::mem::size_of::<[f32; 2]>() {
+            Err(TiffError::LimitsExceeded)
+        } else {
+            Ok(DecodingResult::ComplexF32(vec![[0.0; 2]; size]))
+        }
+    }
+
+    fn new_complex_f64(size: usize, limits: &Limits) -> TiffResult<DecodingResult> {
+        if size > limits.decoding_buffer_size / std::mem::size_of::<[f64; 2]>() {
+            Err(TiffError::LimitsExceeded)
+        } else {
+            Ok(DecodingResult::ComplexF64(vec![[0.0; 2]; size]))
+        }
+    }
+
+    /// Returns the buffer starting at element `start`. The parts of complex values are separate
+    /// elements of the buffer, so `start` counts two for each of them.
     pub fn as_buffer(&mut self, start: usize) -> DecodingBuffer {
         match *self {
             DecodingResult::U8(ref mut buf) => DecodingBuffer::U8(&mut buf[start..]),
             DecodingResult::U16(ref mut buf) => DecodingBuffer::U16(&mut buf[start..]),
             DecodingResult::U32(ref mut buf) => DecodingBuffer::U32(&mut buf[start..]),
             DecodingResult::U64(ref mut buf) => DecodingBuffer::U64(&mut buf[start..]),
+            DecodingResult::F16(ref mut buf) => DecodingBuffer::F16(&mut buf[start..]),
             DecodingResult::F32(ref mut buf) => DecodingBuffer::F32(&mut buf[start..]),
             DecodingResult::F64(ref mut buf) => DecodingBuffer::F64(&mut buf[start..]),
             DecodingResult::I8(ref mut buf) => DecodingBuffer::I8(&mut buf[start..]),
             DecodingResult::I16(ref mut buf) => DecodingBuffer::I16(&mut buf[start..]),
             DecodingResult::I32(ref mut buf) => DecodingBuffer::I32(&mut buf[start..]),
             DecodingResult::I64(ref mut buf) => DecodingBuffer::I64(&mut buf[start..]),
+            DecodingResult::ComplexI16(ref mut buf) => {
+                DecodingBuffer::I16(&mut bytecast::complex_i16_as_mut_parts(buf)[start..])
+            }
+            DecodingResult::ComplexI32(ref mut buf) => {
+                DecodingBuffer::I32(&mut bytecast::complex_i32_as_mut_parts(buf)[start..])
+            }
+            DecodingResult::ComplexF32(ref mut buf) => {
+                DecodingBuffer::F32(&mut bytecast::complex_f32_as_mut_parts(buf)[start..])
+            }
+            DecodingResult::ComplexF64(ref mut buf) => {
+                DecodingBuffer::F64(&mut bytecast::complex_f64_as_mut_parts(buf)[start..])
+            }
         }
     }
 }
@@ -152,6 +279,8 @@ pub enum DecodingBuffer<'a> {
     U32(&'a mut [u32]),
     /// A slice of 64 bit unsigned ints
     U64(&'a mut [u64]),
+    /// A slice of 16 bit IEEE floats
+    F16(&'a mut [f16]),
     /// A slice of 32 bit IEEE floats
     F32(&'a mut [f32]),
     /// A slice of 64 bit IEEE floats
@@ -173,6 +302,7 @@ impl<'a> DecodingBuffer<'a> {
             DecodingBuffer::U16(_) => 2,
             DecodingBuffer::U32(_) => 4,
             DecodingBuffer::U64(_) => 8,
+            DecodingBuffer::F16(_) => 2,
             DecodingBuffer::F32(_) => 4,
             DecodingBuffer::F64(_) => 8,
             DecodingBuffer::I8(_) => 1,
@@ -191,6 +321,7 @@ impl<'a> DecodingBuffer<'a> {
             DecodingBuffer::U16(ref mut buf) => DecodingBuffer::U16(buf),
             DecodingBuffer::U32(ref mut buf) => DecodingBuffer::U32(buf),
             DecodingBuffer::U64(ref mut buf) => DecodingBuffer::U64(buf),
+            DecodingBuffer::F16(ref mut buf) => DecodingBuffer::F16(buf),
             DecodingBuffer::F32(ref mut buf) => DecodingBuffer::F32(buf),
             DecodingBuffer::F64(ref mut buf) => DecodingBuffer::F64(buf),
             DecodingBuffer::I8(ref mut buf) => DecodingBuffer::I8(buf),
@@ -209,6 +340,7 @@ impl<'a> DecodingBuffer<'a> {
             DecodingBuffer::U16(ref mut buf) => DecodingBuffer::U16(&mut buf[range]),
             DecodingBuffer::U32(ref mut buf) => DecodingBuffer::U32(&mut buf[range]),
             DecodingBuffer::U64(ref mut buf) => DecodingBuffer::U64(&mut buf[range]),
+            DecodingBuffer::F16(ref mut buf) => DecodingBuffer::F16(&mut buf[range]),
             DecodingBuffer::F32(ref mut buf) => DecodingBuffer::F32(&mut buf[range]),
             DecodingBuffer::F64(ref mut buf) => DecodingBuffer::F64(&mut buf[range]),
             DecodingBuffer::I8(ref mut buf) => DecodingBuffer::I8(&mut buf[range]),
@@ -228,6 +360,7 @@ impl<'a> DecodingBuffer<'a> {
             DecodingBuffer::I32(buf) => bytecast::i32_as_ne_mut_bytes(buf),
             DecodingBuffer::U64(buf) => bytecast::u64_as_ne_mut_bytes(buf),
             DecodingBuffer::I64(buf) => bytecast::i64_as_ne_mut_bytes(buf),
+            DecodingBuffer::F16(buf) => bytecast::f16_as_ne_mut_bytes(buf),
             DecodingBuffer::F32(buf) => bytecast::f32_as_ne_mut_bytes(buf),
             DecodingBuffer::F64(buf) => bytecast::f64_as_ne_mut_bytes(buf),
         }
@@ -241,6 +374,58 @@ pub enum ChunkType {
     Tile,
 }
 
+/// Reads the sample of `bits` bits that starts at bit `start` of a row.
+///
+/// Samples that fill whole bytes are stored in the byte order of the file, all others are packed
+/// with the most significant bit first.
+fn read_sample_bits(row: &[u8], start: usize, bits: u8, byte_order: ByteOrder) -> u64 {
+    if start % 8 == 0 && bits % 8 == 0 {
+        let bytes = &row[start / 8..][..usize::from(bits / 8)];
+        match byte_order {
+            ByteOrder::LittleEndian => bytes.iter().rev().fold(0, |v, &b| v << 8 | u64::from(b)),
+            ByteOrder::BigEndian => bytes.iter().fold(0, |v, &b| v << 8 | u64::from(b)),
+        }
+    } else {
+        (start..start + usize::from(bits)).fold(0, |v, bit| {
+            v << 1 | u64::from(row[bit / 8] >> (7 - bit % 8) & 1)
+        })
+    }
+}
+
+/// Stores the raw `bits` of a sample at `index`, sign extending them for signed buffers. The parts
+/// of complex values are stored separately, with two indices for each value.
+fn set_sample(channel: &mut DecodingResult, index: usize, bits: u8, value: u64) {
+    let shift = 64 - u32::from(bits);
+    let signed = ((value << shift) as i64) >> shift;
+    match channel {
+        DecodingResult::U8(buf) => buf[index] = value as u8,
+        DecodingResult::U16(buf) => buf[index] = value as u16,
+        DecodingResult::U32(buf) => buf[index] = value as u32,
+        DecodingResult::U64(buf) => buf[index] = value,
+        DecodingResult::F16(buf) => buf[index] = f16::from_bits(value as u16),
+        DecodingResult::F32(buf) if bits == 24 => buf[index] = f24_to_f32(value as u32),
+        DecodingResult::F32(buf) => buf[index] = f32::from_bits(value as u32),
+        DecodingResult::F64(buf) => buf[index] = f64::from_bits(value),
+        DecodingResult::I8(buf) => buf[index] = signed as i8,
+        DecodingResult::I16(buf) => buf[index] = signed as i16,
+        DecodingResult::I32(buf) => buf[index] = signed as i32,
+        DecodingResult::I64(buf) => buf[index] = signed,
+        DecodingResult::ComplexI16(buf) => buf[index / 2][index % 2] = signed as i16,
+        DecodingResult::ComplexI32(buf) => buf[index / 2][index % 2] = signed as i32,
+        DecodingResult::ComplexF32(buf) => buf[index / 2][index % 2] = f32::from_bits(value as u32),
+        DecodingResult::ComplexF64(buf) => buf[index / 2][index % 2] = f64::from_bits(value),
+    }
+}
+
+/// The depth and format of a single sample of a pixel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SampleDescriptor {
+    /// The number of bits of the sample
+    pub bits: u8,
+    /// The format of the sample
+    pub format: SampleFormat,
+}
+
 /// Decoding limits
 #[derive(Clone, Debug)]
 pub struct Limits {
@@ -305,6 +490,9 @@ where
     ifd_offsets: Vec<u64>,
     seen_ifds: HashSet<u64>,
     image: Image,
+    conversions: Conversions,
+    normalized_f32: bool,
+    progress: Option<ProgressCallback>,
 }
 
 trait Wrapping {
@@ -365,6 +553,25 @@ fn rev_hpredict_nsamp<T: Copy + Wrapping>(image: &mut [T], samples: usize) {
     }
 }
 
+pub fn fp_predict_f16(input: &mut [u8], output: &mut [f16], samples: usize) {
+    rev_hpredict_nsamp(input, samples);
+    for i in 0..output.len() {
+        output[i] = f16::from_be_bytes([input[i], input[input.len() / 2 + i]]);
+    }
+}
+
+pub fn fp_predict_f24(input: &mut [u8], output: &mut [f32], samples: usize) {
+    rev_hpredict_nsamp(input, samples);
+    for i in 0..output.len() {
+        output[i] = f24_to_f32(u32::from_be_bytes([
+            0,
+            input[i],
+            input[input.len() / 3 + i],
+            input[input.len() / 3 * 2 + i],
+        ]));
+    }
+}
+
 pub fn fp_predict_f32(input: &mut [u8], output: &mut [f32], samples: usize) {
     rev_hpredict_nsamp(input, samples);
     for i in 0..output.len() {
@@ -395,6 +602,45 @@ pub fn fp_predict_f64(input: &mut [u8], output: &mut [f64], samples: usize) {
     }
 }
 
+/// Widens a 24-bit float, with 1 sign, 7 exponent and 16 mantissa bits, to a `f32`.
+fn f24_to_f32(bits: u32) -> f32 {
+    let exponent = (bits >> 16) & 0x7f;
+    let mantissa = bits & 0xffff;
+    let magnitude = match exponent {
+        0 => mantissa as f32 * 2f32.powi(-78),
+        0x7f => f32::from_bits(0xff << 23 | mantissa << 7),
+        _ => f32::from_bits((exponent + 127 - 63) << 23 | mantissa << 7),
+    };
+
+    if bits & 0x80_0000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Widens a row of 24-bit floats to `f32`. The samples are stored in `byte_order`, or shuffled by
+/// the floating point predictor.
+fn expand_f24(
+    input: &mut [u8],
+    output: &mut [f32],
+    samples: usize,
+    byte_order: ByteOrder,
+    predictor: Predictor,
+) {
+    if predictor == Predictor::FloatingPoint {
+        return fp_predict_f24(input, output, samples);
+    }
+
+    for (value, bytes) in output.iter_mut().zip(input.chunks_exact(3)) {
+        let bits = match byte_order {
+            ByteOrder::LittleEndian => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]),
+            ByteOrder::BigEndian => u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]),
+        };
+        *value = f24_to_f32(bits);
+    }
+}
+
 fn fix_endianness_and_predict(
     mut image: DecodingBuffer,
     samples: usize,
@@ -416,7 +662,7 @@ fn fix_endianness_and_predict(
                 DecodingBuffer::I16(buf) => rev_hpredict_nsamp(buf, samples),
                 DecodingBuffer::I32(buf) => rev_hpredict_nsamp(buf, samples),
                 DecodingBuffer::I64(buf) => rev_hpredict_nsamp(buf, samples),
-                DecodingBuffer::F32(_) | DecodingBuffer::F64(_) => {
+                DecodingBuffer::F16(_) | DecodingBuffer::F32(_) | DecodingBuffer::F64(_) => {
                     unreachable!("Caller should have validated arguments. Please file a bug.")
                 }
             }
@@ -425,6 +671,7 @@ fn fix_endianness_and_predict(
             let mut buffer_copy = image.as_bytes_mut().to_vec();
             match image {
                 DecodingBuffer::F32(buf) => fp_predict_f32(&mut buffer_copy, buf, samples),
+                DecodingBuffer::F16(buf) => fp_predict_f16(&mut buffer_copy, buf, samples),
                 DecodingBuffer::F64(buf) => fp_predict_f64(&mut buffer_copy, buf, samples),
                 _ => unreachable!("Caller should have validated arguments. Please file a bug."),
             }
@@ -432,49 +679,130 @@ fn fix_endianness_and_predict(
     }
 }
 
-fn invert_colors_unsigned<T>(buffer: &mut [T], max: T)
+fn invert_colors_unsigned<T>(buffer: &mut [T], max: T, step: usize)
 where
     T: std::ops::Sub<T> + std::ops::Sub<Output = T> + Copy,
 {
-    for datum in buffer.iter_mut() {
+    for datum in buffer.iter_mut().step_by(step) {
         *datum = max - *datum
     }
 }
 
-fn invert_colors_fp<T>(buffer: &mut [T], max: T)
+fn invert_colors_fp<T>(buffer: &mut [T], max: T, step: usize)
 where
     T: std::ops::Sub<T> + std::ops::Sub<Output = T> + Copy,
 {
-    for datum in buffer.iter_mut() {
+    for datum in buffer.iter_mut().step_by(step) {
         // FIXME: assumes [0, 1) range for floats
         *datum = max - *datum
     }
 }
 
-fn invert_colors(buf: &mut DecodingBuffer, color_type: ColorType) {
-    match (color_type, buf) {
-        (ColorType::Gray(64), DecodingBuffer::U64(ref mut buffer)) => {
-            invert_colors_unsigned(buffer, 0xffff_ffff_ffff_ffff);
+fn invert_colors(buf: &mut DecodingBuffer, color_type: ColorType, samples: usize) {
+    // Only the gray channel is inverted, it is followed by any extra samples of the pixel.
+    let (bits, step) = match color_type {
+        ColorType::Gray(n) => (n, 1),
+        ColorType::GrayA(n) | ColorType::Multiband { bit_depth: n, .. } if n >= 8 => (n, samples),
+        _ => return,
+    };
+    match (bits, buf) {
+        (64, DecodingBuffer::U64(ref mut buffer)) => {
+            invert_colors_unsigned(buffer, 0xffff_ffff_ffff_ffff, step);
+        }
+        (32, DecodingBuffer::U32(ref mut buffer)) => {
+            invert_colors_unsigned(buffer, 0xffff_ffff, step);
         }
-        (ColorType::Gray(32), DecodingBuffer::U32(ref mut buffer)) => {
-            invert_colors_unsigned(buffer, 0xffff_ffff);
+        (16, DecodingBuffer::U16(ref mut buffer)) => {
+            invert_colors_unsigned(buffer, 0xffff, step);
         }
-        (ColorType::Gray(16), DecodingBuffer::U16(ref mut buffer)) => {
-            invert_colors_unsigned(buffer, 0xffff);
+        (n, DecodingBuffer::U8(ref mut buffer)) if n <= 8 => {
+            invert_colors_unsigned(buffer, 0xff, step);
         }
-        (ColorType::Gray(n), DecodingBuffer::U8(ref mut buffer)) if n <= 8 => {
-            invert_colors_unsigned(buffer, 0xff);
+        (16, DecodingBuffer::F16(ref mut buffer)) => {
+            invert_colors_fp(buffer, f16::ONE, step);
         }
-        (ColorType::Gray(32), DecodingBuffer::F32(ref mut buffer)) => {
-            invert_colors_fp(buffer, 1.0);
+        (24, DecodingBuffer::F32(ref mut buffer)) | (32, DecodingBuffer::F32(ref mut buffer)) => {
+            invert_colors_fp(buffer, 1.0, step);
         }
-        (ColorType::Gray(64), DecodingBuffer::F64(ref mut buffer)) => {
-            invert_colors_fp(buffer, 1.0);
+        (64, DecodingBuffer::F64(ref mut buffer)) => {
+            invert_colors_fp(buffer, 1.0, step);
         }
         _ => {}
     }
 }
 
+fn unassociate_alpha_uint<T>(buffer: &mut [T], samples: usize, colors: usize, alpha: usize, max: T)
+where
+    T: Copy + Into<u128> + TryFrom<u128>,
+{
+    // The product of two 64 bit values and the rounding term fit into an u128.
+    let max: u128 = max.into();
+    for pixel in buffer.chunks_exact_mut(samples) {
+        let a: u128 = pixel[alpha].into();
+        if a == 0 {
+            continue;
+        }
+        for datum in pixel[..colors].iter_mut() {
+            let value = ((*datum).into() * max + a / 2) / a;
+            if let Ok(value) = T::try_from(value.min(max)) {
+                *datum = value;
+            }
+        }
+    }
+}
+
+fn unassociate_alpha_int<T>(buffer: &mut [T], samples: usize, colors: usize, alpha: usize, max: T)
+where
+    T: Copy + Into<i128> + TryFrom<i128>,
+{
+    let max: i128 = max.into();
+    for pixel in buffer.chunks_exact_mut(samples) {
+        let a: i128 = pixel[alpha].into();
+        if a <= 0 {
+            continue;
+        }
+        for datum in pixel[..colors].iter_mut() {
+            let value = ((*datum).into() * max + a / 2) / a;
+            if let Ok(value) = T::try_from(value.clamp(-max - 1, max)) {
+                *datum = value;
+            }
+        }
+    }
+}
+
+fn unassociate_alpha_fp<T>(buffer: &mut [T], samples: usize, colors: usize, alpha: usize)
+where
+    T: std::ops::DivAssign<T> + Copy + Default + PartialEq,
+{
+    for pixel in buffer.chunks_exact_mut(samples) {
+        let a = pixel[alpha];
+        if a == T::default() {
+            continue;
+        }
+        for datum in pixel[..colors].iter_mut() {
+            *datum /= a;
+        }
+    }
+}
+
+/// Divides the first `colors` channels of each pixel by its associated alpha channel at index
+/// `alpha`, which turns premultiplied into straight color.
+fn unassociate_alpha(buf: &mut DecodingBuffer, samples: usize, colors: usize, alpha: usize) {
+    match buf {
+        DecodingBuffer::U8(b) => unassociate_alpha_uint(b, samples, colors, alpha, u8::MAX),
+        DecodingBuffer::U16(b) => unassociate_alpha_uint(b, samples, colors, alpha, u16::MAX),
+        DecodingBuffer::U32(b) => unassociate_alpha_uint(b, samples, colors, alpha, u32::MAX),
+        DecodingBuffer::U64(b) => unassociate_alpha_uint(b, samples, colors, alpha, u64::MAX),
+        DecodingBuffer::I8(b) => unassociate_alpha_int(b, samples, colors, alpha, i8::MAX),
+        DecodingBuffer::I16(b) => unassociate_alpha_int(b, samples, colors, alpha, i16::MAX),
+        DecodingBuffer::I32(b) => unassociate_alpha_int(b, samples, colors, alpha, i32::MAX),
+        DecodingBuffer::I64(b) => unassociate_alpha_int(b, samples, colors, alpha, i64::MAX),
+        DecodingBuffer::F16(b) => unassociate_alpha_fp(b, samples, colors, alpha),
+        DecodingBuffer::F32(b) => unassociate_alpha_fp(b, samples, colors, alpha),
+        DecodingBuffer::F64(b) => unassociate_alpha_fp(b, samples, colors, alpha),
+    }
+}
+
 /// Fix endianness. If `byte_order` matches the host, then conversion is a no-op.
 fn fix_endianness(buf: &mut DecodingBuffer, byte_order: ByteOrder) {
     match byte_order {
@@ -486,6 +814,9 @@ fn fix_endianness(buf: &mut DecodingBuffer, byte_order: ByteOrder) {
             DecodingBuffer::I32(b) => b.iter_mut().for_each(|v| *v = i32::from_le(*v)),
             DecodingBuffer::U64(b) => b.iter_mut().for_each(|v| *v = u64::from_le(*v)),
             DecodingBuffer::I64(b) => b.iter_mut().for_each(|v| *v = i64::from_le(*v)),
+            DecodingBuffer::F16(b) => b
+                .iter_mut()
+                .for_each(|v| *v = f16::from_bits(u16::from_le(v.to_bits()))),
             DecodingBuffer::F32(b) => b
                 .iter_mut()
                 .for_each(|v| *v = f32::from_bits(u32::from_le(v.to_bits()))),
@@ -501,6 +832,9 @@ fn fix_endianness(buf: &mut DecodingBuffer, byte_order: ByteOrder) {
             DecodingBuffer::I32(b) => b.iter_mut().for_each(|v| *v = i32::from_be(*v)),
             DecodingBuffer::U64(b) => b.iter_mut().for_each(|v| *v = u64::from_be(*v)),
             DecodingBuffer::I64(b) => b.iter_mut().for_each(|v| *v = i64::from_be(*v)),
+            DecodingBuffer::F16(b) => b
+                .iter_mut()
+                .for_each(|v| *v = f16::from_bits(u16::from_be(v.to_bits()))),
             DecodingBuffer::F32(b) => b
                 .iter_mut()
                 .for_each(|v| *v = f32::from_bits(u32::from_be(v.to_bits()))),
@@ -574,16 +908,26 @@ impl<R: Read + Seek> Decoder<R> {
                 bits_per_sample: vec![1],
                 samples: 1,
                 sample_format: vec![SampleFormat::Uint],
+                extra_samples: Vec::new(),
                 photometric_interpretation: PhotometricInterpretation::BlackIsZero,
                 compression_method: CompressionMethod::None,
+                fill_order: FillOrder::MsbToLsb,
+                planar_config: PlanarConfiguration::Chunky,
                 jpeg_tables: None,
+                old_jpeg: None,
+                lerc_compression: LercCompression::None,
                 predictor: Predictor::None,
                 chunk_type: ChunkType::Strip,
                 strip_decoder: None,
                 tile_attributes: None,
                 chunk_offsets: Vec::new(),
                 chunk_bytes: Vec::new(),
+                ycbcr: None,
+                lab: None,
             },
+            conversions: Conversions::default(),
+            normalized_f32: false,
+            progress: None,
         };
         decoder.next_image()?;
         Ok(decoder)
@@ -594,14 +938,252 @@ impl<R: Read + Seek> Decoder<R> {
         self
     }
 
+    /// Convert premultiplied alpha to straight alpha while decoding.
+    ///
+    /// When enabled, the color channels of images with an [`ExtraSample::AssociatedAlpha`]
+    /// channel are divided by the alpha value. Otherwise, samples are returned as stored.
+    pub fn with_unassociated_alpha(mut self, enabled: bool) -> Decoder<R> {
+        self.conversions.unassociate_alpha = enabled;
+        self
+    }
+
+    /// Convert 8-bit YCbCr images to RGB while decoding.
+    ///
+    /// When enabled, such images report [`ColorType::RGB`] and their samples are converted using
+    /// the `YCbCrCoefficients` and `ReferenceBlackWhite` tags. Otherwise, the Y, Cb and Cr samples
+    /// of each pixel are returned. Subsampled chroma is expanded to every pixel either way.
+    pub fn with_ycbcr_to_rgb(mut self, enabled: bool) -> Decoder<R> {
+        self.conversions.ycbcr_to_rgb = enabled;
+        self
+    }
+
+    /// Convert CIE L*a*b* images to sRGB while decoding.
+    ///
+    /// When enabled, such images of 8 and 16 bits report [`ColorType::RGB`] and their samples are
+    /// converted from L*a*b* relative to the D50 white point. Otherwise, the samples are returned
+    /// as [`ColorType::Lab`].
+    pub fn with_lab_to_srgb(mut self, enabled: bool) -> Decoder<R> {
+        self.conversions.lab_to_srgb = enabled;
+        self
+    }
+
+    /// Convert CIE XYZ images to RGB while decoding.
+    ///
+    /// When enabled, the 32-bit floats that LogLuv images are decoded to report
+    /// [`ColorType::RGB`] and hold linear RGB with ITU-R BT.709 primaries and an equal energy
+    /// white point, like libtiff. Otherwise, the samples are returned as [`ColorType::XYZ`].
+    pub fn with_xyz_to_rgb(mut self, enabled: bool) -> Decoder<R> {
+        self.conversions.xyz_to_rgb = enabled;
+        self
+    }
+
+    /// Return the samples of `read_image` and `read_chunk` as floats in `[0, 1]`.
+    ///
+    /// When enabled, every sample is mapped from its range to [`DecodingResult::F32`]. The range
+    /// is the one declared by `SMinSampleValue` and `SMaxSampleValue`, or by `MinSampleValue` and
+    /// `MaxSampleValue` for unsigned integers. Otherwise, it is the full range of the bit depth of
+    /// integers, while floats are assumed to already be in `[0, 1]`. Samples out of range are
+    /// clamped.
+    pub fn with_normalized_f32(mut self, enabled: bool) -> Decoder<R> {
+        self.normalized_f32 = enabled;
+        self
+    }
+
+    /// Report the [`Progress`] of `read_image` and `read_image_channels` after each chunk is read.
+    pub fn with_progress(mut self, callback: impl FnMut(Progress) + Send + 'static) -> Decoder<R> {
+        self.progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
     pub fn dimensions(&mut self) -> TiffResult<(u32, u32)> {
         Ok((self.image().width, self.image().height))
     }
 
     pub fn colortype(&mut self) -> TiffResult<ColorType> {
+        if self.conversions.ycbcr_to_rgb && self.image().rgb_conversion().is_some() {
+            return Ok(ColorType::RGB(8));
+        }
+        if self.conversions.lab_to_srgb {
+            if let Some(n) = self.image().srgb_conversion() {
+                return Ok(ColorType::RGB(n));
+            }
+        }
+        if self.conversions.xyz_to_rgb {
+            if let Ok(ColorType::XYZ(n)) = self.image().colortype() {
+                return Ok(ColorType::RGB(n));
+            }
+        }
         self.image().colortype()
     }
 
+    /// The meaning of the samples that follow the color channels of each pixel.
+    ///
+    /// This is the value of the `ExtraSamples` tag. An [`ExtraSample::AssociatedAlpha`] channel is
+    /// premultiplied into the color channels, see [`Decoder::with_unassociated_alpha`].
+    pub fn extra_samples(&self) -> &[ExtraSample] {
+        &self.image().extra_samples
+    }
+
+    /// The depth and format of every sample of a pixel, which may differ between samples.
+    pub fn sample_descriptors(&self) -> Vec<SampleDescriptor> {
+        self.image()
+            .bits_per_sample
+            .iter()
+            .zip(self.image().sample_formats())
+            .map(|(&bits, format)| SampleDescriptor { bits, format })
+            .collect()
+    }
+
+    /// The smallest value of each sample of a pixel, from the `MinSampleValue` tag.
+    ///
+    /// This is 0 for every sample if the tag is absent.
+    pub fn min_sample_values(&mut self) -> TiffResult<Vec<u64>> {
+        match self.find_tag_unsigned_vec(Tag::MinSampleValue)? {
+            Some(values) => self.per_sample(values, Tag::MinSampleValue),
+            None => Ok(vec![0; self.image().bits_per_sample.len()]),
+        }
+    }
+
+    /// The largest value of each sample of a pixel, from the `MaxSampleValue` tag.
+    ///
+    /// This is the largest value that fits the bits of each sample if the tag is absent.
+    pub fn max_sample_values(&mut self) -> TiffResult<Vec<u64>> {
+        match self.find_tag_unsigned_vec(Tag::MaxSampleValue)? {
+            Some(values) => self.per_sample(values, Tag::MaxSampleValue),
+            None => Ok(self
+                .image()
+                .bits_per_sample
+                .iter()
+                .map(|&bits| ((1u128 << bits.min(64)) - 1) as u64)
+                .collect()),
+        }
+    }
+
+    /// The smallest value of each sample of a pixel in the format of the samples, from the
+    /// `SMinSampleValue` tag if there is one.
+    pub fn smin_sample_values(&mut self) -> TiffResult<Option<Vec<f64>>> {
+        self.find_tag(Tag::SMinSampleValue)?
+            .map(|value| self.per_sample(value.into_f64_vec()?, Tag::SMinSampleValue))
+            .transpose()
+    }
+
+    /// The largest value of each sample of a pixel in the format of the samples, from the
+    /// `SMaxSampleValue` tag if there is one.
+    pub fn smax_sample_values(&mut self) -> TiffResult<Option<Vec<f64>>> {
+        self.find_tag(Tag::SMaxSampleValue)?
+            .map(|value| self.per_sample(value.into_f64_vec()?, Tag::SMaxSampleValue))
+            .transpose()
+    }
+
+    /// Repeats a single value of `tag` for every sample of a pixel.
+    fn per_sample<T: Copy>(&self, values: Vec<T>, tag: Tag) -> TiffResult<Vec<T>> {
+        let samples = self.image().bits_per_sample.len();
+        match values[..] {
+            [value] => Ok(vec![value; samples]),
+            _ if values.len() == samples => Ok(values),
+            _ => Err(TiffFormatError::InvalidTagValueType(tag).into()),
+        }
+    }
+
+    /// The values that each sample of a pixel maps to 0 and 1 when normalizing it.
+    fn sample_ranges(&mut self) -> TiffResult<Vec<(f64, f64)>> {
+        let (smin, smax) = (self.smin_sample_values()?, self.smax_sample_values()?);
+        let (min, max) = (self.min_sample_values()?, self.max_sample_values()?);
+
+        let mut ranges = Vec::new();
+        for (i, sample) in self.sample_descriptors().into_iter().enumerate() {
+            let (default_min, default_max) = match sample.format {
+                SampleFormat::Uint => (min[i] as f64, max[i] as f64),
+                SampleFormat::Int => {
+                    let half = 2f64.powi(i32::from(sample.bits) - 1);
+                    (-half, half - 1.0)
+                }
+                SampleFormat::IEEEFP => (0.0, 1.0),
+                format => {
+                    return Err(TiffUnsupportedError::UnsupportedSampleFormat(vec![format]).into())
+                }
+            };
+            ranges.push((
+                smin.as_ref().map_or(default_min, |smin| smin[i]),
+                smax.as_ref().map_or(default_max, |smax| smax[i]),
+            ));
+        }
+        Ok(ranges)
+    }
+
+    /// Maps the samples of `result` to floats in `[0, 1]`, where `ranges` holds the range of
+    /// each sample of a pixel and rows have `row_samples` samples.
+    fn normalize_result(
+        &self,
+        result: DecodingResult,
+        ranges: &[(f64, f64)],
+        row_samples: usize,
+    ) -> TiffResult<DecodingResult> {
+        fn normalize<T: Copy>(
+            samples: &[T],
+            ranges: &[(f64, f64)],
+            value: impl Fn(T) -> f64,
+        ) -> Vec<f32> {
+            samples
+                .iter()
+                .enumerate()
+                .map(|(i, &sample)| {
+                    let (min, max) = ranges[i % ranges.len()];
+                    let normalized = if max > min {
+                        (value(sample) - min) / (max - min)
+                    } else {
+                        0.0
+                    };
+                    normalized.clamp(0.0, 1.0) as f32
+                })
+                .collect()
+        }
+
+        let bits = self
+            .image()
+            .bits_per_sample
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(8);
+        Ok(DecodingResult::F32(match result {
+            // Samples of fewer than 8 bits are packed into rows of bytes.
+            DecodingResult::U8(buf) if bits < 8 => {
+                let bits = usize::from(bits);
+                let row_len = (row_samples * bits + 7) / 8;
+                let unpacked: Vec<u8> = buf
+                    .chunks(row_len)
+                    .flat_map(|row| {
+                        (0..row_samples).map(move |i| {
+                            let (byte, shift) = (i * bits / 8, i * bits % 8);
+                            let window = u16::from(row[byte]) << 8
+                                | u16::from(row.get(byte + 1).copied().unwrap_or(0));
+                            (window >> (16 - bits - shift)) as u8 & ((1 << bits) - 1)
+                        })
+                    })
+                    .collect();
+                normalize(&unpacked, ranges, f64::from)
+            }
+            DecodingResult::U8(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::U16(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::U32(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::U64(buf) => normalize(&buf, ranges, |sample| sample as f64),
+            DecodingResult::F16(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::F32(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::F64(buf) => normalize(&buf, ranges, |sample| sample),
+            DecodingResult::I8(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::I16(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::I32(buf) => normalize(&buf, ranges, f64::from),
+            DecodingResult::I64(buf) => normalize(&buf, ranges, |sample| sample as f64),
+            DecodingResult::ComplexI16(_)
+            | DecodingResult::ComplexI32(_)
+            | DecodingResult::ComplexF32(_)
+            | DecodingResult::ComplexF64(_) => {
+                return Err(TiffUnsupportedError::UnsupportedDataType.into())
+            }
+        }))
+    }
+
     fn image(&self) -> &Image {
         &self.image
     }
@@ -872,6 +1454,143 @@ impl<R: Read + Seek> Decoder<R> {
         Ok((dir, next_ifd))
     }
 
+    fn is_native_byte_order(&self) -> bool {
+        self.byte_order() == ByteOrder::native()
+    }
+
+    /// Reads the values of every tag of the current image in native byte order, along with their
+    /// type and count, to write them to another file.
+    pub(crate) fn native_tags(&mut self) -> TiffResult<Vec<NativeTag>> {
+        let entries: Vec<_> = self
+            .image()
+            .ifd
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|(&tag, entry)| (tag, entry.raw()))
+            .collect();
+
+        let mut tags = Vec::with_capacity(entries.len());
+        for (tag, (type_, count, field)) in entries {
+            // Rationals are swapped as two separate integers.
+            let (value_size, values_per_count) = match type_ {
+                Type::BYTE | Type::SBYTE | Type::ASCII | Type::UNDEFINED => (1, 1),
+                Type::SHORT | Type::SSHORT => (2, 1),
+                Type::LONG | Type::SLONG | Type::FLOAT | Type::IFD => (4, 1),
+                Type::RATIONAL | Type::SRATIONAL => (4, 2),
+                Type::LONG8 | Type::SLONG8 | Type::DOUBLE | Type::IFD8 => (8, 1),
+            };
+            let len = count
+                .checked_mul(value_size * values_per_count)
+                .filter(|&len| len <= self.limits.ifd_value_size as u64)
+                .ok_or(TiffError::LimitsExceeded)?;
+            let len = usize::try_from(len)?;
+            let mut bytes = vec![0; len];
+
+            let field_size = if self.bigtiff { 8 } else { 4 };
+            if len <= field_size {
+                bytes.copy_from_slice(&field[..len]);
+            } else {
+                let mut field = SmartReader::wrap(io::Cursor::new(field), self.byte_order());
+                let offset = if self.bigtiff {
+                    field.read_u64()?
+                } else {
+                    field.read_u32()?.into()
+                };
+                self.goto_offset_u64(offset)?;
+                self.reader.read_exact(&mut bytes)?;
+            }
+
+            if !self.is_native_byte_order() {
+                for value in bytes.chunks_exact_mut(value_size as usize) {
+                    value.reverse();
+                }
+            }
+            tags.push((tag, type_, count, bytes));
+        }
+
+        Ok(tags)
+    }
+
+    /// Reads the specified chunk as bytes of samples in native byte order, as they are stored.
+    ///
+    /// Unlike `read_chunk`, none of the conversions enabled on the decoder are applied.
+    pub(crate) fn read_chunk_bytes(&mut self, chunk_index: u32) -> TiffResult<Vec<u8>> {
+        let mut result = self.read_chunk_samples(chunk_index, Conversions::default())?;
+        let bytes = result.as_buffer(0).as_bytes_mut().to_vec();
+        Ok(bytes)
+    }
+
+    /// Reads the IFD at `ifd_index` in a file of native byte order, to rewrite it elsewhere.
+    ///
+    /// Returns the entries of the IFD as they are stored, including those of an unknown type, the
+    /// offset of the next IFD or 0, and the position of the pointer that links to the IFD. Errors
+    /// if the file is a BigTiff unlike `bigtiff`.
+    pub(crate) fn raw_ifd(
+        &mut self,
+        ifd_index: usize,
+        bigtiff: bool,
+    ) -> TiffResult<(Vec<RawEntry>, u64, u64)> {
+        if !self.is_native_byte_order() {
+            return Err(TiffUnsupportedError::NonNativeByteOrder.into());
+        }
+        if self.bigtiff != bigtiff {
+            return Err(TiffError::UsageError(UsageError::InvalidTiffKind {
+                file_is_bigtiff: self.bigtiff,
+            }));
+        }
+
+        while ifd_index >= self.ifd_offsets.len() {
+            self.next_ifd()?;
+        }
+
+        // The first IFD is linked from the header and the others from the end of their previous
+        // IFD, whose entries may include some of an unknown type that are not in its directory.
+        let link = match ifd_index.checked_sub(1) {
+            None if self.bigtiff => 8,
+            None => 4,
+            Some(previous) => {
+                let previous = self.ifd_offsets[previous];
+                self.goto_offset_u64(previous)?;
+                let (count_size, entry_size, entries) = if self.bigtiff {
+                    (8, 20, self.read_long8()?)
+                } else {
+                    (2, 12, self.read_short()?.into())
+                };
+                entries
+                    .checked_mul(entry_size)
+                    .and_then(|len| len.checked_add(previous + count_size))
+                    .ok_or(TiffFormatError::InvalidTag)?
+            }
+        };
+
+        self.goto_offset_u64(self.ifd_offsets[ifd_index])?;
+        let entries = if self.bigtiff {
+            self.read_long8()?
+        } else {
+            self.read_short()?.into()
+        };
+        let mut dir = Vec::new();
+        for _ in 0..entries {
+            let tag = self.read_short()?;
+            let type_ = self.read_short()?;
+            let (count, mut offset) = if self.bigtiff {
+                (self.read_long8()?, vec![0; 8])
+            } else {
+                (self.read_long()?.into(), vec![0; 4])
+            };
+            self.reader.read_exact(&mut offset)?;
+            dir.push((tag, type_, count, offset));
+        }
+        let next_ifd = if self.bigtiff {
+            self.read_long8()?
+        } else {
+            self.read_long()?.into()
+        };
+
+        Ok((dir, next_ifd, link))
+    }
+
     /// Tries to retrieve a tag.
     /// Return `Ok(None)` if the tag is not present.
     pub fn find_tag(&mut self, tag: Tag) -> TiffResult<Option<ifd::Value>> {
@@ -1002,6 +1721,8 @@ impl<R: Read + Seek> Decoder<R> {
     }
 
     /// Number of strips in image
+    ///
+    /// For planar images this counts the strips of all planes.
     pub fn strip_count(&mut self) -> TiffResult<u32> {
         self.check_chunk_type(ChunkType::Strip)?;
         let rows_per_strip = self.image().strip_decoder.as_ref().unwrap().rows_per_strip;
@@ -1016,7 +1737,13 @@ impl<R: Read + Seek> Decoder<R> {
             None => return Err(TiffError::IntSizeError),
         };
 
-        Ok(height / rows_per_strip)
+        let planes = match self.image().planar_config {
+            PlanarConfiguration::Chunky => 1,
+            PlanarConfiguration::Planar => u32::from(self.image().samples),
+        };
+        (height / rows_per_strip)
+            .checked_mul(planes)
+            .ok_or(TiffError::IntSizeError)
     }
 
     /// Number of tiles in image
@@ -1034,29 +1761,30 @@ impl<R: Read + Seek> Decoder<R> {
         let offset = self.image.chunk_file_range(chunk_index)?.0;
         self.goto_offset_u64(offset)?;
 
-        let byte_order = self.reader.byte_order;
-
         self.image.expand_chunk(
             &mut self.reader,
             buffer.copy(),
             output_width,
-            byte_order,
             chunk_index,
             &self.limits,
+            self.conversions,
         )?;
 
         Ok(())
     }
 
-    fn result_buffer(&self, width: usize, height: usize) -> TiffResult<DecodingResult> {
-        let buffer_size = match width
-            .checked_mul(height)
-            .and_then(|x| x.checked_mul(self.image().bits_per_sample.len()))
-        {
+    fn result_buffer(&self, row_len: usize, height: usize) -> TiffResult<DecodingResult> {
+        let buffer_size = match row_len.checked_mul(height) {
             Some(s) => s,
             None => return Err(TiffError::LimitsExceeded),
         };
 
+        // All samples share one buffer, see `read_image_channels` for samples that differ.
+        let sample_formats = self.image().sample_formats();
+        if !sample_formats.windows(2).all(|s| s[0] == s[1]) {
+            return Err(TiffUnsupportedError::UnsupportedSampleFormat(sample_formats).into());
+        }
+
         let max_sample_bits = self
             .image()
             .bits_per_sample
@@ -1064,54 +1792,82 @@ impl<R: Read + Seek> Decoder<R> {
             .cloned()
             .max()
             .unwrap_or(8);
-        match self
-            .image()
-            .sample_format
-            .first()
-            .unwrap_or(&SampleFormat::Uint)
-        {
-            SampleFormat::Uint => match max_sample_bits {
-                n if n <= 8 => DecodingResult::new_u8(buffer_size, &self.limits),
-                n if n <= 16 => DecodingResult::new_u16(buffer_size, &self.limits),
-                n if n <= 32 => DecodingResult::new_u32(buffer_size, &self.limits),
-                n if n <= 64 => DecodingResult::new_u64(buffer_size, &self.limits),
-                n => Err(TiffError::UnsupportedError(
-                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
-                )),
-            },
-            SampleFormat::IEEEFP => match max_sample_bits {
-                32 => DecodingResult::new_f32(buffer_size, &self.limits),
-                64 => DecodingResult::new_f64(buffer_size, &self.limits),
-                n => Err(TiffError::UnsupportedError(
-                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
-                )),
-            },
-            SampleFormat::Int => match max_sample_bits {
-                n if n <= 8 => DecodingResult::new_i8(buffer_size, &self.limits),
-                n if n <= 16 => DecodingResult::new_i16(buffer_size, &self.limits),
-                n if n <= 32 => DecodingResult::new_i32(buffer_size, &self.limits),
-                n if n <= 64 => DecodingResult::new_i64(buffer_size, &self.limits),
-                n => Err(TiffError::UnsupportedError(
-                    TiffUnsupportedError::UnsupportedBitsPerChannel(n),
-                )),
-            },
-            format => {
-                Err(TiffUnsupportedError::UnsupportedSampleFormat(vec![format.clone()]).into())
-            }
-        }
+        let sample = SampleDescriptor {
+            bits: max_sample_bits,
+            format: sample_formats
+                .first()
+                .cloned()
+                .unwrap_or(SampleFormat::Uint),
+        };
+        // Row lengths count both parts of complex samples.
+        let buffer_size = buffer_size / self.image().sample_parts();
+        DecodingResult::new_for_sample(sample, buffer_size, &self.limits)
     }
 
     /// Read the specified chunk (at index `chunk_index`) and return the binary data as a Vector.
     pub fn read_chunk(&mut self, chunk_index: u32) -> TiffResult<DecodingResult> {
+        let result = self.read_chunk_samples(chunk_index, self.conversions)?;
+        if !self.normalized_f32 {
+            return Ok(result);
+        }
+
+        // The chunks of planar images hold a single sample of each pixel.
+        let mut ranges = self.sample_ranges()?;
+        if self.image().planar_config == PlanarConfiguration::Planar {
+            let (plane, _) = self.image().plane_chunk_index(chunk_index)?;
+            ranges = vec![ranges[plane]];
+        }
+        let data_width = self.image().chunk_data_dimensions(chunk_index)?.0 as usize;
+        let row_samples = data_width * self.image().samples_per_chunk();
+        self.normalize_result(result, &ranges, row_samples)
+    }
+
+    fn read_chunk_samples(
+        &mut self,
+        chunk_index: u32,
+        conversions: Conversions,
+    ) -> TiffResult<DecodingResult> {
         let data_dims = self.image().chunk_data_dimensions(chunk_index)?;
 
-        let mut result = self.result_buffer(data_dims.0 as usize, data_dims.1 as usize)?;
+        let row_len = self.image().chunk_row_len(data_dims.0 as usize);
+        let mut result = self.result_buffer(row_len, data_dims.1 as usize)?;
 
-        self.read_chunk_to_buffer(result.as_buffer(0), chunk_index, data_dims.0 as usize)?;
+        let offset = self.image.chunk_file_range(chunk_index)?.0;
+        self.goto_offset_u64(offset)?;
+        self.image.expand_chunk(
+            &mut self.reader,
+            result.as_buffer(0),
+            data_dims.0 as usize,
+            chunk_index,
+            &self.limits,
+            conversions,
+        )?;
 
         Ok(result)
     }
 
+    /// Returns the offset and length in bytes of the specified chunk in the file.
+    pub fn chunk_file_range(&self, chunk_index: u32) -> TiffResult<(u64, u64)> {
+        self.image().chunk_file_range(chunk_index)
+    }
+
+    /// Read the specified chunk as it is stored in the file, without decompressing it.
+    ///
+    /// The bytes are subject to the compression, predictor and [`byte_order`][Self::byte_order]
+    /// of the image.
+    pub fn read_chunk_raw(&mut self, chunk_index: u32) -> TiffResult<Vec<u8>> {
+        let (offset, len) = self.image().chunk_file_range(chunk_index)?;
+        let len = usize::try_from(len)?;
+        if len > self.limits.decoding_buffer_size {
+            return Err(TiffError::LimitsExceeded);
+        }
+
+        let mut bytes = vec![0; len];
+        self.goto_offset_u64(offset)?;
+        self.reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
     /// Returns the default chunk size for the current image. Any given chunk in the image is at most as large as
     /// the value returned here. For the size of the data (chunk minus padding), use `chunk_data_dimensions`.
     pub fn chunk_dimensions(&self) -> (u32, u32) {
@@ -1128,9 +1884,21 @@ impl<R: Read + Seek> Decoder<R> {
 
     /// Decodes the entire image and return it as a Vector
     pub fn read_image(&mut self) -> TiffResult<DecodingResult> {
+        let result = self.read_image_samples()?;
+        if !self.normalized_f32 {
+            return Ok(result);
+        }
+
+        let ranges = self.sample_ranges()?;
+        let row_samples = self.image().width as usize * self.image().bits_per_sample.len();
+        self.normalize_result(result, &ranges, row_samples)
+    }
+
+    fn read_image_samples(&mut self) -> TiffResult<DecodingResult> {
         let width = self.image().width;
         let height = self.image().height;
-        let mut result = self.result_buffer(width as usize, height as usize)?;
+        let row_len = self.image().row_len(width as usize);
+        let mut result = self.result_buffer(row_len, height as usize)?;
         if width == 0 || height == 0 {
             return Ok(result);
         }
@@ -1154,25 +1922,196 @@ impl<R: Read + Seek> Decoder<R> {
         }
 
         let chunks_across = ((width - 1) / chunk_dimensions.0 + 1) as usize;
-        let strip_samples = width as usize * chunk_dimensions.1 as usize * samples;
 
+        if self.image().planar_config == PlanarConfiguration::Planar {
+            self.read_planes(&mut result, chunk_dimensions, chunks_across)?;
+            return Ok(result);
+        }
+
+        let strip_samples = row_len * chunk_dimensions.1 as usize;
+
+        let mut bytes = 0;
         for chunk in 0..self.image().chunk_offsets.len() {
             self.goto_offset_u64(self.image().chunk_offsets[chunk])?;
 
             let x = chunk % chunks_across;
             let y = chunk / chunks_across;
-            let buffer_offset = y * strip_samples + x * chunk_dimensions.0 as usize * samples;
-            let byte_order = self.reader.byte_order;
+            let buffer_offset =
+                y * strip_samples + self.image().row_len(x * chunk_dimensions.0 as usize);
             self.image.expand_chunk(
                 &mut self.reader,
                 result.as_buffer(buffer_offset).copy(),
                 width as usize,
-                byte_order,
                 chunk as u32,
                 &self.limits,
+                self.conversions,
             )?;
+            self.report_progress(chunk, &mut bytes);
         }
 
         Ok(result)
     }
+
+    /// Adds the bytes of `chunk` to `bytes` and reports the progress after reading it.
+    fn report_progress(&mut self, chunk: usize, bytes: &mut u64) {
+        *bytes += self.image.chunk_bytes.get(chunk).copied().unwrap_or(0);
+        if let Some(callback) = &mut self.progress {
+            callback.report(Progress {
+                chunk_index: chunk as u64,
+                chunk_count: self.image.chunk_offsets.len() as u64,
+                bytes: *bytes,
+            });
+        }
+    }
+
+    /// Decodes the entire image into a separate buffer for each sample of a pixel.
+    ///
+    /// Unlike `read_image`, this supports samples that differ in their depth or format, such as
+    /// 5/6/5 bit RGB or a float band next to an integer mask, see `sample_descriptors`. Each buffer
+    /// holds one element per pixel of the smallest type that fits its sample. The samples are
+    /// returned as stored, without applying the photometric interpretation.
+    pub fn read_image_channels(&mut self) -> TiffResult<Vec<DecodingResult>> {
+        let width = self.image().width as usize;
+        let height = self.image().height as usize;
+        let samples = self.sample_descriptors();
+        if let Some(sample) = samples.iter().find(|sample| sample.bits == 0) {
+            return Err(TiffError::UnsupportedError(
+                TiffUnsupportedError::UnsupportedBitsPerChannel(sample.bits),
+            ));
+        }
+
+        // The horizontal predictor applies to the integer value of each sample on its own.
+        let predictor = self.image().predictor;
+        match predictor {
+            Predictor::None => {}
+            Predictor::Horizontal
+                if samples.iter().all(|sample| {
+                    sample.format != SampleFormat::IEEEFP
+                        && sample.format != SampleFormat::ComplexIEEEFP
+                }) => {}
+            _ => {
+                return Err(TiffError::UnsupportedError(
+                    TiffUnsupportedError::UnsupportedPredictor(
+                        predictor,
+                        self.image().sample_formats(),
+                    ),
+                ))
+            }
+        }
+
+        let pixels = width.checked_mul(height).ok_or(TiffError::LimitsExceeded)?;
+        let mut channels = samples
+            .iter()
+            .map(|&sample| DecodingResult::new_for_sample(sample, pixels, &self.limits))
+            .collect::<TiffResult<Vec<_>>>()?;
+
+        let chunk_dimensions = self.image().chunk_dimensions()?;
+        if chunk_dimensions.0 == 0 || chunk_dimensions.1 == 0 {
+            return Err(TiffError::FormatError(
+                TiffFormatError::InconsistentSizesEncountered,
+            ));
+        }
+        let chunks_across = (width - 1) / chunk_dimensions.0 as usize + 1;
+
+        let mut bytes = 0;
+        for chunk in 0..u32::try_from(self.image().chunk_offsets.len())? {
+            let offset = self.image().chunk_file_range(chunk)?.0;
+            self.goto_offset_u64(offset)?;
+            let byte_order = self.reader.byte_order;
+            let data =
+                self.image
+                    .read_chunk_bytes(&mut self.reader, chunk, byte_order, &self.limits)?;
+
+            let chunk_samples = self.image().chunk_samples(chunk)?;
+            let (_, index) = self.image().plane_chunk_index(chunk)?;
+            let data_dims = self.image().chunk_data_dimensions(chunk)?;
+            let x = index as usize % chunks_across * chunk_dimensions.0 as usize;
+            let y = index as usize / chunks_across * chunk_dimensions.1 as usize;
+            let byte_order = self.reader.byte_order;
+
+            let row_len = data.len() / data_dims.1.max(1) as usize;
+            for (row, row_data) in data.chunks_exact(row_len).enumerate() {
+                let mut previous = vec![[0u64; 2]; samples.len()];
+                let mut bit = 0;
+                for column in 0..data_dims.0 as usize {
+                    let pixel = (y + row) * width + x + column;
+                    for sample in chunk_samples.clone() {
+                        // Both parts of a complex sample are read like a sample of their own.
+                        let (bits, parts) = match samples[sample].format {
+                            SampleFormat::ComplexInt | SampleFormat::ComplexIEEEFP => {
+                                (samples[sample].bits / 2, 2)
+                            }
+                            _ => (samples[sample].bits, 1),
+                        };
+                        for part in 0..parts {
+                            let mut value = read_sample_bits(row_data, bit, bits, byte_order);
+                            bit += usize::from(bits);
+
+                            if predictor == Predictor::Horizontal {
+                                let previous = &mut previous[sample][part];
+                                value = value.wrapping_add(*previous) & (u64::MAX >> (64 - bits));
+                                *previous = value;
+                            }
+                            set_sample(&mut channels[sample], pixel * parts + part, bits, value);
+                        }
+                    }
+                }
+            }
+            self.report_progress(chunk as usize, &mut bytes);
+        }
+
+        Ok(channels)
+    }
+
+    /// Decodes the chunks of a planar image and interleaves their samples into `result`.
+    fn read_planes(
+        &mut self,
+        result: &mut DecodingResult,
+        chunk_dimensions: (u32, u32),
+        chunks_across: usize,
+    ) -> TiffResult<()> {
+        let width = self.image().width as usize;
+        let samples = self.image().bits_per_sample.len();
+        if self.image().bits_per_sample.iter().any(|&bits| bits < 8) {
+            // Packed samples of different planes do not start on byte boundaries.
+            return Err(TiffError::UnsupportedError(
+                TiffUnsupportedError::UnsupportedPlanarConfig(Some(PlanarConfiguration::Planar)),
+            ));
+        }
+
+        let mut bytes = 0;
+        for chunk in 0..self.image().chunk_offsets.len() {
+            let chunk = u32::try_from(chunk)?;
+            let (plane, index) = self.image().plane_chunk_index(chunk)?;
+            let mut plane_chunk = self.read_chunk_samples(chunk, self.conversions)?;
+            let data_dims = self.image().chunk_data_dimensions(chunk)?;
+            let (data_width, data_height) = (data_dims.0 as usize, data_dims.1 as usize);
+
+            let x = index as usize % chunks_across * chunk_dimensions.0 as usize;
+            let y = index as usize / chunks_across * chunk_dimensions.1 as usize;
+
+            let mut input = plane_chunk.as_buffer(0);
+            let mut output = result.as_buffer(0);
+            let sample_len = output.byte_len() * self.image.sample_parts();
+            let (input, output) = (input.as_bytes_mut(), output.as_bytes_mut());
+            for row in 0..data_height {
+                for column in 0..data_width {
+                    let from = (row * data_width + column) * sample_len;
+                    let to = (((y + row) * width + x + column) * samples + plane) * sample_len;
+                    output[to..to + sample_len].copy_from_slice(&input[from..from + sample_len]);
+                }
+            }
+            self.report_progress(chunk as usize, &mut bytes);
+        }
+
+        if let Some((colors, alpha)) = self
+            .image()
+            .associated_alpha()
+            .filter(|_| self.conversions.unassociate_alpha)
+        {
+            unassociate_alpha(&mut result.as_buffer(0), samples, colors, alpha);
+        }
+
+        Ok(())
+    }
 }
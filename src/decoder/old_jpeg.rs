@@ -0,0 +1,363 @@
+//! Support for old-style JPEG compression, as it was specified in the TIFF 6.0 specification.
+//!
+//! Writers of such files disagree on how the JPEG data is laid out, so decoding is best effort.
+//! Either `JPEGInterchangeFormat` points to a complete stream of the whole image, or the chunks
+//! only hold entropy coded data and the headers to decode them are reconstructed from the
+//! interchange stream or the tables tags.
+use std::convert::TryFrom;
+use std::io::{Read, Seek};
+
+use super::stream::SmartReader;
+use super::tag_reader::TagReader;
+use crate::tags::{PhotometricInterpretation, Tag};
+use crate::{TiffError, TiffFormatError, TiffResult};
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const DQT: u8 = 0xdb;
+const DHT: u8 = 0xc4;
+const DRI: u8 = 0xdd;
+const SOF0: u8 = 0xc0;
+const SOF3: u8 = 0xc3;
+
+/// The JPEG headers shared by all chunks of an image with old-style JPEG compression.
+#[derive(Debug)]
+pub(crate) struct OldJpeg {
+    /// The quantization, Huffman, and restart interval segments.
+    tables: Vec<u8>,
+    /// The identifier, sampling factors, and quantization table of each component.
+    components: Vec<[u8; 3]>,
+    /// The number of DC and AC Huffman tables.
+    huffman_tables: [u8; 2],
+    /// The predictor and point transform of the lossless process.
+    lossless: Option<(u8, u8)>,
+    /// The file range of a stream that holds the whole image.
+    pub whole_image: Option<(u64, u64)>,
+}
+
+impl OldJpeg {
+    pub(crate) fn from_tags<R: Read + Seek>(
+        tag_reader: &mut TagReader<R>,
+        photometric_interpretation: PhotometricInterpretation,
+        dimensions: (u32, u32),
+        samples: u8,
+        chunk_ranges: impl Iterator<Item = (u64, u64)>,
+    ) -> TiffResult<Self> {
+        // Besides the baseline process, the lossless process has a predictor and point transform.
+        let lossless = match tag_reader
+            .find_tag(Tag::JPEGProc)?
+            .map(|proc| proc.into_u16())
+            .transpose()?
+        {
+            Some(14) => {
+                let first = |values: Option<Vec<u8>>| values.and_then(|v| v.first().copied());
+                let predictor = first(tag_reader.find_tag_uint_vec(Tag::JPEGLosslessPredictors)?);
+                let transform = first(tag_reader.find_tag_uint_vec(Tag::JPEGPointTransforms)?);
+                Some((predictor.unwrap_or(1), transform.unwrap_or(0)))
+            }
+            _ => None,
+        };
+
+        let mut jpeg = OldJpeg {
+            tables: Vec::new(),
+            components: Vec::new(),
+            huffman_tables: [0; 2],
+            lossless,
+            whole_image: None,
+        };
+
+        if let Some(offset) = tag_reader
+            .find_tag(Tag::JPEGInterchangeFormat)?
+            .map(|offset| offset.into_u64())
+            .transpose()?
+        {
+            // Some writers omit the length, in which case the stream extends to the chunk data.
+            let length = match tag_reader
+                .find_tag(Tag::JPEGInterchangeFormatLength)?
+                .map(|length| length.into_u64())
+                .transpose()?
+            {
+                Some(length) if length > 0 => length,
+                _ => chunk_ranges
+                    .map(|(offset, length)| offset.saturating_add(length))
+                    .max()
+                    .and_then(|end| end.checked_sub(offset))
+                    .filter(|&length| length > 0)
+                    .ok_or(TiffFormatError::InvalidTagValueType(
+                        Tag::JPEGInterchangeFormatLength,
+                    ))?,
+            };
+            let stream = read_range(tag_reader, offset, length)?;
+            // Without a length, the stream may run into the scan of the first chunk instead.
+            if jpeg.parse_stream(&stream)? == Some(dimensions) {
+                jpeg.whole_image = Some((offset, length));
+                return Ok(jpeg);
+            }
+        }
+
+        if jpeg.components.is_empty() {
+            jpeg.components = default_components(tag_reader, photometric_interpretation, samples)?;
+        }
+        if !jpeg.tables.windows(2).any(|marker| marker == [0xff, DQT]) {
+            jpeg.read_quantization_tables(tag_reader)?;
+        }
+        if jpeg.huffman_tables == [0; 2] {
+            jpeg.read_huffman_tables(tag_reader)?;
+        }
+        if !jpeg.tables.windows(2).any(|marker| marker == [0xff, DRI]) {
+            if let Some(interval) = tag_reader
+                .find_tag(Tag::JPEGRestartInterval)?
+                .map(|interval| interval.into_u16())
+                .transpose()?
+            {
+                jpeg.tables.extend_from_slice(&[0xff, DRI, 0, 4]);
+                jpeg.tables.extend_from_slice(&interval.to_be_bytes());
+            }
+        }
+
+        Ok(jpeg)
+    }
+
+    /// Collects the tables and components of an interchange stream. If the stream goes on with
+    /// image data, returns the dimensions of its frame.
+    fn parse_stream(&mut self, stream: &[u8]) -> TiffResult<Option<(u32, u32)>> {
+        if !stream.starts_with(&[0xff, SOI]) {
+            return Err(invalid("interchange stream does not start an image"));
+        }
+
+        let mut frame = None;
+        let mut rest = &stream[2..];
+        loop {
+            // Markers may be preceded by any number of fill bytes.
+            let start = rest
+                .iter()
+                .position(|&byte| byte != 0xff)
+                .unwrap_or(rest.len());
+            let marker = match rest.get(start) {
+                Some(&marker) if start > 0 => marker,
+                // Like libtiff, tolerate a stream that ends without a proper end of image.
+                _ => return Ok(None),
+            };
+            rest = &rest[start + 1..];
+
+            match marker {
+                SOS => return Ok(frame),
+                EOI => return Ok(None),
+                0xd0..=0xd7 | 0x01 => continue,
+                _ => {}
+            }
+
+            let segment = match rest {
+                [high, low, ..] => rest.get(..usize::from(u16::from_be_bytes([*high, *low]))),
+                _ => None,
+            };
+            let segment = match segment {
+                Some(segment) if segment.len() >= 2 => segment,
+                _ => return Ok(None),
+            };
+            rest = &rest[segment.len()..];
+
+            match marker {
+                DQT | DRI => {
+                    self.tables.extend_from_slice(&[0xff, marker]);
+                    self.tables.extend_from_slice(segment);
+                }
+                DHT => {
+                    self.count_huffman_tables(&segment[2..]);
+                    self.tables.extend_from_slice(&[0xff, marker]);
+                    self.tables.extend_from_slice(segment);
+                }
+                SOF0..=SOF3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                    if let [_, _, _, height_high, height_low, width_high, width_low, ..] = segment {
+                        let height = u16::from_be_bytes([*height_high, *height_low]);
+                        let width = u16::from_be_bytes([*width_high, *width_low]);
+                        frame = Some((u32::from(width), u32::from(height)));
+                    }
+                    self.components = segment
+                        .get(8..)
+                        .unwrap_or_default()
+                        .chunks_exact(3)
+                        .map(|component| [component[0], component[1], component[2]])
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn count_huffman_tables(&mut self, mut segment: &[u8]) {
+        while let [class_id, counts @ ..] = segment {
+            let values: usize = counts.iter().take(16).map(|&n| usize::from(n)).sum();
+            let class = usize::from(class_id >> 4).min(1);
+            self.huffman_tables[class] = self.huffman_tables[class].max((class_id & 0xf) + 1);
+            segment = counts.get(16 + values..).unwrap_or_default();
+        }
+    }
+
+    fn read_quantization_tables<R: Read + Seek>(
+        &mut self,
+        tag_reader: &mut TagReader<R>,
+    ) -> TiffResult<()> {
+        let offsets = match tag_reader.find_tag(Tag::JPEGQTables)? {
+            Some(offsets) => offsets.into_u64_vec()?,
+            None => return Ok(()),
+        };
+
+        for (id, &offset) in offsets.iter().enumerate().take(4) {
+            let table = read_range(tag_reader, offset, 64)?;
+            self.tables.extend_from_slice(&[0xff, DQT, 0, 67, id as u8]);
+            self.tables.extend_from_slice(&table);
+        }
+        Ok(())
+    }
+
+    fn read_huffman_tables<R: Read + Seek>(
+        &mut self,
+        tag_reader: &mut TagReader<R>,
+    ) -> TiffResult<()> {
+        for (class, tag) in [(0, Tag::JPEGDCTables), (1, Tag::JPEGACTables)] {
+            let offsets = match tag_reader.find_tag(tag)? {
+                Some(offsets) => offsets.into_u64_vec()?,
+                None => continue,
+            };
+
+            for (id, &offset) in offsets.iter().enumerate().take(4) {
+                let counts = read_range(tag_reader, offset, 16)?;
+                let length: u64 = counts.iter().map(|&n| u64::from(n)).sum();
+                let values = read_range(tag_reader, offset + 16, length)?;
+
+                let segment_length = u16::try_from(3 + counts.len() + values.len())?;
+                self.tables.extend_from_slice(&[0xff, DHT]);
+                self.tables.extend_from_slice(&segment_length.to_be_bytes());
+                self.tables.push(class << 4 | id as u8);
+                self.tables.extend_from_slice(&counts);
+                self.tables.extend_from_slice(&values);
+                self.huffman_tables[usize::from(class)] = id as u8 + 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Completes the data of a chunk to a stream that can be decoded on its own.
+    ///
+    /// The chunk holds the components in `components`, which index those of the image.
+    pub(crate) fn chunk_stream(
+        &self,
+        data: &[u8],
+        (width, height): (u32, u32),
+        components: std::ops::Range<usize>,
+    ) -> TiffResult<Vec<u8>> {
+        let mut stream = vec![0xff, SOI];
+        stream.extend_from_slice(&self.tables);
+
+        // Some writers store complete streams in each chunk, whose own tables take precedence.
+        if let Some(chunk) = data.strip_prefix(&[0xff, SOI]) {
+            stream.extend_from_slice(chunk);
+            return Ok(stream);
+        }
+
+        let planar = components.len() == 1 && self.components.len() > 1;
+        let components = self
+            .components
+            .get(components)
+            .ok_or_else(|| invalid("components do not match the samples"))?;
+        let too_large = || TiffError::LimitsExceeded;
+        let (width, height) = (
+            u16::try_from(width).map_err(|_| too_large())?,
+            u16::try_from(height).map_err(|_| too_large())?,
+        );
+
+        let marker = if self.lossless.is_some() { SOF3 } else { SOF0 };
+        stream.extend_from_slice(&[0xff, marker]);
+        stream.extend_from_slice(&(8 + 3 * components.len() as u16).to_be_bytes());
+        stream.push(8);
+        stream.extend_from_slice(&height.to_be_bytes());
+        stream.extend_from_slice(&width.to_be_bytes());
+        stream.push(components.len() as u8);
+        for &[id, sampling, quantization] in components {
+            // A plane of its own is not subsampled relative to other components.
+            let sampling = if planar { 0x11 } else { sampling };
+            stream.extend_from_slice(&[id, sampling, quantization]);
+        }
+
+        if !data.starts_with(&[0xff, SOS]) {
+            stream.extend_from_slice(&[0xff, SOS]);
+            stream.extend_from_slice(&(6 + 2 * components.len() as u16).to_be_bytes());
+            stream.push(components.len() as u8);
+            for (index, &[id, ..]) in components.iter().enumerate() {
+                // Like libtiff, assume that each component has its own tables if there are any.
+                let index = index as u8;
+                let dc = index.min(self.huffman_tables[0].saturating_sub(1));
+                let ac = index.min(self.huffman_tables[1].saturating_sub(1));
+                stream.extend_from_slice(&[id, dc << 4 | ac]);
+            }
+            match self.lossless {
+                Some((predictor, transform)) => {
+                    stream.extend_from_slice(&[predictor, 0, transform])
+                }
+                None => stream.extend_from_slice(&[0, 63, 0]),
+            }
+        }
+
+        stream.extend_from_slice(data);
+        if !data.ends_with(&[0xff, EOI]) {
+            stream.extend_from_slice(&[0xff, EOI]);
+        }
+        Ok(stream)
+    }
+}
+
+/// The components of an image whose interchange stream does not describe them.
+fn default_components<R: Read + Seek>(
+    tag_reader: &mut TagReader<R>,
+    photometric_interpretation: PhotometricInterpretation,
+    samples: u8,
+) -> TiffResult<Vec<[u8; 3]>> {
+    // Luma is sampled at a higher resolution than chroma, two times in each direction by default.
+    let luma_sampling = match (photometric_interpretation, samples) {
+        (PhotometricInterpretation::YCbCr, 3) => {
+            match tag_reader.find_tag_uint_vec::<u8>(Tag::YCbCrSubsampling)? {
+                Some(sampling) if sampling.len() == 2 => sampling[0] << 4 | sampling[1],
+                _ => 0x22,
+            }
+        }
+        _ => 0x11,
+    };
+    let quantization_tables = tag_reader
+        .find_tag(Tag::JPEGQTables)?
+        .map(|offsets| offsets.into_u64_vec())
+        .transpose()?
+        .map_or(1, |offsets| offsets.len().clamp(1, 4) as u8);
+
+    Ok((0..samples)
+        .map(|index| {
+            let sampling = if index == 0 { luma_sampling } else { 0x11 };
+            [index + 1, sampling, index.min(quantization_tables - 1)]
+        })
+        .collect())
+}
+
+fn read_range<R: Read + Seek>(
+    tag_reader: &mut TagReader<R>,
+    offset: u64,
+    length: u64,
+) -> TiffResult<Vec<u8>> {
+    let length = usize::try_from(length)?;
+    if length > tag_reader.limits.intermediate_buffer_size {
+        return Err(TiffError::LimitsExceeded);
+    }
+
+    let reader: &mut SmartReader<R> = tag_reader.reader;
+    reader.goto_offset(offset)?;
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn invalid(reason: &str) -> TiffError {
+    TiffError::FormatError(TiffFormatError::Format(format!(
+        "Old-style JPEG {}",
+        reason
+    )))
+}
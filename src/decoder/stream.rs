@@ -5,7 +5,7 @@ use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Take};
 use std::sync::Arc;
 
 /// Byte order of the TIFF file.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ByteOrder {
     /// little endian byte order
     LittleEndian,
@@ -13,6 +13,17 @@ pub enum ByteOrder {
     BigEndian,
 }
 
+impl ByteOrder {
+    /// The byte order of the target, which is the one the encoder writes.
+    pub fn native() -> ByteOrder {
+        if cfg!(target_endian = "little") {
+            ByteOrder::LittleEndian
+        } else {
+            ByteOrder::BigEndian
+        }
+    }
+}
+
 /// Reader that is aware of the byte order.
 pub trait EndianReader: Read {
     /// Byte order that should be adhered to
@@ -128,6 +139,13 @@ pub trait EndianReader: Read {
 
 pub type DeflateReader<R> = flate2::read::ZlibDecoder<R>;
 
+///
+/// ## Zstandard Reader
+///
+
+#[cfg(feature = "zstd")]
+pub type ZstdReader<'r, R> = zstd::stream::read::Decoder<'r, BufReader<R>>;
+
 ///
 /// ## LZW Reader
 ///
@@ -352,6 +370,32 @@ impl<R: Read> Read for PackBitsReader<R> {
     }
 }
 
+///
+/// ## Bit reversing Reader (for `FillOrder` 2)
+///
+
+/// Reader that reverses the bit order of every byte, turning LSB-first data into MSB-first data.
+pub struct ReverseBitsReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> ReverseBitsReader<R> {
+    /// Wraps a reader
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> Read for ReverseBitsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        for byte in &mut buf[..read] {
+            *byte = byte.reverse_bits();
+        }
+        Ok(read)
+    }
+}
+
 ///
 /// ## SmartReader Reader
 ///
@@ -432,4 +476,16 @@ mod test {
         ];
         assert_eq!(decoded, expected);
     }
+
+    #[test]
+    fn test_reverse_bits() {
+        let encoded = vec![0x01, 0x80, 0x0F, 0xC4];
+
+        let mut decoder = ReverseBitsReader::new(io::Cursor::new(encoded));
+
+        let mut decoded = Vec::new();
+        decoder.read_to_end(&mut decoded).unwrap();
+
+        assert_eq!(decoded, vec![0x80, 0x01, 0xF0, 0x23]);
+    }
 }
@@ -0,0 +1,124 @@
+//! Support for YCbCr images whose chroma is subsampled, and their conversion to RGB.
+//!
+//! Subsampled chunks are stored as a sequence of data units. Each unit covers a block of pixels
+//! and holds all their luma samples, followed by a single Cb and Cr sample for the whole block.
+use std::io::{Read, Seek};
+
+use super::tag_reader::TagReader;
+use crate::tags::{Tag, YCbCrPositioning};
+use crate::{TiffFormatError, TiffResult};
+
+/// The encoding of the samples of a YCbCr image.
+#[derive(Debug)]
+pub(crate) struct YCbCr {
+    /// The horizontal and vertical number of luma samples per chroma sample.
+    pub subsampling: (u8, u8),
+    /// The proportions of red, green and blue in luma.
+    coefficients: [f32; 3],
+    /// The codes of the black and white point of each component.
+    reference: [f32; 6],
+}
+
+impl YCbCr {
+    pub(crate) fn from_tags<R: Read + Seek>(tag_reader: &mut TagReader<R>) -> TiffResult<Self> {
+        let subsampling = match tag_reader.find_tag_uint_vec::<u8>(Tag::YCbCrSubsampling)? {
+            Some(sampling) => match sampling[..] {
+                [h @ (1 | 2 | 4), v @ (1 | 2 | 4)] => (h, v),
+                _ => return Err(TiffFormatError::InvalidTagValueType(Tag::YCbCrSubsampling).into()),
+            },
+            None => (2, 2),
+        };
+
+        // Chroma is replicated over the pixels of its data unit, which is the nearest sample for
+        // either positioning.
+        if let Some(positioning) = tag_reader.find_tag(Tag::YCbCrPositioning)? {
+            YCbCrPositioning::from_u16(positioning.into_u16()?)
+                .ok_or(TiffFormatError::InvalidTagValueType(Tag::YCbCrPositioning))?;
+        }
+
+        let coefficients = match tag_reader.find_tag(Tag::YCbCrCoefficients)? {
+            Some(value) => match value.into_f32_vec()?[..] {
+                [red, green, blue] if green != 0.0 => [red, green, blue],
+                _ => {
+                    return Err(TiffFormatError::InvalidTagValueType(Tag::YCbCrCoefficients).into())
+                }
+            },
+            None => [0.299, 0.587, 0.114],
+        };
+
+        let reference = match tag_reader.find_tag(Tag::ReferenceBlackWhite)? {
+            Some(value) => match value.into_f32_vec()?[..] {
+                [y_black, y_white, cb_black, cb_white, cr_black, cr_white]
+                    if y_black != y_white && cb_black != cb_white && cr_black != cr_white =>
+                {
+                    [y_black, y_white, cb_black, cb_white, cr_black, cr_white]
+                }
+                _ => {
+                    return Err(
+                        TiffFormatError::InvalidTagValueType(Tag::ReferenceBlackWhite).into(),
+                    )
+                }
+            },
+            None => [0.0, 255.0, 128.0, 255.0, 128.0, 255.0],
+        };
+
+        Ok(YCbCr {
+            subsampling,
+            coefficients,
+            reference,
+        })
+    }
+
+    /// Reads the data units of `rows` rows of 8-bit samples and expands them to a Y, Cb and Cr
+    /// sample for each of the `width` pixels in a row.
+    pub(crate) fn upsample(
+        &self,
+        mut reader: impl Read,
+        width: usize,
+        rows: usize,
+    ) -> TiffResult<Vec<u8>> {
+        let (h, v) = (
+            usize::from(self.subsampling.0),
+            usize::from(self.subsampling.1),
+        );
+        let units_across = (width + h - 1) / h;
+        let unit_len = h * v + 2;
+
+        let mut units = vec![0; units_across * unit_len];
+        let mut samples = vec![0; width * rows * 3];
+        for unit_row in 0..(rows + v - 1) / v {
+            reader.read_exact(&mut units)?;
+            for (unit_x, unit) in units.chunks_exact(unit_len).enumerate() {
+                let (cb, cr) = (unit[h * v], unit[h * v + 1]);
+                for (i, &y) in unit[..h * v].iter().enumerate() {
+                    let (x, row) = (unit_x * h + i % h, unit_row * v + i / h);
+                    // Units at the right and bottom edge extend past the chunk.
+                    if x < width && row < rows {
+                        let pixel = (row * width + x) * 3;
+                        samples[pixel..pixel + 3].copy_from_slice(&[y, cb, cr]);
+                    }
+                }
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Converts interleaved 8-bit Y, Cb and Cr samples to RGB in place.
+    pub(crate) fn to_rgb(&self, samples: &mut [u8]) {
+        let [luma_red, luma_green, luma_blue] = self.coefficients;
+        let [y_black, y_white, cb_black, cb_white, cr_black, cr_white] = self.reference;
+        let to_u8 = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+
+        for pixel in samples.chunks_exact_mut(3) {
+            let y = (f32::from(pixel[0]) - y_black) * 255.0 / (y_white - y_black);
+            let cb = (f32::from(pixel[1]) - cb_black) * 127.0 / (cb_white - cb_black);
+            let cr = (f32::from(pixel[2]) - cr_black) * 127.0 / (cr_white - cr_black);
+
+            let red = y + cr * (2.0 - 2.0 * luma_red);
+            let blue = y + cb * (2.0 - 2.0 * luma_blue);
+            let green = (y - luma_blue * blue - luma_red * red) / luma_green;
+            pixel.copy_from_slice(&[to_u8(red), to_u8(green), to_u8(blue)]);
+        }
+    }
+}
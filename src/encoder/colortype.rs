@@ -1,4 +1,6 @@
-use crate::tags::{PhotometricInterpretation, SampleFormat};
+use crate::tags::{ExtraSample, PhotometricInterpretation, SampleFormat};
+use half::f16;
+use std::marker::PhantomData;
 
 /// Trait for different colortypes that can be encoded.
 pub trait ColorType {
@@ -10,6 +12,8 @@ pub trait ColorType {
     const BITS_PER_SAMPLE: &'static [u16];
     /// The value of the tiff tag `SampleFormat`
     const SAMPLE_FORMAT: &'static [SampleFormat];
+    /// The value of the tiff tag `ExtraSamples`, which is not written when empty
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[];
 }
 
 pub struct Gray8;
@@ -60,6 +64,14 @@ impl ColorType for GrayI32 {
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Int];
 }
 
+pub struct Gray16Float;
+impl ColorType for Gray16Float {
+    type Inner = f16;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::BlackIsZero;
+    const BITS_PER_SAMPLE: &'static [u16] = &[16];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::IEEEFP];
+}
+
 pub struct Gray32Float;
 impl ColorType for Gray32Float {
     type Inner = f32;
@@ -92,6 +104,38 @@ impl ColorType for Gray64Float {
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::IEEEFP];
 }
 
+pub struct ComplexI16;
+impl ColorType for ComplexI16 {
+    type Inner = [i16; 2];
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::BlackIsZero;
+    const BITS_PER_SAMPLE: &'static [u16] = &[32];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::ComplexInt];
+}
+
+pub struct ComplexI32;
+impl ColorType for ComplexI32 {
+    type Inner = [i32; 2];
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::BlackIsZero;
+    const BITS_PER_SAMPLE: &'static [u16] = &[64];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::ComplexInt];
+}
+
+pub struct Complex32Float;
+impl ColorType for Complex32Float {
+    type Inner = [f32; 2];
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::BlackIsZero;
+    const BITS_PER_SAMPLE: &'static [u16] = &[64];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::ComplexIEEEFP];
+}
+
+pub struct Complex64Float;
+impl ColorType for Complex64Float {
+    type Inner = [f64; 2];
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::BlackIsZero;
+    const BITS_PER_SAMPLE: &'static [u16] = &[128];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::ComplexIEEEFP];
+}
+
 pub struct RGB8;
 impl ColorType for RGB8 {
     type Inner = u8;
@@ -116,6 +160,14 @@ impl ColorType for RGB32 {
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 3];
 }
 
+pub struct RGB16Float;
+impl ColorType for RGB16Float {
+    type Inner = f16;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
+    const BITS_PER_SAMPLE: &'static [u16] = &[16, 16, 16];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::IEEEFP; 3];
+}
+
 pub struct RGB32Float;
 impl ColorType for RGB32Float {
     type Inner = f32;
@@ -146,6 +198,7 @@ impl ColorType for RGBA8 {
     const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
     const BITS_PER_SAMPLE: &'static [u16] = &[8, 8, 8, 8];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
 }
 
 pub struct RGBA16;
@@ -154,6 +207,7 @@ impl ColorType for RGBA16 {
     const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
     const BITS_PER_SAMPLE: &'static [u16] = &[16, 16, 16, 16];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
 }
 
 pub struct RGBA32;
@@ -162,6 +216,16 @@ impl ColorType for RGBA32 {
     const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
     const BITS_PER_SAMPLE: &'static [u16] = &[32, 32, 32, 32];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
+}
+
+pub struct RGBA16Float;
+impl ColorType for RGBA16Float {
+    type Inner = f16;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
+    const BITS_PER_SAMPLE: &'static [u16] = &[16, 16, 16, 16];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::IEEEFP; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
 }
 
 pub struct RGBA32Float;
@@ -170,6 +234,7 @@ impl ColorType for RGBA32Float {
     const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
     const BITS_PER_SAMPLE: &'static [u16] = &[32, 32, 32, 32];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::IEEEFP; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
 }
 
 pub struct RGBA64;
@@ -178,6 +243,7 @@ impl ColorType for RGBA64 {
     const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
     const BITS_PER_SAMPLE: &'static [u16] = &[64, 64, 64, 64];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
 }
 
 pub struct RGBA64Float;
@@ -186,6 +252,7 @@ impl ColorType for RGBA64Float {
     const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::RGB;
     const BITS_PER_SAMPLE: &'static [u16] = &[64, 64, 64, 64];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::IEEEFP; 4];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = &[ExtraSample::UnassociatedAlpha];
 }
 
 pub struct CMYK8;
@@ -243,3 +310,37 @@ impl ColorType for YCbCr8 {
     const BITS_PER_SAMPLE: &'static [u16] = &[8, 8, 8];
     const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 3];
 }
+
+/// CIE L*a*b* samples where a* and b* are offset by half the range, as in ICC profiles.
+pub struct Lab8;
+impl ColorType for Lab8 {
+    type Inner = u8;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::ICCLab;
+    const BITS_PER_SAMPLE: &'static [u16] = &[8, 8, 8];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 3];
+}
+
+/// CIE L*a*b* samples where a* and b* are offset by half the range, as in ICC profiles.
+pub struct Lab16;
+impl ColorType for Lab16 {
+    type Inner = u16;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::ICCLab;
+    const BITS_PER_SAMPLE: &'static [u16] = &[16, 16, 16];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[SampleFormat::Uint; 3];
+}
+
+/// An image with `N` samples per pixel that share the sample type of the gray colortype `C`,
+/// for example `MultiBand<Gray16, 8>` for eight 16-bit bands.
+///
+/// The first band is written as the gray channel and all others as unspecified extra samples.
+pub struct MultiBand<C, const N: usize>(PhantomData<C>);
+impl<C: ColorType, const N: usize> ColorType for MultiBand<C, N> {
+    type Inner = C::Inner;
+    const TIFF_VALUE: PhotometricInterpretation = PhotometricInterpretation::BlackIsZero;
+    const BITS_PER_SAMPLE: &'static [u16] = &[C::BITS_PER_SAMPLE[0]; N];
+    const SAMPLE_FORMAT: &'static [SampleFormat] = &[C::SAMPLE_FORMAT[0]; N];
+    const EXTRA_SAMPLES: &'static [ExtraSample] = match &[ExtraSample::Unspecified; N] as &[_] {
+        [_, extra @ ..] => extra,
+        [] => &[],
+    };
+}
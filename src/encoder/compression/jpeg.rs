@@ -0,0 +1,225 @@
+use crate::{encoder::compression::*, tags::CompressionMethod};
+use jpeg_encoder::{ColorType as JpegColorType, Encoder as JpegEncoder, SamplingFactor};
+use std::convert::TryFrom;
+use std::io::Write;
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const DQT: u8 = 0xdb;
+const DHT: u8 = 0xc4;
+const COM: u8 = 0xfe;
+
+/// The JPEG algorithm used to compress image data in TIFF files.
+///
+/// Every chunk is written as an abbreviated JPEG stream, while the quantization and Huffman
+/// tables shared by all of them are stored once in the `JPEGTables` tag. Supports gray and RGB
+/// samples of 8 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jpeg {
+    quality: u8,
+    ycbcr: bool,
+    layout: Option<ChunkLayout>,
+}
+
+impl Jpeg {
+    /// Create a new JPEG compressor with a specific quality.
+    ///
+    /// The quality ranges from 1 for the smallest size to 100 for the highest quality.
+    pub fn with_quality(quality: u8) -> Self {
+        Self {
+            quality: quality.clamp(1, 100),
+            ycbcr: false,
+            layout: None,
+        }
+    }
+
+    /// Convert RGB samples to YCbCr and subsample their chroma by two in both directions, which
+    /// compresses much better. The image is then stored with a YCbCr photometric interpretation.
+    pub fn with_ycbcr(mut self, ycbcr: bool) -> Self {
+        self.ycbcr = ycbcr;
+        self
+    }
+
+    pub(crate) fn ycbcr(&self) -> bool {
+        self.ycbcr
+    }
+
+    /// The number of rows and columns of the blocks that chunks with this many samples per pixel
+    /// consist of: 16 if the chroma is subsampled, and 8 otherwise.
+    pub(crate) fn block_size(&self, samples: usize) -> u64 {
+        if self.ycbcr && samples == 3 {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub(crate) fn set_chunk_layout(&mut self, layout: ChunkLayout) {
+        self.layout = Some(layout);
+    }
+
+    /// The value of the `JPEGTables` tag for chunks with this many samples per pixel.
+    pub(crate) fn tables(&self, samples: usize) -> io::Result<Vec<u8>> {
+        // The tables only depend on the settings, so they are taken from an arbitrary image.
+        let stream = self.encode(&vec![0; 16 * 16 * samples], 16, 16, samples)?;
+        Ok(split_stream(&stream)?.0)
+    }
+
+    fn encode(
+        &self,
+        bytes: &[u8],
+        width: usize,
+        height: usize,
+        samples: usize,
+    ) -> io::Result<Vec<u8>> {
+        let (color_type, sampling) = match samples {
+            1 => (JpegColorType::Luma, SamplingFactor::F_1_1),
+            3 if self.ycbcr => (JpegColorType::Rgb, SamplingFactor::F_2_2),
+            // Encoding the samples as if they were YCbCr keeps the RGB colors untransformed.
+            3 => (JpegColorType::Ycbcr, SamplingFactor::F_1_1),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "JPEG only supports gray and RGB samples of 8 bits",
+                ))
+            }
+        };
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "JPEG chunks are limited to 65535 pixels in each direction",
+                ))
+            }
+        };
+
+        let mut stream = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut stream, self.quality);
+        encoder.set_sampling_factor(sampling);
+        encoder
+            .encode(bytes, width, height, color_type)
+            .map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("JPEG encoding failed: {:?}", err),
+                )
+            })?;
+        Ok(stream)
+    }
+}
+
+impl Default for Jpeg {
+    fn default() -> Self {
+        Self::with_quality(75)
+    }
+}
+
+impl Compression for Jpeg {
+    const COMPRESSION_METHOD: CompressionMethod = CompressionMethod::ModernJPEG;
+
+    fn get_algorithm(&self) -> Compressor {
+        Compressor::Jpeg(*self)
+    }
+}
+
+impl CompressionAlgorithm for Jpeg {
+    fn write_to<W: Write>(&mut self, writer: &mut W, bytes: &[u8]) -> Result<u64, io::Error> {
+        let layout = self.layout.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "JPEG compresses whole chunks of an image",
+            )
+        })?;
+        if layout.bits_per_sample != 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "JPEG only supports gray and RGB samples of 8 bits",
+            ));
+        }
+        if bytes.len() != layout.width * layout.height * layout.samples {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Chunk does not match its dimensions",
+            ));
+        }
+
+        let stream = self.encode(bytes, layout.width, layout.height, layout.samples)?;
+        let (_, abbreviated) = split_stream(&stream)?;
+        writer.write_all(&abbreviated)?;
+        Ok(abbreviated.len() as u64)
+    }
+}
+
+/// Splits a complete JPEG stream into its quantization and Huffman tables, and an abbreviated
+/// stream of the image without them. Application and comment segments are dropped.
+fn split_stream(stream: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed JPEG stream");
+
+    let mut tables = vec![0xff, SOI];
+    let mut image = vec![0xff, SOI];
+    let mut rest = stream.strip_prefix(&[0xff, SOI]).ok_or_else(invalid)?;
+    loop {
+        let marker = match rest {
+            [0xff, marker, ..] => *marker,
+            _ => return Err(invalid()),
+        };
+        if marker == SOS {
+            // The entropy coded data follows, up to and including the end of image.
+            image.extend_from_slice(rest);
+            break;
+        }
+
+        let length = match rest {
+            [_, _, high, low, ..] => 2 + usize::from(u16::from_be_bytes([*high, *low])),
+            _ => return Err(invalid()),
+        };
+        let segment = rest.get(..length).ok_or_else(invalid)?;
+        match marker {
+            DQT | DHT => tables.extend_from_slice(segment),
+            0xe0..=0xef | COM => {}
+            _ => image.extend_from_slice(segment),
+        }
+        rest = &rest[length..];
+    }
+
+    tables.extend_from_slice(&[0xff, EOI]);
+    Ok((tables, image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::SampleFormat;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_jpeg_abbreviated_stream() {
+        let bytes: Vec<u8> = (0..32 * 16 * 3).map(|i| (i / 3 % 32 * 8) as u8).collect();
+        let tables = Jpeg::default().with_ycbcr(true).tables(3).unwrap();
+
+        let mut jpeg = Jpeg::default().with_ycbcr(true);
+        jpeg.set_chunk_layout(ChunkLayout {
+            width: 32,
+            height: 16,
+            samples: 3,
+            bits_per_sample: 8,
+            sample_format: SampleFormat::Uint,
+        });
+        let mut compressed_data = Vec::<u8>::new();
+        let mut writer = Cursor::new(&mut compressed_data);
+        let byte_count = jpeg.write_to(&mut writer, &bytes).unwrap();
+        assert_eq!(byte_count, compressed_data.len() as u64);
+
+        // Neither stream is complete on its own, but together they are.
+        assert!(::jpeg::Decoder::new(&compressed_data[..]).decode().is_err());
+        let mut stream = tables[..tables.len() - 2].to_vec();
+        stream.extend_from_slice(&compressed_data[2..]);
+        let decoded = ::jpeg::Decoder::new(&stream[..]).decode().unwrap();
+        assert_eq!(decoded.len(), bytes.len());
+        for (decoded, sample) in decoded.iter().zip(&bytes) {
+            assert!((i16::from(*decoded) - i16::from(*sample)).abs() <= 16);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    encoder::compression::*,
+    lerc::{self, DataType},
+    tags::{CompressionMethod, LercCompression},
+};
+use flate2::{write::ZlibEncoder, Compression as FlateCompression};
+use std::io::Write;
+
+/// The LERC (Limited Error Raster Compression) algorithm used to compress image data in TIFF files.
+///
+/// Each chunk is compressed such that every sample stays within a maximum error of its value,
+/// which makes it lossless for a maximum error of zero. Supports 8, 16 and 32 bit integer samples
+/// and 32 and 64 bit floating point samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lerc {
+    max_error: f64,
+    compression: LercCompression,
+    layout: Option<ChunkLayout>,
+}
+
+impl Lerc {
+    /// Create a new LERC compressor that keeps every sample within `max_error` of its value.
+    ///
+    /// Integer samples are quantized in whole steps, so their maximum error is rounded down.
+    pub fn with_max_error(max_error: f64) -> Self {
+        Self {
+            max_error,
+            compression: LercCompression::None,
+            layout: None,
+        }
+    }
+
+    /// Additionally compress the LERC data of each chunk with Deflate or Zstandard.
+    pub fn with_compression(mut self, compression: LercCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// The values of the `LercParameters` tag, which are the LERC version and the additional
+    /// compression.
+    pub(crate) fn parameters(&self) -> [u32; 2] {
+        [4, u32::from(self.compression.to_u16())]
+    }
+
+    pub(crate) fn set_chunk_layout(&mut self, layout: ChunkLayout) {
+        self.layout = Some(layout);
+    }
+}
+
+impl Default for Lerc {
+    fn default() -> Self {
+        Self::with_max_error(0.0)
+    }
+}
+
+impl Compression for Lerc {
+    const COMPRESSION_METHOD: CompressionMethod = CompressionMethod::Lerc;
+
+    fn get_algorithm(&self) -> Compressor {
+        Compressor::Lerc(*self)
+    }
+}
+
+impl CompressionAlgorithm for Lerc {
+    fn write_to<W: Write>(&mut self, writer: &mut W, bytes: &[u8]) -> Result<u64, io::Error> {
+        let layout = self.layout.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "LERC compresses whole chunks of an image",
+            )
+        })?;
+        let data_type = DataType::from_sample(layout.sample_format, layout.bits_per_sample)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Sample type is unsupported by LERC",
+                )
+            })?;
+
+        let big_endian = cfg!(target_endian = "big");
+        let values: Vec<f64> = bytes
+            .chunks_exact(data_type.byte_len())
+            .map(|sample| data_type.read(sample, big_endian))
+            .collect();
+        let blob = lerc::encode(
+            &values,
+            layout.width,
+            layout.height,
+            layout.samples,
+            data_type,
+            self.max_error,
+        )?;
+
+        let blob = match self.compression {
+            LercCompression::None => blob,
+            LercCompression::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), FlateCompression::default());
+                encoder.write_all(&blob)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "zstd")]
+            LercCompression::Zstd => {
+                ::zstd::bulk::compress(&blob, ::zstd::DEFAULT_COMPRESSION_LEVEL)?
+            }
+            #[cfg(not(feature = "zstd"))]
+            LercCompression::Zstd => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "ZSTD compression of LERC requires the zstd feature",
+                ))
+            }
+        };
+        writer.write_all(&blob)?;
+        Ok(blob.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::compression::tests::TEST_DATA;
+    use crate::tags::SampleFormat;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_lerc_max_error() {
+        let samples: Vec<f32> = (0..300).map(|i| (i as f32 * 0.37).sin() * 100.0).collect();
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+
+        let mut lerc = Lerc::with_max_error(0.25);
+        lerc.set_chunk_layout(ChunkLayout {
+            width: 20,
+            height: 15,
+            samples: 1,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::IEEEFP,
+        });
+        let mut compressed_data = Vec::<u8>::new();
+        let mut writer = Cursor::new(&mut compressed_data);
+        let byte_count = lerc.write_to(&mut writer, &bytes).unwrap();
+        assert_eq!(byte_count, compressed_data.len() as u64);
+        assert!(compressed_data.len() < bytes.len() / 2);
+
+        let raster = lerc::decode(&compressed_data, samples.len()).unwrap();
+        for (&decoded, &sample) in raster.values.iter().zip(&samples) {
+            assert!((decoded - f64::from(sample)).abs() <= 0.25 + 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_lerc_without_layout() {
+        let mut writer = Cursor::new(Vec::new());
+        assert!(Lerc::default().write_to(&mut writer, TEST_DATA).is_err());
+    }
+}
@@ -1,15 +1,27 @@
-use crate::tags::CompressionMethod;
+use crate::tags::{CompressionMethod, SampleFormat};
 use std::io::{self, Write};
 
 mod deflate;
+mod jpeg;
+mod lerc;
 mod lzw;
 mod packbits;
 mod uncompressed;
+#[cfg(feature = "webp")]
+mod webp;
+#[cfg(feature = "zstd")]
+mod zstd;
 
 pub use self::deflate::{Deflate, DeflateLevel};
+pub use self::jpeg::Jpeg;
+pub use self::lerc::Lerc;
 pub use self::lzw::Lzw;
 pub use self::packbits::Packbits;
 pub use self::uncompressed::Uncompressed;
+#[cfg(feature = "webp")]
+pub use self::webp::WebP;
+#[cfg(feature = "zstd")]
+pub use self::zstd::Zstd;
 
 /// An algorithm used for compression
 pub trait CompressionAlgorithm {
@@ -29,12 +41,22 @@ pub trait Compression: CompressionAlgorithm {
 }
 
 /// An enum to store each compression algorithm.
+///
+/// The list of variants grows with the supported compressions, and some of them depend on
+/// features. Matching against this exhaustively is not covered by interface stability guarantees.
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum Compressor {
     Uncompressed(Uncompressed),
     Lzw(Lzw),
     Deflate(Deflate),
     Packbits(Packbits),
+    #[cfg(feature = "zstd")]
+    Zstd(Zstd),
+    Lerc(Lerc),
+    #[cfg(feature = "webp")]
+    WebP(WebP),
+    Jpeg(Jpeg),
 }
 
 impl Default for Compressor {
@@ -51,10 +73,39 @@ impl CompressionAlgorithm for Compressor {
             Compressor::Lzw(algorithm) => algorithm.write_to(writer, bytes),
             Compressor::Deflate(algorithm) => algorithm.write_to(writer, bytes),
             Compressor::Packbits(algorithm) => algorithm.write_to(writer, bytes),
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd(algorithm) => algorithm.write_to(writer, bytes),
+            Compressor::Lerc(algorithm) => algorithm.write_to(writer, bytes),
+            #[cfg(feature = "webp")]
+            Compressor::WebP(algorithm) => algorithm.write_to(writer, bytes),
+            Compressor::Jpeg(algorithm) => algorithm.write_to(writer, bytes),
         }
     }
 }
 
+impl Compressor {
+    /// Describes the chunk that is compressed next, for algorithms that need more than its bytes.
+    pub(crate) fn set_chunk_layout(&mut self, layout: ChunkLayout) {
+        match self {
+            Compressor::Lerc(algorithm) => algorithm.set_chunk_layout(layout),
+            #[cfg(feature = "webp")]
+            Compressor::WebP(algorithm) => algorithm.set_chunk_layout(layout),
+            Compressor::Jpeg(algorithm) => algorithm.set_chunk_layout(layout),
+            _ => {}
+        }
+    }
+}
+
+/// The dimensions and sample type of a chunk, for algorithms that compress it as a raster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChunkLayout {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub bits_per_sample: u16,
+    pub sample_format: SampleFormat,
+}
+
 #[cfg(test)]
 mod tests {
     pub const TEST_DATA: &'static [u8] =
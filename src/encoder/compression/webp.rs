@@ -0,0 +1,145 @@
+use crate::{encoder::compression::*, tags::CompressionMethod};
+use std::io::Write;
+
+/// The WebP algorithm used to compress image data in TIFF files.
+///
+/// Each chunk is compressed as a separate WebP image, which requires RGB or RGBA samples of 8
+/// bits. Images are compressed losslessly by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WebP {
+    lossless: bool,
+    quality: f32,
+    layout: Option<ChunkLayout>,
+}
+
+impl WebP {
+    /// Create a new WebP compressor that does not lose any information.
+    pub fn lossless() -> Self {
+        Self {
+            lossless: true,
+            quality: 75.0,
+            layout: None,
+        }
+    }
+
+    /// Create a new lossy WebP compressor with a specific quality.
+    ///
+    /// The quality ranges from 0 for the smallest size to 100 for the highest quality.
+    pub fn with_quality(quality: f32) -> Self {
+        Self {
+            lossless: false,
+            quality: quality.clamp(0.0, 100.0),
+            layout: None,
+        }
+    }
+
+    pub(crate) fn set_chunk_layout(&mut self, layout: ChunkLayout) {
+        self.layout = Some(layout);
+    }
+}
+
+impl Default for WebP {
+    fn default() -> Self {
+        Self::lossless()
+    }
+}
+
+impl Compression for WebP {
+    const COMPRESSION_METHOD: CompressionMethod = CompressionMethod::WebP;
+
+    fn get_algorithm(&self) -> Compressor {
+        Compressor::WebP(*self)
+    }
+}
+
+impl CompressionAlgorithm for WebP {
+    fn write_to<W: Write>(&mut self, writer: &mut W, bytes: &[u8]) -> Result<u64, io::Error> {
+        let layout = self.layout.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "WebP compresses whole chunks of an image",
+            )
+        })?;
+        let pixel_layout = match (layout.samples, layout.bits_per_sample) {
+            (3, 8) => ::webp::PixelLayout::Rgb,
+            (4, 8) => ::webp::PixelLayout::Rgba,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "WebP only supports RGB and RGBA samples of 8 bits",
+                ))
+            }
+        };
+        if bytes.len() != layout.width * layout.height * layout.samples {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Chunk does not match its dimensions",
+            ));
+        }
+
+        let encoder = ::webp::Encoder::new(
+            bytes,
+            pixel_layout,
+            layout.width as u32,
+            layout.height as u32,
+        );
+        let mut config = ::webp::WebPConfig::new()
+            .map_err(|()| io::Error::new(io::ErrorKind::Other, "WebP configuration is invalid"))?;
+        config.lossless = i32::from(self.lossless);
+        config.quality = self.quality;
+        // Keep the color of fully transparent pixels, which is otherwise altered to compress better.
+        config.exact = i32::from(self.lossless);
+        let compressed = encoder.encode_advanced(&config).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("WebP encoding failed: {:?}", err),
+            )
+        })?;
+        writer.write_all(&compressed)?;
+        Ok(compressed.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::SampleFormat;
+    use std::io::Cursor;
+
+    fn rgb_layout() -> ChunkLayout {
+        ChunkLayout {
+            width: 16,
+            height: 8,
+            samples: 3,
+            bits_per_sample: 8,
+            sample_format: SampleFormat::Uint,
+        }
+    }
+
+    #[test]
+    fn test_webp_lossless() {
+        let bytes: Vec<u8> = (0..16 * 8 * 3).map(|i| (i * 7 % 256) as u8).collect();
+
+        let mut webp = WebP::lossless();
+        webp.set_chunk_layout(rgb_layout());
+        let mut compressed_data = Vec::<u8>::new();
+        let mut writer = Cursor::new(&mut compressed_data);
+        let byte_count = webp.write_to(&mut writer, &bytes).unwrap();
+        assert_eq!(byte_count, compressed_data.len() as u64);
+
+        let image = ::webp::Decoder::new(&compressed_data).decode().unwrap();
+        assert_eq!((image.width(), image.height()), (16, 8));
+        assert_eq!(&*image, &bytes[..]);
+    }
+
+    #[test]
+    fn test_webp_unsupported_samples() {
+        let mut webp = WebP::with_quality(90.0);
+        webp.set_chunk_layout(ChunkLayout {
+            samples: 1,
+            ..rgb_layout()
+        });
+        let mut writer = Cursor::new(Vec::new());
+        assert!(webp.write_to(&mut writer, &[0; 16 * 8]).is_err());
+    }
+}
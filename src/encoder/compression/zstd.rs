@@ -0,0 +1,65 @@
+use crate::{encoder::compression::*, tags::CompressionMethod};
+use std::io::Write;
+
+/// The Zstandard algorithm used to compress image data in TIFF files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zstd {
+    level: i32,
+}
+
+impl Zstd {
+    /// Create a new Zstandard compressor with a specific level of compression.
+    ///
+    /// Levels range from 1 to 22, higher levels trade compression speed for ratio. Negative
+    /// levels favor speed even further, and 0 selects the library default.
+    pub fn with_level(level: i32) -> Self {
+        let range = ::zstd::compression_level_range();
+        Self {
+            level: level.clamp(*range.start(), *range.end()),
+        }
+    }
+}
+
+impl Default for Zstd {
+    fn default() -> Self {
+        Self::with_level(::zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+}
+
+impl Compression for Zstd {
+    const COMPRESSION_METHOD: CompressionMethod = CompressionMethod::ZSTD;
+
+    fn get_algorithm(&self) -> Compressor {
+        Compressor::Zstd(*self)
+    }
+}
+
+impl CompressionAlgorithm for Zstd {
+    fn write_to<W: Write>(&mut self, writer: &mut W, bytes: &[u8]) -> Result<u64, io::Error> {
+        let compressed = ::zstd::bulk::compress(bytes, self.level)?;
+        writer.write_all(&compressed)?;
+        Ok(compressed.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::compression::tests::TEST_DATA;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_zstd() {
+        for level in [-5, 1, 3, 19] {
+            let mut compressed_data = Vec::<u8>::new();
+            let mut writer = Cursor::new(&mut compressed_data);
+            let byte_count = Zstd::with_level(level)
+                .write_to(&mut writer, TEST_DATA)
+                .unwrap();
+            assert_eq!(byte_count, compressed_data.len() as u64);
+
+            let decompressed_data = ::zstd::stream::decode_all(&compressed_data[..]).unwrap();
+            assert_eq!(TEST_DATA, decompressed_data.as_slice());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::mem;
+
+use super::writer::TiffWriter;
+use super::{DirectoryEncoder, DirectoryEntry, TiffKind, TiffKindBig, TiffKindStandard, TiffValue};
+use crate::decoder::Decoder;
+use crate::error::TiffResult;
+use crate::tags::Tag;
+
+/// Editor for the tags of a directory in an existing Tiff or BigTiff file.
+///
+/// The edited directory is written at the end of the file and replaces the original one in the
+/// chain of directories, so that the image data and any other directory are left untouched.
+/// Nothing is written until `finish` is called.
+///
+/// Only files in native byte order can be edited, as that is the one the encoder writes. Other
+/// files are rejected before anything is written.
+///
+/// # Examples
+/// ```
+/// # extern crate tiff;
+/// # fn main() {
+/// # let mut file = std::io::Cursor::new(Vec::new());
+/// # let image_data = vec![0; 100*100*3];
+/// use tiff::encoder::*;
+/// use tiff::tags::Tag;
+///
+/// # TiffEncoder::new(&mut file).unwrap().write_image::<colortype::RGB8>(100, 100, &image_data).unwrap();
+/// // edit the tags of the first directory
+/// let mut editor = TagEditor::new(&mut file, 0).unwrap();
+/// editor.write_tag(Tag::Software, "image-tiff").unwrap();
+/// editor.remove_tag(Tag::Artist);
+/// editor.finish().unwrap();
+/// # }
+/// ```
+pub struct TagEditor<W: Write + Seek, K: TiffKind = TiffKindStandard> {
+    writer: TiffWriter<W>,
+    ifd_pointer_pos: u64,
+    next_ifd: u64,
+    ifd: BTreeMap<u16, DirectoryEntry<K::OffsetType>>,
+    kind: PhantomData<K>,
+}
+
+/// Constructor functions to edit standard Tiff files.
+impl<W: Read + Write + Seek> TagEditor<W> {
+    /// Creates an editor for the directory at `ifd_index` of a standard Tiff file.
+    pub fn new(writer: W, ifd_index: usize) -> TiffResult<TagEditor<W, TiffKindStandard>> {
+        TagEditor::new_generic(writer, ifd_index)
+    }
+}
+
+/// Constructor functions to edit BigTiff files.
+impl<W: Read + Write + Seek> TagEditor<W, TiffKindBig> {
+    /// Creates an editor for the directory at `ifd_index` of a BigTiff file.
+    pub fn new_big(writer: W, ifd_index: usize) -> TiffResult<Self> {
+        TagEditor::new_generic(writer, ifd_index)
+    }
+}
+
+impl<W: Read + Write + Seek, K: TiffKind> TagEditor<W, K> {
+    /// Creates an editor for the directory at `ifd_index` of a Tiff or BigTiff file, inferred
+    /// from the return type.
+    ///
+    /// This errors with [`UsageError::InvalidTiffKind`][crate::UsageError::InvalidTiffKind] if
+    /// the kind of the file differs from the one of the editor, and with
+    /// [`TiffUnsupportedError::NonNativeByteOrder`][crate::TiffUnsupportedError::NonNativeByteOrder]
+    /// if the file is not in native byte order.
+    pub fn new_generic(mut writer: W, ifd_index: usize) -> TiffResult<Self> {
+        let bigtiff = mem::size_of::<K::OffsetType>() == 8;
+        writer.seek(SeekFrom::Start(0))?;
+        let (dir, next_ifd, ifd_pointer_pos) =
+            Decoder::new(&mut writer)?.raw_ifd(ifd_index, bigtiff)?;
+
+        // Entries are kept as they are, including offsets to values outside of the directory and
+        // entries of a type unknown to the decoder.
+        let mut ifd = BTreeMap::new();
+        for (tag, data_type, count, data) in dir {
+            ifd.insert(
+                tag,
+                DirectoryEntry {
+                    data_type,
+                    count: K::convert_offset(count)?,
+                    data,
+                },
+            );
+        }
+
+        let end = writer.seek(SeekFrom::End(0))?;
+        let mut writer = TiffWriter::new(writer);
+        writer.goto_offset(end)?;
+        Ok(TagEditor {
+            writer,
+            ifd_pointer_pos,
+            next_ifd,
+            ifd,
+            kind: PhantomData,
+        })
+    }
+}
+
+impl<W: Write + Seek, K: TiffKind> TagEditor<W, K> {
+    /// Write a single ifd tag, replacing any previous value.
+    pub fn write_tag<T: TiffValue>(&mut self, tag: Tag, value: T) -> TiffResult<()> {
+        self.ifd.insert(tag.to_u16(), DirectoryEntry::new(value)?);
+
+        Ok(())
+    }
+
+    /// Remove a tag from the directory, returning whether it was present.
+    pub fn remove_tag(&mut self, tag: Tag) -> bool {
+        self.ifd.remove(&tag.to_u16()).is_some()
+    }
+
+    /// Write out the edited ifd directory and link it in place of the original one.
+    pub fn finish(mut self) -> TiffResult<()> {
+        self.writer.set_ifd_pointer_pos(self.ifd_pointer_pos);
+        let mut encoder = DirectoryEncoder::<W, K>::new(&mut self.writer)?;
+        encoder.ifd = mem::take(&mut self.ifd);
+        encoder.next_ifd = self.next_ifd;
+        encoder.finish()
+    }
+}
@@ -1,31 +1,44 @@
-use futures::{Stream, StreamExt, lock::Mutex,  future::join_all};
+#[cfg(feature = "async")]
+use futures::{Stream, StreamExt};
+#[cfg(feature = "async")]
+use std::collections::VecDeque;
 pub use tiff_value::*;
 
 use std::{
     cmp,
     collections::BTreeMap,
     convert::{TryFrom, TryInto},
-    io::{self, Seek, Write},
+    collections::HashSet,
+    error::Error,
+    io::{self, Read, Seek, SeekFrom, Write},
     marker::PhantomData,
     mem,
-    num::TryFromIntError, sync:: Arc, 
+    num::TryFromIntError,
 };
 
-
 use crate::{
-    decoder::ChunkType,
+    decoder::{ByteOrder, ChunkType},
     error::TiffResult,
-    tags::{CompressionMethod, ResolutionUnit, Tag, SubfileType, NewSubfileType},
-    TiffError, TiffFormatError,
+    progress::{Progress, ProgressCallback},
+    tags::{CompressionMethod, PhotometricInterpretation, ResolutionUnit, Tag, SubfileType, NewSubfileType},
+    ChunkError, ChunkErrorKind, TiffError, TiffFormatError, TiffUnsupportedError, UsageError,
 };
 
 pub mod colortype;
 pub mod compression;
+mod editor;
+mod pool;
 mod tiff_value;
+mod transcoder;
 mod writer;
 
+pub use self::editor::TagEditor;
+pub use self::transcoder::Transcoder;
+pub use self::writer::StreamingWriter;
+
 use self::colortype::*;
 use self::compression::*;
+use self::pool::CompressionPool;
 use self::writer::*;
 
 
@@ -68,6 +81,49 @@ impl<W: Write + Seek> TiffEncoder<W> {
     pub fn new(writer: W) -> TiffResult<TiffEncoder<W, TiffKindStandard>> {
         TiffEncoder::new_generic(writer)
     }
+
+    /// Creates an encoder that appends directories to an existing standard Tiff file.
+    ///
+    /// The kind of the file has to be known in advance: a BigTiff file errors with
+    /// [`UsageError::InvalidTiffKind`] and has to be opened with
+    /// [`append_big`][TiffEncoder::append_big] instead. A file that is not in native byte order
+    /// errors with [`TiffUnsupportedError::NonNativeByteOrder`], as the encoder can not write it.
+    /// See [`append_generic`][TiffEncoder::append_generic].
+    pub fn append(writer: W) -> TiffResult<TiffEncoder<W, TiffKindStandard>>
+    where
+        W: Read,
+    {
+        TiffEncoder::append_generic(writer)
+    }
+}
+
+/// Constructor functions to create standard Tiff files on sinks that can not seek.
+impl<W: Write> TiffEncoder<StreamingWriter<W>> {
+    /// Creates a new encoder for standard Tiff files that only writes to `writer`.
+    ///
+    /// Memory use is not bounded: the whole data of an image is buffered until its directory is
+    /// written, so each image must fit in memory. Call [`into_inner`][TiffEncoder::into_inner]
+    /// when done to write out the rest.
+    pub fn new_streaming(writer: W) -> TiffResult<Self> {
+        TiffEncoder::new_generic(StreamingWriter::new(writer))
+    }
+}
+
+/// Constructor functions to create BigTiff files on sinks that can not seek.
+impl<W: Write> TiffEncoder<StreamingWriter<W>, TiffKindBig> {
+    /// Creates a new encoder for BigTiff files that only writes to `writer`.
+    ///
+    /// See [`new_streaming`][TiffEncoder::new_streaming].
+    pub fn new_big_streaming(writer: W) -> TiffResult<Self> {
+        TiffEncoder::new_generic(StreamingWriter::new(writer))
+    }
+}
+
+impl<W: Write, K: TiffKind> TiffEncoder<StreamingWriter<W>, K> {
+    /// Writes out all remaining data and returns the underlying writer.
+    pub fn into_inner(self) -> TiffResult<W> {
+        Ok(self.writer.into_inner().into_inner()?)
+    }
 }
 
 /// Constructor functions to create BigTiff files.
@@ -79,6 +135,17 @@ impl<W: Write + Seek> TiffEncoder<W, TiffKindBig> {
     pub fn new_big(writer: W) -> TiffResult<Self> {
         TiffEncoder::new_generic(writer)
     }
+
+    /// Creates an encoder that appends directories to an existing BigTiff file.
+    ///
+    /// A standard Tiff file errors with [`UsageError::InvalidTiffKind`] and has to be opened with
+    /// [`append`][TiffEncoder::append] instead. See [`append_generic`][TiffEncoder::append_generic].
+    pub fn append_big(writer: W) -> TiffResult<Self>
+    where
+        W: Read,
+    {
+        TiffEncoder::append_generic(writer)
+    }
 }
 
 /// Generic functions that are available for both Tiff and BigTiff encoders.
@@ -95,6 +162,90 @@ impl<W: Write + Seek, K: TiffKind> TiffEncoder<W, K> {
         Ok(encoder)
     }
 
+    /// Creates an encoder that appends directories to an existing Tiff or BigTiff file.
+    ///
+    /// The header of the file is read to detect its kind and byte order. New directories are
+    /// written at the end of the file, and the first of them is linked to the last directory
+    /// that is already in the file.
+    ///
+    /// This errors if the kind of the file differs from the one of the encoder, or if the file
+    /// is not in native byte order, which is the only one that the encoder writes.
+    pub fn append_generic(mut writer: W) -> TiffResult<Self>
+    where
+        W: Read,
+    {
+        let mut header = [0; 4];
+        writer.seek(SeekFrom::Start(0))?;
+        writer.read_exact(&mut header)?;
+        let native = if cfg!(target_endian = "little") {
+            0x49
+        } else {
+            0x4d
+        };
+        match header[..2] {
+            [order, order2] if order == order2 && order == native => {}
+            [order, order2] if order == order2 && (order == 0x49 || order == 0x4d) => {
+                return Err(TiffUnsupportedError::NonNativeByteOrder.into())
+            }
+            _ => return Err(TiffFormatError::TiffSignatureNotFound.into()),
+        }
+
+        let big = match u16::from_ne_bytes([header[2], header[3]]) {
+            42 => false,
+            43 => true,
+            _ => return Err(TiffFormatError::TiffSignatureInvalid.into()),
+        };
+        let offset_size = mem::size_of::<K::OffsetType>();
+        if big != (offset_size == 8) {
+            return Err(TiffError::UsageError(UsageError::InvalidTiffKind {
+                file_is_bigtiff: big,
+            }));
+        }
+        if big {
+            // The size of offsets and a constant zero.
+            writer.read_exact(&mut header)?;
+            if header != [8, 0, 0, 0] && header != [0, 8, 0, 0] {
+                return Err(TiffFormatError::TiffSignatureInvalid.into());
+            }
+        }
+
+        // Follow the chain of directories to the pointer that ends it.
+        let (count_size, entry_size) = if big { (8, 20) } else { (2, 12) };
+        let mut ifd_pointer_pos = if big { 8 } else { 4 };
+        let mut seen_ifds = HashSet::new();
+        loop {
+            writer.seek(SeekFrom::Start(ifd_pointer_pos))?;
+            let ifd = read_ne_uint(&mut writer, offset_size)?;
+            if ifd == 0 {
+                break;
+            }
+            if !seen_ifds.insert(ifd) {
+                return Err(TiffFormatError::CycleInOffsets.into());
+            }
+            writer.seek(SeekFrom::Start(ifd))?;
+            let entries = read_ne_uint(&mut writer, count_size)?;
+            ifd_pointer_pos = entries
+                .checked_mul(entry_size)
+                .and_then(|len| len.checked_add(ifd + count_size as u64))
+                .ok_or(TiffFormatError::InvalidTag)?;
+        }
+
+        let end = writer.seek(SeekFrom::End(0))?;
+        let mut writer = TiffWriter::new(writer);
+        writer.goto_offset(end)?;
+        writer.set_ifd_pointer_pos(ifd_pointer_pos);
+        Ok(TiffEncoder {
+            writer,
+            kind: PhantomData,
+        })
+    }
+
+    /// Report the [`Progress`] of the images of this encoder after each chunk is written.
+    pub fn with_progress(mut self, callback: impl FnMut(Progress) + Send + 'static) -> Self {
+        self.writer.set_progress(ProgressCallback::new(callback));
+        self
+    }
+
     /// Create a [`DirectoryEncoder`] to encode an ifd directory.
     pub fn new_directory(&mut self) -> TiffResult<DirectoryEncoder<W, K>> {
         DirectoryEncoder::new(&mut self.writer)
@@ -178,6 +329,17 @@ impl<W: Write + Seek, K: TiffKind> TiffEncoder<W, K> {
     }
 }
 
+/// Reads an unsigned integer of `size` bytes in native byte order.
+fn read_ne_uint<R: Read>(reader: &mut R, size: usize) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes[..size])?;
+    Ok(match size {
+        2 => u16::from_ne_bytes([bytes[0], bytes[1]]).into(),
+        4 => u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).into(),
+        _ => u64::from_ne_bytes(bytes),
+    })
+}
+
 /// Low level interface to encode ifd directories.
 ///
 /// You should call `finish` on this when you are finished with it.
@@ -188,37 +350,29 @@ pub struct DirectoryEncoder<'a, W: 'a + Write + Seek, K: TiffKind> {
     // We use BTreeMap to make sure tags are written in correct order
     ifd_pointer_pos: u64,
     ifd: BTreeMap<u16, DirectoryEntry<K::OffsetType>>,
+    // The offset of the directory that follows this one, if it replaces an existing directory
+    next_ifd: u64,
 }
 
 impl<'a, W: 'a + Write + Seek, K: TiffKind> DirectoryEncoder<'a, W, K> {
     fn new(writer: &'a mut TiffWriter<W>) -> TiffResult<Self> {
-        // the previous word is the IFD offset position
-        let ifd_pointer_pos = writer.offset() - mem::size_of::<K::OffsetType>() as u64;
+        // the previous word is the IFD offset position, unless the writer holds another one
+        let ifd_pointer_pos = writer
+            .take_ifd_pointer_pos()
+            .unwrap_or_else(|| writer.offset() - mem::size_of::<K::OffsetType>() as u64);
         writer.pad_word_boundary()?; // TODO: Do we need to adjust this for BigTiff?
         Ok(DirectoryEncoder {
             writer,
             dropped: false,
             ifd_pointer_pos,
             ifd: BTreeMap::new(),
+            next_ifd: 0,
         })
     }
 
     /// Write a single ifd tag.
     pub fn write_tag<T: TiffValue>(&mut self, tag: Tag, value: T) -> TiffResult<()> {
-        let mut bytes = Vec::with_capacity(value.bytes());
-        {
-            let mut writer = TiffWriter::new(&mut bytes);
-            value.write(&mut writer)?;
-        }
-
-        self.ifd.insert(
-            tag.to_u16(),
-            DirectoryEntry {
-                data_type: <T>::FIELD_TYPE.to_u16(),
-                count: value.count().try_into()?,
-                data: bytes,
-            },
-        );
+        self.ifd.insert(tag.to_u16(), DirectoryEntry::new(value)?);
 
         Ok(())
     }
@@ -287,7 +441,7 @@ impl<'a, W: 'a + Write + Seek, K: TiffKind> DirectoryEncoder<'a, W, K> {
         self.writer.goto_offset(self.ifd_pointer_pos)?;
         K::write_offset(&mut self.writer, ifd_pointer)?;
         self.writer.goto_offset(curr_pos)?;
-        K::write_offset(&mut self.writer, 0)?;
+        K::write_offset(&mut self.writer, self.next_ifd)?;
 
         self.dropped = true;
 
@@ -298,6 +452,12 @@ impl<'a, W: 'a + Write + Seek, K: TiffKind> DirectoryEncoder<'a, W, K> {
     pub fn finish(mut self) -> TiffResult<()> {
         self.finish_internal()
     }
+
+    /// Give up on the directory, so that the next one is linked in its place.
+    fn abandon(&mut self) {
+        self.writer.set_ifd_pointer_pos(self.ifd_pointer_pos);
+        self.dropped = true;
+    }
 }
 
 impl<'a, W: Write + Seek, K: TiffKind> Drop for DirectoryEncoder<'a, W, K> {
@@ -351,6 +511,7 @@ pub struct ImageEncoder<
     encoder: DirectoryEncoder<'a, W, K>,
     data_idx: u64,
     chunk_count: u64,
+    bytes_written: u64,
     data_unit_size: u64,
     width: u32,
     height: u32,
@@ -365,6 +526,8 @@ pub struct ImageEncoder<
     chunks_per_row: u64, // 1 for stripped images
     chunk_width: u64, // `width` for images
     chunk_type: ChunkType, // Lives in decoder. Should be shared?
+    // The number of chunks compressed at once by `write_data` and `write_chunks_from_stream`, if set
+    concurrency: Option<usize>,
 }
 
 impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
@@ -421,6 +584,8 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
                 let chunk_height = {
                     match D::COMPRESSION_METHOD {
                         CompressionMethod::PackBits => 1, // Each row must be packed separately. Do not compress across row boundaries
+                        // Strips of JPEG data must cover whole blocks of subsampled pixels
+                        CompressionMethod::ModernJPEG => ((1_000_000 + row_bytes - 1) / row_bytes + 15) / 16 * 16,
                         _ => (1_000_000 + row_bytes - 1) / row_bytes,
                     }
                 };
@@ -431,6 +596,15 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             ChunkType::Tile => {
                 let (chunk_width, chunk_height) = chunk_dims
                     .expect("Must supply a valid tile size when constructing a tiled image");
+                if let Some(block) = Self::jpeg_block_size(&compression) {
+                    if chunk_width % block != 0 || chunk_height % block != 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "JPEG tile sizes must be multiples of 8, or of 16 with YCbCr",
+                        )
+                        .into());
+                    }
+                }
                 let data_unit_size = u64::try_from(<T>::BITS_PER_SAMPLE.len())?;
                 (data_unit_size, chunk_height, chunk_width)
             }
@@ -443,11 +617,34 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
         encoder.write_tag(Tag::ImageWidth, width)?;
         encoder.write_tag(Tag::ImageLength, height)?;
         encoder.write_tag(Tag::Compression, D::COMPRESSION_METHOD.to_u16())?;
+        let mut photometric_interpretation = <T>::TIFF_VALUE;
+        match compression.get_algorithm() {
+            Compressor::Lerc(lerc) => {
+                encoder.write_tag(Tag::LercParameters, &lerc.parameters()[..])?;
+            }
+            Compressor::Jpeg(jpeg) => {
+                let tables = jpeg.tables(<T>::BITS_PER_SAMPLE.len())?;
+                encoder.write_tag(Tag::JPEGTables, Undefined(tables))?;
+                if jpeg.ycbcr() && photometric_interpretation == PhotometricInterpretation::RGB {
+                    photometric_interpretation = PhotometricInterpretation::YCbCr;
+                    encoder.write_tag(Tag::YCbCrSubsampling, &[2u16, 2][..])?;
+                }
+            }
+            _ => {}
+        }
+        // Readers assume subsampled chroma by default, while these samples cover every pixel.
+        if <T>::TIFF_VALUE == PhotometricInterpretation::YCbCr {
+            encoder.write_tag(Tag::YCbCrSubsampling, &[1u16, 1][..])?;
+        }
 
         encoder.write_tag(Tag::BitsPerSample, <T>::BITS_PER_SAMPLE)?;
         let sample_format: Vec<_> = <T>::SAMPLE_FORMAT.iter().map(|s| s.to_u16()).collect();
         encoder.write_tag(Tag::SampleFormat, &sample_format[..])?;
-        encoder.write_tag(Tag::PhotometricInterpretation, <T>::TIFF_VALUE.to_u16())?;
+        encoder.write_tag(Tag::PhotometricInterpretation, photometric_interpretation.to_u16())?;
+        if !<T>::EXTRA_SAMPLES.is_empty() {
+            let extra_samples: Vec<_> = <T>::EXTRA_SAMPLES.iter().map(|s| s.to_u16()).collect();
+            encoder.write_tag(Tag::ExtraSamples, &extra_samples[..])?;
+        }
         
         encoder.write_tag(
             Tag::SamplesPerPixel,
@@ -467,14 +664,11 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             }
         }
 
-        println!("Encoder write position: {}", encoder.writer.offset());
-
-
-
         Ok(ImageEncoder {
             encoder,
             chunk_count,
             data_idx: 0,
+            bytes_written: 0,
             data_unit_size,
             chunk_height,
             width,
@@ -488,17 +682,30 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             chunk_type,
             chunks_per_col,
             chunks_per_row,
+            concurrency: None,
         })
     }
 
 
+    /// The size of the blocks that chunks consist of, if they are JPEG compressed.
+    fn jpeg_block_size(compression: &D) -> Option<u64> {
+        match compression.get_algorithm() {
+            Compressor::Jpeg(jpeg) => Some(jpeg.block_size(<T>::BITS_PER_SAMPLE.len())),
+            _ => None,
+        }
+    }
+
     pub fn next_chunk_dimensions(&self) -> (u64, u64) {
-        if self.data_idx >= self.chunk_count {
+        self.chunk_dimensions(self.data_idx)
+    }
+
+    fn chunk_dimensions(&self, index: u64) -> (u64, u64) {
+        if index >= self.chunk_count {
             return (0, 0);
         }
 
         if self.chunk_type == ChunkType::Strip {
-            let raw_start_row = self.data_idx * self.chunk_height;
+            let raw_start_row = index * self.chunk_height;
             let start_row = cmp::min(u64::from(self.height), raw_start_row);
             let end_row = cmp::min(u64::from(self.height), raw_start_row + self.chunk_height);
 
@@ -518,6 +725,17 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
         dims.0 * dims.1 * self.data_unit_size
     }
 
+    /// The layout of a chunk with the given dimensions, for the compressor.
+    fn chunk_layout(&self, (width, height): (u64, u64)) -> TiffResult<ChunkLayout> {
+        Ok(ChunkLayout {
+            width: usize::try_from(width)?,
+            height: usize::try_from(height)?,
+            samples: usize::try_from(self.data_unit_size)?,
+            bits_per_sample: T::BITS_PER_SAMPLE[0],
+            sample_format: T::SAMPLE_FORMAT[0],
+        })
+    }
+
     pub fn write_chunk(&mut self, value: &[T::Inner]) -> TiffResult<()>
     where
         [T::Inner]: TiffValue,
@@ -530,85 +748,172 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             )
             .into());
         }
+
+        let layout = self.chunk_layout(self.next_chunk_dimensions())?;
+        self.encoder.writer.set_chunk_layout(layout);
         
         // Write the (possibly compressed) data to the encoder.
         let offset = self.encoder.write_data(value)?;
         
 
-        let byte_count = self.encoder.last_written() as usize;
+        let byte_count = self.encoder.last_written();
+        self.push_chunk(offset, byte_count)
+    }
 
+    /// Record a chunk that was written and report the progress.
+    fn push_chunk(&mut self, offset: u64, byte_count: u64) -> TiffResult<()> {
         self.chunk_offsets.push(K::convert_offset(offset)?);
-        self.chunk_byte_count.push(byte_count.try_into()?);
-
+        self.chunk_byte_count.push(K::convert_offset(byte_count)?);
+        self.bytes_written += byte_count;
+
+        self.encoder.writer.report_progress(Progress {
+            chunk_index: self.data_idx,
+            chunk_count: self.chunk_count,
+            bytes: self.bytes_written,
+        });
         self.data_idx += 1;
         Ok(())
     }
 
-    // chunk_writer_from stream
+    /// Write a chunk that is already compressed, as it is.
+    ///
+    /// The bytes have to match the compression of the image and any `Predictor` tag, and
+    /// `byte_order` is the one of their samples. This allows to copy chunks that were read with
+    /// [`Decoder::read_chunk_raw`][crate::decoder::Decoder::read_chunk_raw] without
+    /// recompressing them, along with [`Decoder::byte_order`][crate::decoder::Decoder::byte_order].
+    ///
+    /// Samples of more than one byte have to be in [native byte order][ByteOrder::native], which
+    /// is the one the encoder writes. Other chunks error with
+    /// [`TiffUnsupportedError::NonNativeByteOrder`].
+    pub fn write_raw_chunk(&mut self, bytes: &[u8], byte_order: ByteOrder) -> TiffResult<()> {
+        if <T::Inner>::BYTE_LEN > 1 && byte_order != ByteOrder::native() {
+            return Err(TiffUnsupportedError::NonNativeByteOrder.into());
+        }
+        if self.data_idx >= self.chunk_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "All chunks of the image were already written",
+            )
+            .into());
+        }
+
+        self.encoder.writer.reset_compression();
+        let offset = self.encoder.write_data(bytes)?;
+        self.push_chunk(offset, u64::try_from(bytes.len())?)
+    }
+
+    /// Write the chunks of `stream`, which are compressed in parallel tasks.
+    ///
+    /// If this fails, the image is abandoned: its directory is not written, neither by `finish`
+    /// nor on drop, and the next directory is linked where this one would have been.
+    #[cfg(feature = "async")]
     pub async fn write_chunks_from_stream(
         &mut self,
-        stream: impl Stream<Item = Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>>
+        stream: impl Stream<Item = Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>>,
     ) -> TiffResult<()> {
-        let mut tasks: Vec<tokio::task::JoinHandle<Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>>> = Vec::new();
-        let mut pin = Box::pin(stream);
-        let compression = Arc::new(Mutex::new(self.compression.get_algorithm().clone()));
+        let result = self.write_stream(stream).await;
+        if result.is_err() {
+            self.abandon();
+        }
+        result
+    }
 
-        let n = 0;
+    #[cfg(feature = "async")]
+    async fn write_stream(
+        &mut self,
+        stream: impl Stream<Item = Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>>,
+    ) -> TiffResult<()> {
+        let mut tasks: VecDeque<tokio::task::JoinHandle<io::Result<Vec<u8>>>> = VecDeque::new();
+        let mut pin = Box::pin(stream);
+        let compressor = self.compression.get_algorithm();
+        let window = self
+            .concurrency
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(8, |n| n.get()));
 
         while let Some(slice_result) = pin.next().await {
-            if tasks.len() == 8 {
-                for result in join_all(tasks).await {
-                    let compressed_data = result.unwrap().unwrap();
-                    let byte_count = compressed_data.len();
-                    let offset = self.encoder.writer.offset();
-                    self.encoder.writer.write_bytes(&compressed_data)?;
-                    self.chunk_offsets.push(K::convert_offset(offset)?);
-                    self.chunk_byte_count.push(byte_count.try_into()?);
-                    self.data_idx += 1;
-                    
-                    if self.data_idx % 100 == 0 {
-                        println!("{} chunks written", self.data_idx);
-                    }
-                }
-                tasks = Vec::new();
+            // Keep a window of tasks in flight and write their chunks in order.
+            if tasks.len() >= window {
+                let task = tasks.pop_front().unwrap();
+                self.write_compressed_chunk(task.await.map_err(Into::into))?;
             }
 
+            let index = self.data_idx + tasks.len() as u64;
             match slice_result {
-                Ok(slice) => {
-                    let data = slice.to_vec(); // clone the slice to move it into the closure
-                    let compression = Arc::clone(&compression); // clone the Arc to move it into the closure
-                    tasks.push(tokio::spawn(async move {
+                Ok(data) => {
+                    self.check_chunk_size(index, &data)?;
+                    let mut compressor = compressor.clone();
+                    compressor.set_chunk_layout(self.chunk_layout(self.chunk_dimensions(index))?);
+                    tasks.push_back(tokio::spawn(async move {
                         let mut compressed_data = Vec::new();
-                        let mut compressor = compression.lock().await.clone();
-                        match compressor.write_to(&mut compressed_data, &data) {
-                            Ok(_) => Ok(compressed_data),
-                            Err(e) => Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>),
-                        }
+                        compressor.write_to(&mut compressed_data, &data)?;
+                        Ok(compressed_data)
                     }));
-                },
-                Err(_) => return Err(TiffError::ThreadError),
+                }
+                Err(e) => {
+                    return Err(TiffError::ChunkError(ChunkError {
+                        index,
+                        kind: ChunkErrorKind::Stream(e),
+                    }))
+                }
             }
         }
-        
-        if !tasks.is_empty() {
-            for result in join_all(tasks).await {
-                let compressed_data = result.unwrap().unwrap();
-                let byte_count = compressed_data.len();
+
+        for task in tasks {
+            self.write_compressed_chunk(task.await.map_err(Into::into))?;
+        }
+        if self.data_idx < self.chunk_count {
+            return Err(TiffError::ChunkError(ChunkError {
+                index: self.data_idx,
+                kind: ChunkErrorKind::Missing,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Check that the chunk at `index` exists and that `data` holds all of its samples.
+    ///
+    /// Tiles at the right and bottom edge are padded to the full tile size.
+    #[cfg(feature = "async")]
+    fn check_chunk_size(&self, index: u64, data: &[u8]) -> TiffResult<()> {
+        let kind = if index >= self.chunk_count {
+            ChunkErrorKind::Unexpected
+        } else {
+            let (width, height) = self.chunk_dimensions(index);
+            let expected = width * height * self.data_unit_size * u64::from(<T::Inner>::BYTE_LEN);
+            let actual = u64::try_from(data.len())?;
+            if actual == expected {
+                return Ok(());
+            }
+            ChunkErrorKind::InvalidSize { expected, actual }
+        };
+        Err(TiffError::ChunkError(ChunkError { index, kind }))
+    }
+
+    /// Write the next chunk, as compressed by a task or thread that may have failed.
+    fn write_compressed_chunk(
+        &mut self,
+        result: Result<io::Result<Vec<u8>>, Box<dyn Error + Send + Sync>>,
+    ) -> TiffResult<()> {
+        let kind = match result {
+            Ok(Ok(compressed_data)) => {
                 let offset = self.encoder.writer.offset();
                 self.encoder.writer.write_bytes(&compressed_data)?;
-                self.chunk_offsets.push(K::convert_offset(offset)?);
-                self.chunk_byte_count.push(byte_count.try_into()?);
-                self.data_idx += 1;
-                if self.data_idx % 100 == 0 {
-                    println!("{} chunks written", self.data_idx);
-                }
+                return self.push_chunk(offset, u64::try_from(compressed_data.len())?);
             }
-        }        
-        
-        TiffResult::from(Ok(()))
-
+            Ok(Err(e)) => ChunkErrorKind::Compression(e),
+            Err(e) => ChunkErrorKind::Task(e),
+        };
+        Err(TiffError::ChunkError(ChunkError {
+            index: self.data_idx,
+            kind,
+        }))
     }
 
+    /// Give up on the image without writing its directory.
+    fn abandon(&mut self) {
+        self.dropped = true;
+        self.encoder.abandon();
+    }
 
     pub fn write_chunk_with_compression(&mut self, value: &[T::Inner]) -> TiffResult<()>
     where
@@ -634,19 +939,22 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
     }
 
     /// Write strips from data
+    ///
+    /// For a tiled image, `data` holds the full tiles one after another, each of
+    /// [`next_chunk_sample_count`][ImageEncoder::next_chunk_sample_count] samples. Chunks are
+    /// compressed on [`concurrency`][ImageEncoder::concurrency] threads if that is set, and
+    /// written in order.
     pub fn write_data(mut self, data: &[T::Inner]) -> TiffResult<()>
     where
         [T::Inner]: TiffValue,
     {
-        let num_pix = usize::try_from(self.width)?
-            .checked_mul(usize::try_from(self.height)?)
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Image width * height exceeds usize",
-                )
-            })?;
-        if data.len() < num_pix {
+        let num_samples: u64 = (self.data_idx..self.chunk_count)
+            .map(|index| {
+                let (width, height) = self.chunk_dimensions(index);
+                width * height * self.data_unit_size
+            })
+            .sum();
+        if (data.len() as u64) < num_samples {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Input data slice is undersized for provided dimensions",
@@ -654,22 +962,89 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             .into());
         }
 
+        let data = data.data();
+        let result = match self.concurrency {
+            Some(threads) if threads > 1 && D::COMPRESSION_METHOD != CompressionMethod::None => {
+                self.write_chunks_parallel(&data, threads)
+            }
+            _ => self.write_chunks_sequential(&data),
+        };
+        if result.is_err() {
+            self.abandon();
+            return result;
+        }
+        self.finish()
+    }
+
+    fn write_chunks_sequential(&mut self, data: &[u8]) -> TiffResult<()> {
         self.encoder
             .writer
             .set_compression(self.compression.get_algorithm());
 
-        let mut idx = 0;
-        while self.next_chunk_sample_count() > 0 {
-            let sample_count = usize::try_from(self.next_chunk_sample_count())?;
-            self.write_chunk(&data[idx..idx + sample_count])?;
-            idx += sample_count;
+        let first = self.data_idx;
+        let mut result = Ok(());
+        for index in first..self.chunk_count {
+            let chunk = self.chunk_bytes(data, first, index)?;
+            let layout = self.chunk_layout(self.chunk_dimensions(index))?;
+            self.encoder.writer.set_chunk_layout(layout);
+            result = self.encoder.write_data(chunk).and_then(|offset| {
+                let byte_count = self.encoder.last_written();
+                self.push_chunk(offset, byte_count)
+            });
+            if result.is_err() {
+                break;
+            }
         }
 
         self.encoder.writer.reset_compression();
-        self.finish()?;
+        result
+    }
+
+    /// Compress the chunks on `threads` threads and write them in order.
+    fn write_chunks_parallel(&mut self, data: &[u8], threads: usize) -> TiffResult<()> {
+        let pool = self.encoder.writer.take_pool(threads);
+        self.write_chunks_to_pool(data, &pool)?;
+        // Threads that still hold chunks after an error are not reused.
+        self.encoder.writer.put_pool(pool);
         Ok(())
     }
 
+    fn write_chunks_to_pool(&mut self, data: &[u8], pool: &CompressionPool) -> TiffResult<()> {
+        let first = self.data_idx;
+        let threads = u64::try_from(pool.threads())?;
+        let compressor = self.compression.get_algorithm();
+        for index in first..self.chunk_count + threads {
+            let worker = usize::try_from((index - first) % threads)?;
+            // Write the chunk that this thread was given in its previous turn.
+            if index >= first + threads {
+                self.write_compressed_chunk(pool.recv(worker).map_err(Into::into))?;
+            }
+            if index < self.chunk_count {
+                let mut compressor = compressor.clone();
+                compressor.set_chunk_layout(self.chunk_layout(self.chunk_dimensions(index))?);
+                let chunk = self.chunk_bytes(data, first, index)?.to_vec();
+                pool.send(worker, compressor, chunk).map_err(|e| {
+                    TiffError::ChunkError(ChunkError {
+                        index,
+                        kind: ChunkErrorKind::Task(Box::new(e)),
+                    })
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The bytes of the chunk at `index`, out of the bytes of the chunks from `first` on one
+    /// after another.
+    fn chunk_bytes<'d>(&self, data: &'d [u8], first: u64, index: u64) -> TiffResult<&'d [u8]> {
+        let pixel_len = self.data_unit_size * u64::from(<T::Inner>::BYTE_LEN);
+        let (chunk_width, chunk_height) = self.chunk_dimensions(index);
+        let chunk_len = self.chunk_width * self.chunk_height * pixel_len;
+        let start = usize::try_from((index - first) * chunk_len)?;
+        let len = usize::try_from(chunk_width * chunk_height * pixel_len)?;
+        Ok(&data[start..start + len])
+    }
+
     /// Set image resolution
     pub fn resolution(&mut self, unit: ResolutionUnit, value: Rational) {
         self.encoder
@@ -710,6 +1085,18 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             .unwrap();
     }
 
+    /// Set the number of chunks that `write_data` and `write_chunks_from_stream` compress at once.
+    ///
+    /// Chunks are still written in order. By default, `write_data` compresses each chunk on the
+    /// calling thread, while `write_chunks_from_stream` keeps as many tasks in flight as there is
+    /// available parallelism.
+    ///
+    /// With more than one, `write_data` copies each chunk to one of as many threads. The threads
+    /// are kept by the [`TiffEncoder`] for later images with the same concurrency.
+    pub fn concurrency(&mut self, tasks: usize) {
+        self.concurrency = Some(tasks.max(1));
+    }
+
     pub fn get_chunk_dim_counts(&self) -> (u64, u64) {
         (self.chunks_per_row, self.chunks_per_col)
     }
@@ -717,7 +1104,8 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
     /// Set image number of lines per strip
     ///
     /// This function needs to be called before any calls to `write_data` or
-    /// `write_strip` and will return an error otherwise.
+    /// `write_strip` and will return an error otherwise. With JPEG compression, the value must be
+    /// a multiple of 8, or of 16 with YCbCr, unless it covers the whole image.
     pub fn rows_per_strip(&mut self, value: u32) -> TiffResult<()> {
         if self.data_idx != 0 {
             return Err(io::Error::new(
@@ -726,6 +1114,16 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
             )
             .into());
         }
+        // Strips of JPEG data must cover whole blocks, except for one that covers the image.
+        if let Some(block) = Self::jpeg_block_size(&self.compression) {
+            if u64::from(value) % block != 0 && value < self.height {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "JPEG strips must be a multiple of 8 rows, or of 16 with YCbCr",
+                )
+                .into());
+            }
+        }
         // Write tag as 32 bits
         self.encoder.write_tag(Tag::RowsPerStrip, value)?;
 
@@ -737,6 +1135,13 @@ impl<'a, W: 'a + Write + Seek, T: ColorType, K: TiffKind, D: Compression>
     }
 
     fn finish_internal(&mut self) -> TiffResult<()> {
+        if self.dropped {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The image was abandoned after writing its chunks failed",
+            )
+            .into());
+        }
         match self.chunk_type {
             ChunkType::Strip => {
                 self.encoder
@@ -787,6 +1192,22 @@ struct DirectoryEntry<S> {
     data: Vec<u8>,
 }
 
+impl<S: TryFrom<usize, Error = TryFromIntError>> DirectoryEntry<S> {
+    fn new<T: TiffValue>(value: T) -> TiffResult<Self> {
+        let mut bytes = Vec::with_capacity(value.bytes());
+        {
+            let mut writer = TiffWriter::new(&mut bytes);
+            value.write(&mut writer)?;
+        }
+
+        Ok(DirectoryEntry {
+            data_type: <T>::FIELD_TYPE.to_u16(),
+            count: value.count().try_into()?,
+            data: bytes,
+        })
+    }
+}
+
 /// Trait to abstract over Tiff/BigTiff differences.
 ///
 /// Implemented for [`TiffKindStandard`] and [`TiffKindBig`].
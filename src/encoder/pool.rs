@@ -0,0 +1,68 @@
+//! Threads that compress the chunks of `ImageEncoder::write_data`.
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvError, SendError, Sender};
+use std::thread;
+
+use super::compression::{CompressionAlgorithm, Compressor};
+
+/// A thread that compresses the chunks it is sent, in order.
+struct Worker {
+    jobs: Sender<(Compressor, Vec<u8>)>,
+    results: Receiver<io::Result<Vec<u8>>>,
+}
+
+/// Threads that compress chunks, to be reused for the images of an encoder.
+///
+/// Chunks are dealt out to the threads in turn, so that their results are received in order. The
+/// threads stop once the pool is dropped.
+pub(crate) struct CompressionPool {
+    workers: Vec<Worker>,
+}
+
+impl CompressionPool {
+    pub(crate) fn new(threads: usize) -> Self {
+        let workers = (0..threads)
+            .map(|_| {
+                let (jobs, job_receiver) = mpsc::channel::<(Compressor, Vec<u8>)>();
+                let (result_sender, results) = mpsc::channel();
+                thread::spawn(move || {
+                    for (mut compressor, chunk) in job_receiver {
+                        let mut compressed_data = Vec::new();
+                        let result = compressor
+                            .write_to(&mut compressed_data, &chunk)
+                            .map(|_| compressed_data);
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker { jobs, results }
+            })
+            .collect();
+        CompressionPool { workers }
+    }
+
+    pub(crate) fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Send a chunk to be compressed by the thread `worker`.
+    ///
+    /// This fails if the thread stopped, after it panicked.
+    pub(crate) fn send(
+        &self,
+        worker: usize,
+        compressor: Compressor,
+        chunk: Vec<u8>,
+    ) -> Result<(), SendError<()>> {
+        self.workers[worker]
+            .jobs
+            .send((compressor, chunk))
+            .map_err(|_| SendError(()))
+    }
+
+    /// Receive the oldest chunk that was compressed by the thread `worker`.
+    pub(crate) fn recv(&self, worker: usize) -> Result<io::Result<Vec<u8>>, RecvError> {
+        self.workers[worker].results.recv()
+    }
+}
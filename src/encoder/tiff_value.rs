@@ -1,5 +1,7 @@
 use std::{borrow::Cow, io::Write, slice::from_ref};
 
+use half::f16;
+
 use crate::{bytecast, tags::Type, TiffError, TiffFormatError, TiffResult};
 
 use super::writer::TiffWriter;
@@ -130,6 +132,21 @@ impl TiffValue for [i64] {
     }
 }
 
+// There is no field type for half precision floats, they are written as `SHORT` bits.
+impl TiffValue for [f16] {
+    const BYTE_LEN: u8 = 2;
+    const FIELD_TYPE: Type = Type::SHORT;
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn data(&self) -> Cow<[u8]> {
+        // We write using native endian so this should be safe
+        Cow::Borrowed(bytecast::f16_as_ne_bytes(self))
+    }
+}
+
 impl TiffValue for [f32] {
     const BYTE_LEN: u8 = 4;
     const FIELD_TYPE: Type = Type::FLOAT;
@@ -302,6 +319,24 @@ impl TiffValue for i64 {
     }
 }
 
+impl TiffValue for f16 {
+    const BYTE_LEN: u8 = 2;
+    const FIELD_TYPE: Type = Type::SHORT;
+
+    fn count(&self) -> usize {
+        1
+    }
+
+    fn write<W: Write>(&self, writer: &mut TiffWriter<W>) -> TiffResult<()> {
+        writer.write_u16(self.to_bits())?;
+        Ok(())
+    }
+
+    fn data(&self) -> Cow<[u8]> {
+        Cow::Borrowed(bytecast::f16_as_ne_bytes(from_ref(self)))
+    }
+}
+
 impl TiffValue for f32 {
     const BYTE_LEN: u8 = 4;
     const FIELD_TYPE: Type = Type::FLOAT;
@@ -374,6 +409,19 @@ impl TiffValue for Ifd8 {
     }
 }
 
+impl TiffValue for Undefined {
+    const BYTE_LEN: u8 = 1;
+    const FIELD_TYPE: Type = Type::UNDEFINED;
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<[u8]> {
+        Cow::Borrowed(&self.0)
+    }
+}
+
 impl TiffValue for Rational {
     const BYTE_LEN: u8 = 8;
     const FIELD_TYPE: Type = Type::RATIONAL;
@@ -458,6 +506,10 @@ impl<'a, T: TiffValue + ?Sized> TiffValue for &'a T {
         (*self).count()
     }
 
+    fn bytes(&self) -> usize {
+        (*self).bytes()
+    }
+
     fn write<W: Write>(&self, writer: &mut TiffWriter<W>) -> TiffResult<()> {
         (*self).write(writer)
     }
@@ -500,6 +552,52 @@ impl_tiff_value_for_contiguous_sequence!(Ifd8; 8; Type::IFD8);
 impl_tiff_value_for_contiguous_sequence!(Rational; 8; Type::RATIONAL);
 impl_tiff_value_for_contiguous_sequence!(SRational; 8; Type::SRATIONAL);
 
+// There is no field type for complex values, in tags they are bytes of type UNDEFINED. The
+// length of a value is still the one of a sample, as they are mostly written as sample data.
+macro_rules! impl_tiff_value_for_complex {
+    ($part:ty; $bytes:expr; $as_bytes:path) => {
+        impl TiffValue for [$part; 2] {
+            const BYTE_LEN: u8 = $bytes;
+            const FIELD_TYPE: Type = Type::UNDEFINED;
+
+            fn count(&self) -> usize {
+                $bytes
+            }
+
+            fn bytes(&self) -> usize {
+                self.count()
+            }
+
+            fn data(&self) -> Cow<[u8]> {
+                Cow::Borrowed($as_bytes(from_ref(self)))
+            }
+        }
+
+        impl TiffValue for [[$part; 2]] {
+            const BYTE_LEN: u8 = $bytes;
+            const FIELD_TYPE: Type = Type::UNDEFINED;
+
+            fn count(&self) -> usize {
+                self.len() * $bytes
+            }
+
+            fn bytes(&self) -> usize {
+                self.count()
+            }
+
+            fn data(&self) -> Cow<[u8]> {
+                // We write using native endian so this should be safe
+                Cow::Borrowed($as_bytes(self))
+            }
+        }
+    };
+}
+
+impl_tiff_value_for_complex!(i16; 4; bytecast::complex_i16_as_ne_bytes);
+impl_tiff_value_for_complex!(i32; 8; bytecast::complex_i32_as_ne_bytes);
+impl_tiff_value_for_complex!(f32; 8; bytecast::complex_f32_as_ne_bytes);
+impl_tiff_value_for_complex!(f64; 16; bytecast::complex_f64_as_ne_bytes);
+
 /// Type to represent tiff values of type `IFD`
 #[derive(Clone)]
 pub struct Ifd(pub u32);
@@ -508,6 +606,10 @@ pub struct Ifd(pub u32);
 #[derive(Clone)]
 pub struct Ifd8(pub u64);
 
+/// Type to represent tiff values of type `UNDEFINED`, bytes whose meaning depends on their tag
+#[derive(Clone)]
+pub struct Undefined(pub Vec<u8>);
+
 /// Type to represent tiff values of type `RATIONAL`
 #[derive(Clone)]
 pub struct Rational {
@@ -0,0 +1,461 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Seek, Write};
+
+use super::compression::{Compression, Compressor};
+use super::{DirectoryEntry, TiffEncoder, TiffKind};
+use crate::decoder::{ChunkType, Decoder};
+use crate::error::TiffResult;
+use crate::tags::{
+    CompressionMethod, PhotometricInterpretation, Predictor, SampleFormat, Tag, Type,
+};
+use crate::{TiffFormatError, TiffUnsupportedError};
+
+/// Tags that describe the layout and encoding of the image data, which are written anew.
+const STRUCTURAL_TAGS: [Tag; 15] = [
+    Tag::Compression,
+    Tag::Predictor,
+    Tag::FillOrder,
+    Tag::RowsPerStrip,
+    Tag::StripOffsets,
+    Tag::StripByteCounts,
+    Tag::TileWidth,
+    Tag::TileLength,
+    Tag::TileOffsets,
+    Tag::TileByteCounts,
+    Tag::JPEGTables,
+    Tag::LercParameters,
+    Tag::YCbCrSubsampling,
+    Tag::FreeOffsets,
+    Tag::FreeByteCounts,
+];
+
+/// Tags that point to other directories of the source file, which are not transcoded: SubIFDs
+/// and the Exif, GPS and interoperability directories.
+const DIRECTORY_TAGS: [u16; 4] = [330, 34665, 34853, 40965];
+
+/// The layout of the chunks of transcoded images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chunking {
+    Source,
+    Strips(u32),
+    Tiles(u32, u32),
+}
+
+/// Recompresses the images of a file without changing their samples.
+///
+/// Every image from the current one of the decoder on is written to a new directory of an
+/// encoder, with the tags of the source except for those that describe the layout and encoding
+/// of the image data. Images are decoded and recompressed one row of chunks at a time, such that
+/// only the source chunks covering that row are held in memory.
+///
+/// # Examples
+/// ```
+/// # extern crate tiff;
+/// # fn main() {
+/// # let mut source = std::io::Cursor::new(Vec::new());
+/// # let mut file = std::io::Cursor::new(Vec::new());
+/// # let image_data = vec![0; 100*100*3];
+/// # tiff::encoder::TiffEncoder::new(&mut source).unwrap().write_image::<tiff::encoder::colortype::RGB8>(100, 100, &image_data).unwrap();
+/// # source.set_position(0);
+/// use tiff::decoder::Decoder;
+/// use tiff::encoder::{compression::Deflate, TiffEncoder, Transcoder};
+/// use tiff::tags::Predictor;
+///
+/// let decoder = Decoder::new(&mut source).unwrap();
+/// let mut tiff = TiffEncoder::new(&mut file).unwrap();
+/// Transcoder::new(decoder, Deflate::default())
+///     .unwrap()
+///     .with_predictor(Predictor::Horizontal)
+///     .with_tiles(64, 64)
+///     .transcode(&mut tiff)
+///     .unwrap();
+/// # }
+/// ```
+pub struct Transcoder<R: Read + Seek> {
+    decoder: Decoder<R>,
+    compressor: Compressor,
+    compression_method: CompressionMethod,
+    predictor: Predictor,
+    chunking: Chunking,
+}
+
+impl<R: Read + Seek> Transcoder<R> {
+    /// Creates a transcoder from the images of `decoder` to ones with `compression`.
+    ///
+    /// Only lossless compression methods are supported.
+    pub fn new<D: Compression>(decoder: Decoder<R>, compression: D) -> TiffResult<Self> {
+        match D::COMPRESSION_METHOD {
+            CompressionMethod::None
+            | CompressionMethod::LZW
+            | CompressionMethod::Deflate
+            | CompressionMethod::PackBits
+            | CompressionMethod::ZSTD => {}
+            method => return Err(TiffUnsupportedError::UnsupportedCompressionMethod(method).into()),
+        }
+
+        Ok(Transcoder {
+            decoder,
+            compressor: compression.get_algorithm(),
+            compression_method: D::COMPRESSION_METHOD,
+            predictor: Predictor::None,
+            chunking: Chunking::Source,
+        })
+    }
+
+    /// Apply `predictor` to the samples before compressing them.
+    ///
+    /// The horizontal predictor applies to integer samples of at least 8 bits, and the floating
+    /// point predictor to floats of 16, 32 or 64 bits.
+    pub fn with_predictor(mut self, predictor: Predictor) -> Self {
+        self.predictor = predictor;
+        self
+    }
+
+    /// Write images in strips of `rows_per_strip` rows instead of the chunks of the source.
+    pub fn with_strips(mut self, rows_per_strip: u32) -> Self {
+        self.chunking = Chunking::Strips(rows_per_strip);
+        self
+    }
+
+    /// Write images in tiles of the given size instead of the chunks of the source.
+    ///
+    /// The width and height must be multiples of 16.
+    pub fn with_tiles(mut self, width: u32, height: u32) -> Self {
+        self.chunking = Chunking::Tiles(width, height);
+        self
+    }
+
+    /// Transcodes the current image of the decoder and all images after it, each to a new
+    /// directory of `encoder`.
+    pub fn transcode<W: Write + Seek, K: TiffKind>(
+        mut self,
+        encoder: &mut TiffEncoder<W, K>,
+    ) -> TiffResult<()> {
+        loop {
+            self.transcode_image(encoder)?;
+            if !self.decoder.more_images() {
+                return Ok(());
+            }
+            self.decoder.next_image()?;
+        }
+    }
+
+    fn transcode_image<W: Write + Seek, K: TiffKind>(
+        &mut self,
+        encoder: &mut TiffEncoder<W, K>,
+    ) -> TiffResult<()> {
+        // Samples that are converted while decoding could not be stored as they are.
+        let source_compression = self
+            .decoder
+            .find_tag_unsigned(Tag::Compression)?
+            .map(CompressionMethod::from_u16_exhaustive)
+            .unwrap_or(CompressionMethod::None);
+        match source_compression {
+            CompressionMethod::None
+            | CompressionMethod::LZW
+            | CompressionMethod::Deflate
+            | CompressionMethod::OldDeflate
+            | CompressionMethod::PackBits
+            | CompressionMethod::ZSTD
+            | CompressionMethod::Lerc => {}
+            method => return Err(TiffUnsupportedError::UnsupportedCompressionMethod(method).into()),
+        }
+
+        let (width, height) = self.decoder.dimensions()?;
+        let samples = self.decoder.sample_descriptors();
+        let bits = samples[0].bits;
+        if !samples.iter().all(|sample| sample.bits == bits)
+            || ![1, 2, 4, 8, 16, 32, 64].contains(&bits)
+        {
+            return Err(TiffUnsupportedError::UnsupportedBitsPerChannel(bits).into());
+        }
+
+        let format = samples[0].format;
+        let supported = match self.predictor {
+            Predictor::None => true,
+            Predictor::Horizontal => {
+                bits >= 8 && matches!(format, SampleFormat::Uint | SampleFormat::Int)
+            }
+            Predictor::FloatingPoint => bits >= 16 && format == SampleFormat::IEEEFP,
+        };
+        if !supported {
+            let formats = samples.iter().map(|sample| sample.format).collect();
+            return Err(TiffUnsupportedError::UnsupportedPredictor(self.predictor, formats).into());
+        }
+
+        // Planar images hold a single sample of each pixel in their chunks.
+        let planar = self.decoder.find_tag_unsigned(Tag::PlanarConfiguration)? == Some(2u16);
+        let (planes, chunk_samples) = if planar {
+            (samples.len(), 1)
+        } else {
+            (1, samples.len())
+        };
+        let pixel_bits = usize::from(bits) * chunk_samples;
+
+        let (chunk_type, chunk_width, chunk_height) = match self.chunking {
+            Chunking::Source => {
+                let (source_width, source_height) = self.decoder.chunk_dimensions();
+                match self.decoder.get_chunk_type() {
+                    ChunkType::Strip => (ChunkType::Strip, width, source_height.min(height)),
+                    ChunkType::Tile => (ChunkType::Tile, source_width, source_height),
+                }
+            }
+            Chunking::Strips(rows) if rows > 0 => (ChunkType::Strip, width, rows.min(height)),
+            Chunking::Tiles(tile_width, tile_height)
+                if tile_width > 0
+                    && tile_height > 0
+                    && tile_width % 16 == 0
+                    && tile_height % 16 == 0 =>
+            {
+                (ChunkType::Tile, tile_width, tile_height)
+            }
+            Chunking::Strips(rows) => {
+                return Err(TiffFormatError::InvalidDimensions(width, rows).into())
+            }
+            Chunking::Tiles(tile_width, tile_height) => {
+                return Err(TiffFormatError::InvalidDimensions(tile_width, tile_height).into())
+            }
+        };
+
+        // Chunks are read without the optional conversions of the decoder, but WhiteIsZero is
+        // always inverted and CIE L*a*b* always normalized to the ICC encoding.
+        let photometric = self
+            .decoder
+            .find_tag_unsigned(Tag::PhotometricInterpretation)?
+            .and_then(PhotometricInterpretation::from_u16);
+        let ycbcr = photometric == Some(PhotometricInterpretation::YCbCr);
+        let decoded_photometric = match photometric {
+            Some(PhotometricInterpretation::WhiteIsZero) => {
+                Some(PhotometricInterpretation::BlackIsZero)
+            }
+            Some(PhotometricInterpretation::CIELab | PhotometricInterpretation::ITULab)
+                if bits == 8 || bits == 16 =>
+            {
+                Some(PhotometricInterpretation::ICCLab)
+            }
+            _ => None,
+        };
+        let tags = self.decoder.native_tags()?;
+
+        let mut dir = encoder.new_directory()?;
+        for (tag, type_, count, data) in tags {
+            if STRUCTURAL_TAGS.contains(&tag)
+                || DIRECTORY_TAGS.contains(&tag.to_u16())
+                || (decoded_photometric.is_some() && tag == Tag::Decode)
+                || matches!(type_, Type::IFD | Type::IFD8)
+            {
+                continue;
+            }
+            let entry = DirectoryEntry {
+                data_type: type_.to_u16(),
+                count: K::convert_offset(count)?,
+                data,
+            };
+            dir.ifd.insert(tag.to_u16(), entry);
+        }
+
+        dir.write_tag(Tag::Compression, self.compression_method.to_u16())?;
+        if let Some(photometric) = decoded_photometric {
+            dir.write_tag(Tag::PhotometricInterpretation, photometric.to_u16())?;
+        }
+        if self.predictor != Predictor::None {
+            dir.write_tag(Tag::Predictor, self.predictor.to_u16())?;
+        }
+        // Subsampled chroma is expanded to every pixel while decoding.
+        if ycbcr {
+            dir.write_tag(Tag::YCbCrSubsampling, &[1u16, 1][..])?;
+        }
+        match chunk_type {
+            ChunkType::Strip => dir.write_tag(Tag::RowsPerStrip, chunk_height)?,
+            ChunkType::Tile => {
+                dir.write_tag(Tag::TileWidth, chunk_width)?;
+                dir.write_tag(Tag::TileLength, chunk_height)?;
+            }
+        }
+
+        let row_bytes = row_len(width, pixel_bits);
+        let chunk_row_bytes = row_len(chunk_width, pixel_bits);
+        let chunks_across = (width - 1) / chunk_width + 1;
+        let mut offsets = Vec::new();
+        let mut byte_counts = Vec::new();
+
+        dir.writer.set_compression(self.compressor.clone());
+        for plane in 0..planes {
+            let mut cache = HashMap::new();
+            for band_y in (0..height).step_by(chunk_height as usize) {
+                let band_rows = chunk_height.min(height - band_y);
+                let band = self.read_band(plane, band_y, band_rows, pixel_bits, &mut cache)?;
+
+                // Tiles are padded to their full size, while the last strip may be shorter.
+                let rows = match chunk_type {
+                    ChunkType::Strip => band_rows,
+                    ChunkType::Tile => chunk_height,
+                };
+                for x in 0..chunks_across {
+                    let start = byte_offset(x * chunk_width, pixel_bits, bits)?;
+                    let len = chunk_row_bytes.min(row_bytes - start);
+
+                    let mut chunk = vec![0; chunk_row_bytes * rows as usize];
+                    for (row, source) in chunk
+                        .chunks_exact_mut(chunk_row_bytes)
+                        .zip(band.chunks_exact(row_bytes))
+                    {
+                        row[..len].copy_from_slice(&source[start..start + len]);
+                    }
+                    for row in chunk.chunks_exact_mut(chunk_row_bytes) {
+                        self.predict(row, chunk_samples, bits);
+                    }
+
+                    let offset = dir.write_data(&chunk[..])?;
+                    offsets.push(K::convert_offset(offset)?);
+                    byte_counts.push(K::convert_offset(dir.last_written())?);
+                }
+            }
+        }
+        dir.writer.reset_compression();
+
+        match chunk_type {
+            ChunkType::Strip => {
+                dir.write_tag(Tag::StripOffsets, K::convert_slice(&offsets))?;
+                dir.write_tag(Tag::StripByteCounts, K::convert_slice(&byte_counts))?;
+            }
+            ChunkType::Tile => {
+                dir.write_tag(Tag::TileOffsets, K::convert_slice(&offsets))?;
+                dir.write_tag(Tag::TileByteCounts, K::convert_slice(&byte_counts))?;
+            }
+        }
+        dir.finish()
+    }
+
+    /// Reads `band_rows` rows of `plane` from `band_y` on, out of the source chunks that cover
+    /// them. Chunks that also cover rows after the band are kept in `cache`.
+    fn read_band(
+        &mut self,
+        plane: usize,
+        band_y: u32,
+        band_rows: u32,
+        pixel_bits: usize,
+        cache: &mut HashMap<u32, Vec<u8>>,
+    ) -> TiffResult<Vec<u8>> {
+        let (width, height) = self.decoder.dimensions()?;
+        let (source_width, source_height) = self.decoder.chunk_dimensions();
+        let (source_width, source_height) = (
+            u64::from(source_width.min(width)),
+            u64::from(source_height.min(height)),
+        );
+        let across = (u64::from(width) - 1) / source_width + 1;
+        let down = (u64::from(height) - 1) / source_height + 1;
+        let bits = self.decoder.sample_descriptors()[0].bits;
+
+        let row_bytes = row_len(width, pixel_bits);
+        let mut band = vec![0; row_bytes * band_rows as usize];
+        let (band_start, band_end) = (u64::from(band_y), u64::from(band_y + band_rows));
+        for y in band_start / source_height..=(band_end - 1) / source_height {
+            for x in 0..across {
+                let index = u32::try_from((plane as u64 * down + y) * across + x)?;
+                let chunk = match cache.entry(index) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(self.decoder.read_chunk_bytes(index)?),
+                };
+
+                let data_width = self.decoder.chunk_data_dimensions(index).0;
+                let chunk_row_bytes = row_len(data_width, pixel_bits);
+                let start = byte_offset(u32::try_from(x * source_width)?, pixel_bits, bits)?;
+
+                let first_row = band_start.max(y * source_height);
+                let last_row = band_end.min((y + 1) * source_height);
+                for row in first_row..last_row {
+                    let source = (row - y * source_height) as usize * chunk_row_bytes;
+                    let target = (row - band_start) as usize * row_bytes + start;
+                    band[target..target + chunk_row_bytes]
+                        .copy_from_slice(&chunk[source..source + chunk_row_bytes]);
+                }
+            }
+        }
+
+        cache.retain(|&index, _| {
+            let y = u64::from(index) / across % down;
+            (y + 1) * source_height > band_end
+        });
+        Ok(band)
+    }
+
+    /// Applies the predictor to a row of a chunk with `samples` samples per pixel.
+    fn predict(&self, row: &mut [u8], samples: usize, bits: u8) {
+        match (self.predictor, bits) {
+            (Predictor::Horizontal, 8) => {
+                predict_horizontal(row, samples, |a: [u8; 1], b| [a[0].wrapping_sub(b[0])])
+            }
+            (Predictor::Horizontal, 16) => predict_horizontal(row, samples, |a, b| {
+                u16::from_ne_bytes(a)
+                    .wrapping_sub(u16::from_ne_bytes(b))
+                    .to_ne_bytes()
+            }),
+            (Predictor::Horizontal, 32) => predict_horizontal(row, samples, |a, b| {
+                u32::from_ne_bytes(a)
+                    .wrapping_sub(u32::from_ne_bytes(b))
+                    .to_ne_bytes()
+            }),
+            (Predictor::Horizontal, 64) => predict_horizontal(row, samples, |a, b| {
+                u64::from_ne_bytes(a)
+                    .wrapping_sub(u64::from_ne_bytes(b))
+                    .to_ne_bytes()
+            }),
+            (Predictor::FloatingPoint, _) => {
+                predict_floating_point(row, samples, usize::from(bits / 8))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The number of bytes in a row of `width` pixels of `pixel_bits` bits.
+fn row_len(width: u32, pixel_bits: usize) -> usize {
+    (width as usize * pixel_bits + 7) / 8
+}
+
+/// The byte offset of pixel `x` in a row, which has to start at a byte boundary.
+fn byte_offset(x: u32, pixel_bits: usize, bits: u8) -> TiffResult<usize> {
+    let start = x as usize * pixel_bits;
+    if start % 8 != 0 {
+        return Err(TiffUnsupportedError::UnsupportedBitsPerChannel(bits).into());
+    }
+    Ok(start / 8)
+}
+
+/// Replaces each sample of `N` bytes in `row` by its difference to the same sample of the
+/// previous pixel, as undone by the horizontal predictor.
+fn predict_horizontal<const N: usize>(
+    row: &mut [u8],
+    samples: usize,
+    sub: impl Fn([u8; N], [u8; N]) -> [u8; N],
+) {
+    for i in (samples..row.len() / N).rev() {
+        let (previous, current) = row.split_at_mut(i * N);
+        let previous = previous[(i - samples) * N..][..N].try_into().unwrap();
+        let value = sub(current[..N].try_into().unwrap(), previous);
+        current[..N].copy_from_slice(&value);
+    }
+}
+
+/// Shuffles the big-endian bytes of the floats in `row` into planes and replaces each byte by
+/// its difference to the byte of the previous pixel, as undone by the floating point predictor.
+fn predict_floating_point(row: &mut [u8], samples: usize, sample_bytes: usize) {
+    let count = row.len() / sample_bytes;
+    let mut planes = vec![0; row.len()];
+    for (i, sample) in row.chunks_exact(sample_bytes).enumerate() {
+        for (byte, &value) in sample.iter().enumerate() {
+            let plane = if cfg!(target_endian = "little") {
+                sample_bytes - 1 - byte
+            } else {
+                byte
+            };
+            planes[plane * count + i] = value;
+        }
+    }
+    for i in (samples..planes.len()).rev() {
+        planes[i] = planes[i].wrapping_sub(planes[i - samples]);
+    }
+    row.copy_from_slice(&planes);
+}
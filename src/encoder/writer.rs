@@ -1,5 +1,8 @@
 use crate::encoder::compression::*;
+use crate::encoder::pool::CompressionPool;
 use crate::error::TiffResult;
+use crate::progress::{Progress, ProgressCallback};
+use std::convert::TryFrom;
 use std::io::{self, Seek, SeekFrom, Write};
 
 pub fn write_tiff_header<W: Write>(writer: &mut TiffWriter<W>) -> TiffResult<()> {
@@ -45,6 +48,12 @@ pub struct TiffWriter<W> {
     offset: u64,
     byte_count: u64,
     compressor: Compressor,
+    progress: Option<ProgressCallback>,
+    // The position of the pointer to link the next directory to, if it is not the last word
+    // written, such as the end of the chain in an appended file.
+    ifd_pointer_pos: Option<u64>,
+    // The threads that compressed the chunks of a previous image, if any.
+    pool: Option<CompressionPool>,
 }
 
 impl<W: Write> TiffWriter<W> {
@@ -54,6 +63,9 @@ impl<W: Write> TiffWriter<W> {
             offset: 0,
             byte_count: 0,
             compressor: Compressor::default(),
+            progress: None,
+            ifd_pointer_pos: None,
+            pool: None,
         }
     }
 
@@ -65,10 +77,49 @@ impl<W: Write> TiffWriter<W> {
         self.compressor = Compressor::default();
     }
 
+    pub(crate) fn set_chunk_layout(&mut self, layout: ChunkLayout) {
+        self.compressor.set_chunk_layout(layout);
+    }
+
+    pub(crate) fn set_progress(&mut self, progress: ProgressCallback) {
+        self.progress = Some(progress);
+    }
+
+    pub(crate) fn report_progress(&mut self, progress: Progress) {
+        if let Some(callback) = &mut self.progress {
+            callback.report(progress);
+        }
+    }
+
+    pub(crate) fn set_ifd_pointer_pos(&mut self, pos: u64) {
+        self.ifd_pointer_pos = Some(pos);
+    }
+
+    pub(crate) fn take_ifd_pointer_pos(&mut self) -> Option<u64> {
+        self.ifd_pointer_pos.take()
+    }
+
+    /// Take the pool of compression threads, which is only reused if it has `threads` threads.
+    pub(crate) fn take_pool(&mut self, threads: usize) -> CompressionPool {
+        match self.pool.take() {
+            Some(pool) if pool.threads() == threads => pool,
+            _ => CompressionPool::new(threads),
+        }
+    }
+
+    /// Keep the pool of compression threads for later images.
+    pub(crate) fn put_pool(&mut self, pool: CompressionPool) {
+        self.pool = Some(pool);
+    }
+
     pub fn offset(&self) -> u64 {
         self.offset
     }
 
+    pub(crate) fn into_inner(self) -> W {
+        self.writer
+    }
+
     pub fn last_written(&self) -> u64 {
         self.byte_count
     }
@@ -186,3 +237,107 @@ impl<W: Seek> TiffWriter<W> {
         Ok(())
     }
 }
+
+/// Adapter to encode to sinks that can not seek, such as pipes, sockets or compressing writers.
+///
+/// Data is held back in memory until it is final. The encoder only seeks back to link a new
+/// directory into the chain of directories, after which it returns to the end of the data, so
+/// everything before either position is final. This holds back the data of each image until its
+/// directory is written.
+///
+/// Any remaining data is written out when this is dropped, but errors can only be noticed with
+/// [`into_inner`][StreamingWriter::into_inner].
+pub struct StreamingWriter<W: Write> {
+    writer: Option<W>,
+    buffer: Vec<u8>,
+    // The offset of the first byte of `buffer`, before which everything was written out.
+    start: u64,
+    position: u64,
+}
+
+impl<W: Write> StreamingWriter<W> {
+    pub fn new(writer: W) -> Self {
+        StreamingWriter {
+            writer: Some(writer),
+            buffer: Vec::new(),
+            start: 0,
+            position: 0,
+        }
+    }
+
+    /// Writes out all data and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.write_out(self.end())?;
+        let mut writer = self.writer.take().unwrap();
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.buffer.len() as u64
+    }
+
+    /// Writes out the data before `offset`, which is final.
+    fn write_out(&mut self, offset: u64) -> io::Result<()> {
+        let len = (offset.min(self.end()) - self.start) as usize;
+        self.writer
+            .as_mut()
+            .unwrap()
+            .write_all(&self.buffer[..len])?;
+        self.buffer.drain(..len);
+        self.start += len as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for StreamingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let offset = (self.position - self.start) as usize;
+        if offset > self.buffer.len() {
+            self.buffer.resize(offset, 0);
+        }
+        let overlap = (self.buffer.len() - offset).min(buf.len());
+        self.buffer[offset..offset + overlap].copy_from_slice(&buf[..overlap]);
+        self.buffer.extend_from_slice(&buf[overlap..]);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Seek for StreamingWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = self.end();
+        let target = match pos {
+            SeekFrom::Start(offset) => i128::from(offset),
+            SeekFrom::End(offset) => i128::from(end) + i128::from(offset),
+            SeekFrom::Current(offset) => i128::from(self.position) + i128::from(offset),
+        };
+        let target = u64::try_from(target)
+            .ok()
+            .filter(|&target| target >= self.start)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Cannot seek to data that was already written out",
+                )
+            })?;
+
+        if target < self.position || (target == end && target > self.position) {
+            self.write_out(target)?;
+        }
+        self.position = target;
+        Ok(target)
+    }
+}
+
+impl<W: Write> Drop for StreamingWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_out(self.end());
+        }
+    }
+}
@@ -10,7 +10,7 @@ use jpeg::UnsupportedFeature;
 
 use crate::decoder::{ifd::Value, ChunkType};
 use crate::tags::{
-    CompressionMethod, PhotometricInterpretation, PlanarConfiguration, SampleFormat, Tag,
+    CompressionMethod, PhotometricInterpretation, PlanarConfiguration, Predictor, SampleFormat, Tag,
 };
 use crate::ColorType;
 
@@ -35,8 +35,8 @@ pub enum TiffError {
     /// limits of the platform size or limits of the format.
     IntSizeError,
 
-    /// An error occured in one of the encoder threads.
-    ThreadError,
+    /// Writing a chunk of an image failed, see [`ChunkError`].
+    ChunkError(ChunkError),
 
     /// The image does not support the requested operation
     UsageError(UsageError),
@@ -154,9 +154,12 @@ pub enum TiffUnsupportedError {
     UnsupportedColorType(ColorType),
     UnsupportedBitsPerChannel(u8),
     UnsupportedPlanarConfig(Option<PlanarConfiguration>),
+    UnsupportedPredictor(Predictor, Vec<SampleFormat>),
     UnsupportedDataType,
     UnsupportedInterpretation(PhotometricInterpretation),
     UnsupportedJpegFeature(UnsupportedFeature),
+    UnsupportedLercVersion(i32),
+    NonNativeByteOrder,
 }
 
 impl fmt::Display for TiffUnsupportedError {
@@ -201,6 +204,11 @@ impl fmt::Display for TiffUnsupportedError {
             UnsupportedPlanarConfig(config) => {
                 write!(fmt, "Unsupported planar configuration “{:?}”.", config)
             }
+            UnsupportedPredictor(predictor, ref formats) => write!(
+                fmt,
+                "Predictor {:?} for samples {:?} is unsupported.",
+                predictor, formats
+            ),
             UnsupportedDataType => write!(fmt, "Unsupported data type."),
             UnsupportedInterpretation(interpretation) => {
                 write!(
@@ -212,6 +220,15 @@ impl fmt::Display for TiffUnsupportedError {
             UnsupportedJpegFeature(ref unsupported_feature) => {
                 write!(fmt, "Unsupported JPEG feature {:?}", unsupported_feature)
             }
+            UnsupportedLercVersion(version) => {
+                write!(fmt, "LERC blob version {} is unsupported.", version)
+            }
+            NonNativeByteOrder => {
+                write!(
+                    fmt,
+                    "Encoding to a file in non-native byte order is unsupported."
+                )
+            }
         }
     }
 }
@@ -223,6 +240,10 @@ impl fmt::Display for TiffUnsupportedError {
 pub enum UsageError {
     InvalidChunkType(ChunkType, ChunkType),
     InvalidChunkIndex(u32),
+    /// The file is of another kind than the encoder or editor.
+    InvalidTiffKind {
+        file_is_bigtiff: bool,
+    },
 }
 
 impl fmt::Display for UsageError {
@@ -237,6 +258,73 @@ impl fmt::Display for UsageError {
                 )
             }
             InvalidChunkIndex(index) => write!(fmt, "Image chunk index ({}) requested.", index),
+            InvalidTiffKind { file_is_bigtiff } => {
+                if file_is_bigtiff {
+                    write!(fmt, "BigTiff file requires a BigTiff encoder.")
+                } else {
+                    write!(fmt, "Standard Tiff file requires a standard Tiff encoder.")
+                }
+            }
+        }
+    }
+}
+
+/// Writing the chunk at `index` of an image from a stream failed.
+#[derive(Debug)]
+pub struct ChunkError {
+    pub index: u64,
+    pub kind: ChunkErrorKind,
+}
+
+/// The cause of a [`ChunkError`].
+///
+/// The list of variants may grow to incorporate errors of future features. Matching against this
+/// exhaustively is not covered by interface stability guarantees.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ChunkErrorKind {
+    /// The stream yielded an error instead of the chunk.
+    Stream(Box<dyn Error + Send + Sync>),
+    /// Compressing the chunk failed.
+    Compression(io::Error),
+    /// The task that compressed the chunk panicked or was cancelled.
+    Task(Box<dyn Error + Send + Sync>),
+    /// The chunk does not have the bytes of a full strip or padded tile.
+    InvalidSize { expected: u64, actual: u64 },
+    /// The stream yielded more chunks than the image has.
+    Unexpected,
+    /// The stream ended before the chunk.
+    Missing,
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::ChunkErrorKind::*;
+        match self.kind {
+            Stream(ref e) => write!(fmt, "The stream failed at chunk {}: {}", self.index, e),
+            Compression(ref e) => write!(fmt, "Compressing chunk {} failed: {}", self.index, e),
+            InvalidSize { expected, actual } => write!(
+                fmt,
+                "Chunk {} has {} bytes instead of {}",
+                self.index, actual, expected
+            ),
+            Unexpected => write!(fmt, "The image has no chunk {}", self.index),
+            Missing => write!(fmt, "The stream ended before chunk {}", self.index),
+            Task(ref e) => write!(
+                fmt,
+                "The task compressing chunk {} failed: {}",
+                self.index, e
+            ),
+        }
+    }
+}
+
+impl Error for ChunkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind {
+            ChunkErrorKind::Stream(ref e) | ChunkErrorKind::Task(ref e) => Some(&**e),
+            ChunkErrorKind::Compression(ref e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -254,7 +342,7 @@ impl fmt::Display for TiffError {
             TiffError::IoError(ref e) => e.fmt(fmt),
             TiffError::LimitsExceeded => write!(fmt, "The Decoder limits are exceeded"),
             TiffError::IntSizeError => write!(fmt, "Platform or format size limits exceeded"),
-            TiffError::ThreadError => write!(fmt, "An error occured in one of the encoder threads"),
+            TiffError::ChunkError(ref e) => e.fmt(fmt),
             TiffError::UsageError(ref e) => write!(fmt, "Usage error: {}", e),
         }
     }
@@ -268,7 +356,7 @@ impl Error for TiffError {
             TiffError::IoError(..) => "IO error",
             TiffError::LimitsExceeded => "Decoder limits exceeded",
             TiffError::IntSizeError => "Platform or format size limits exceeded",
-            TiffError::ThreadError => "An error occured in one of the encoder threads",
+            TiffError::ChunkError(..) => "Chunk error",
             TiffError::UsageError(..) => "Invalid usage",
         }
     }
@@ -276,6 +364,7 @@ impl Error for TiffError {
     fn cause(&self) -> Option<&dyn Error> {
         match *self {
             TiffError::IoError(ref e) => Some(e),
+            TiffError::ChunkError(ref e) => Some(e),
             _ => None,
         }
     }
@@ -0,0 +1,1103 @@
+//! Limited Error Raster Compression (LERC) of the samples of a chunk, in the LERC2 blob format.
+//!
+//! Blobs of versions 1 to 4 are decoded. Encoding writes version 3 blobs, or version 4 blobs for
+//! more than one sample per pixel, and splits the raster into micro blocks of 8×8 pixels.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::ops::Range;
+
+use crate::tags::SampleFormat;
+use crate::{TiffError, TiffFormatError, TiffResult, TiffUnsupportedError};
+
+const FILE_KEY: &[u8] = b"Lerc2 ";
+const MAX_VERSION: i32 = 4;
+/// The checksum covers everything after the file key, version and checksum itself.
+const CHECKSUM_START: usize = 14;
+const MICRO_BLOCK_SIZE: usize = 8;
+/// The number of bits that Huffman codes are looked up with in a table.
+const HUFFMAN_TABLE_BITS: u32 = 12;
+
+/// The type of the values in a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DataType {
+    Char,
+    Byte,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl DataType {
+    fn from_i32(n: i32) -> Option<Self> {
+        Some(match n {
+            0 => DataType::Char,
+            1 => DataType::Byte,
+            2 => DataType::Short,
+            3 => DataType::UShort,
+            4 => DataType::Int,
+            5 => DataType::UInt,
+            6 => DataType::Float,
+            7 => DataType::Double,
+            _ => return None,
+        })
+    }
+
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+
+    /// The data type that holds samples of a format and bit depth.
+    pub(crate) fn from_sample(format: SampleFormat, bits: u16) -> Option<Self> {
+        Some(match (format, bits) {
+            (SampleFormat::Int, 8) => DataType::Char,
+            (SampleFormat::Uint, 8) => DataType::Byte,
+            (SampleFormat::Int, 16) => DataType::Short,
+            (SampleFormat::Uint, 16) => DataType::UShort,
+            (SampleFormat::Int, 32) => DataType::Int,
+            (SampleFormat::Uint, 32) => DataType::UInt,
+            (SampleFormat::IEEEFP, 32) => DataType::Float,
+            (SampleFormat::IEEEFP, 64) => DataType::Double,
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn byte_len(self) -> usize {
+        match self {
+            DataType::Char | DataType::Byte => 1,
+            DataType::Short | DataType::UShort => 2,
+            DataType::Int | DataType::UInt | DataType::Float => 4,
+            DataType::Double => 8,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(self, DataType::Float | DataType::Double)
+    }
+
+    /// The largest quantized value that tiles are bit stuffed with.
+    fn max_quantized(self) -> f64 {
+        match self {
+            DataType::Char | DataType::Byte | DataType::Short | DataType::UShort => {
+                f64::from((1 << 15) - 1)
+            }
+            _ => f64::from((1 << 30) - 1),
+        }
+    }
+
+    /// The type that holds the offset of a tile, given the two bits that select it.
+    fn reduced(self, code: u8) -> Option<Self> {
+        let n = self.to_i32();
+        let code = i32::from(code);
+        match self {
+            DataType::Short | DataType::Int => DataType::from_i32(n - code),
+            DataType::UShort | DataType::UInt => DataType::from_i32(n - 2 * code),
+            DataType::Float => match code {
+                0 => Some(DataType::Float),
+                1 => Some(DataType::Short),
+                _ => Some(DataType::Byte),
+            },
+            DataType::Double => match code {
+                0 => Some(DataType::Double),
+                _ => DataType::from_i32(n - 2 * code + 1),
+            },
+            DataType::Char | DataType::Byte => Some(self),
+        }
+    }
+
+    /// Reads a value from the start of `bytes`.
+    pub(crate) fn read(self, bytes: &[u8], big_endian: bool) -> f64 {
+        macro_rules! read {
+            ($ty:ty) => {{
+                const LEN: usize = std::mem::size_of::<$ty>();
+                let mut buf = [0; LEN];
+                buf.copy_from_slice(&bytes[..LEN]);
+                if big_endian {
+                    <$ty>::from_be_bytes(buf)
+                } else {
+                    <$ty>::from_le_bytes(buf)
+                }
+            }};
+        }
+
+        match self {
+            DataType::Char => f64::from(read!(i8)),
+            DataType::Byte => f64::from(read!(u8)),
+            DataType::Short => f64::from(read!(i16)),
+            DataType::UShort => f64::from(read!(u16)),
+            DataType::Int => f64::from(read!(i32)),
+            DataType::UInt => f64::from(read!(u32)),
+            DataType::Float => f64::from(read!(f32)),
+            DataType::Double => read!(f64),
+        }
+    }
+
+    /// Appends a value, which is truncated to the data type.
+    pub(crate) fn write(self, value: f64, big_endian: bool, out: &mut Vec<u8>) {
+        macro_rules! write {
+            ($value:expr) => {{
+                let value = $value;
+                if big_endian {
+                    out.extend_from_slice(&value.to_be_bytes())
+                } else {
+                    out.extend_from_slice(&value.to_le_bytes())
+                }
+            }};
+        }
+
+        match self {
+            DataType::Char => write!(value as i8),
+            DataType::Byte => write!(value as u8),
+            DataType::Short => write!(value as i16),
+            DataType::UShort => write!(value as u16),
+            DataType::Int => write!(value as i32),
+            DataType::UInt => write!(value as u32),
+            DataType::Float => write!(value as f32),
+            DataType::Double => write!(value),
+        }
+    }
+}
+
+/// The decoded values of a blob, with the samples of each pixel next to each other.
+#[derive(Debug)]
+pub(crate) struct Raster {
+    pub data_type: DataType,
+    pub depth: usize,
+    pub values: Vec<f64>,
+    /// The bits that mark the valid pixels, starting at the most significant bit of each byte, if
+    /// not all pixels are valid.
+    pub mask: Option<Vec<u8>>,
+}
+
+impl Raster {
+    fn is_valid(&self, pixel: usize) -> bool {
+        match &self.mask {
+            Some(mask) => mask[pixel / 8] & (0x80 >> (pixel % 8)) != 0,
+            None => true,
+        }
+    }
+
+    /// Sets the samples of the pixels that the blob masks out to NaN.
+    pub(crate) fn mask_with_nan(&mut self) {
+        if self.mask.is_none() {
+            return;
+        }
+        for pixel in 0..self.values.len() / self.depth {
+            if !self.is_valid(pixel) {
+                let start = pixel * self.depth;
+                self.values[start..start + self.depth].fill(f64::NAN);
+            }
+        }
+    }
+
+    /// Appends a sample to each pixel that is `opaque` if the pixel is valid and zero otherwise.
+    pub(crate) fn mask_to_alpha(&mut self, opaque: f64) {
+        let pixels = self.values.len() / self.depth;
+        let mut values = Vec::with_capacity(pixels * (self.depth + 1));
+        for pixel in 0..pixels {
+            let start = pixel * self.depth;
+            values.extend_from_slice(&self.values[start..start + self.depth]);
+            values.push(if self.is_valid(pixel) { opaque } else { 0.0 });
+        }
+        self.values = values;
+        self.depth += 1;
+        self.mask = None;
+    }
+
+    /// The values in their data type. Pixels that the blob masks out are zero.
+    pub(crate) fn into_bytes(self, big_endian: bool) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.values.len() * self.data_type.byte_len());
+        for &value in &self.values {
+            self.data_type.write(value, big_endian, &mut bytes);
+        }
+        bytes
+    }
+}
+
+fn invalid(reason: &str) -> TiffError {
+    TiffError::FormatError(TiffFormatError::Format(format!("LERC blob {}", reason)))
+}
+
+/// The Fletcher-32 checksum over the bytes of a blob, processed as big endian words.
+fn fletcher32(bytes: &[u8]) -> u32 {
+    let (mut sum1, mut sum2) = (0xffffu32, 0xffffu32);
+    let (words, straggler) = bytes.split_at(bytes.len() & !1);
+    for block in words.chunks(718) {
+        for word in block.chunks_exact(2) {
+            sum1 = sum1.wrapping_add(u32::from(word[0]) << 8 | u32::from(word[1]));
+            sum2 = sum2.wrapping_add(sum1);
+        }
+        sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+        sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+    }
+    if let Some(&byte) = straggler.first() {
+        sum1 = sum1.wrapping_add(u32::from(byte) << 8);
+        sum2 = sum2.wrapping_add(sum1);
+    }
+    sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+    sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+    sum2 << 16 | sum1
+}
+
+struct BlobReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BlobReader<'a> {
+    fn bytes(&mut self, len: usize) -> TiffResult<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| invalid("is truncated"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> TiffResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> TiffResult<i16> {
+        let bytes = self.bytes(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> TiffResult<i32> {
+        Ok(self.value(DataType::Int)? as i32)
+    }
+
+    fn u32(&mut self) -> TiffResult<u32> {
+        Ok(self.value(DataType::UInt)? as u32)
+    }
+
+    fn f64(&mut self) -> TiffResult<f64> {
+        self.value(DataType::Double)
+    }
+
+    fn value(&mut self, data_type: DataType) -> TiffResult<f64> {
+        Ok(data_type.read(self.bytes(data_type.byte_len())?, false))
+    }
+
+    /// Reads a count of `len` bytes, as selected by the two high bits of a bit stuffing header.
+    fn count(&mut self, code: u8) -> TiffResult<usize> {
+        Ok(match code {
+            0 => self.u32()? as usize,
+            1 => self.value(DataType::UShort)? as usize,
+            2 => usize::from(self.u8()?),
+            _ => return Err(invalid("has an invalid element count")),
+        })
+    }
+
+    /// Reads `count` values of `bits` each, packed as done by the given blob version.
+    fn unstuff(&mut self, count: usize, bits: u32, version: i32) -> TiffResult<Vec<u32>> {
+        if bits == 0 {
+            return Ok(vec![0; count]);
+        }
+        let total_bits = count
+            .checked_mul(bits as usize)
+            .ok_or_else(|| invalid("is too large"))?;
+        let bytes = self.bytes((total_bits + 7) / 8)?;
+
+        let mut values = Vec::with_capacity(count);
+        if version >= 3 {
+            // Values are packed starting at the least significant bit.
+            let (mut acc, mut acc_bits, mut bytes) = (0u64, 0, bytes.iter());
+            for _ in 0..count {
+                while acc_bits < bits {
+                    acc |= u64::from(*bytes.next().unwrap()) << acc_bits;
+                    acc_bits += 8;
+                }
+                values.push((acc & ((1 << bits) - 1)) as u32);
+                acc >>= bits;
+                acc_bits -= bits;
+            }
+        } else {
+            // Values are packed into little endian words starting at the most significant bit,
+            // and the unused low bytes of the last word are left out.
+            let words: Vec<u32> = bytes
+                .chunks(4)
+                .map(|word| {
+                    let missing = 4 - word.len();
+                    let value = word.iter().rev().fold(0, |v, &b| v << 8 | u32::from(b));
+                    value << (8 * missing)
+                })
+                .collect();
+            for i in 0..count {
+                let start = i * bits as usize;
+                let (word, offset) = (start / 32, start % 32);
+                let pair =
+                    u64::from(words[word]) << 32 | u64::from(*words.get(word + 1).unwrap_or(&0));
+                values.push((pair << offset >> (64 - bits)) as u32);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Reads a bit stuffed array of at most `max_count` values, which may go through a lookup
+    /// table of distinct values.
+    fn bit_stuffed(&mut self, max_count: usize, version: i32) -> TiffResult<Vec<u32>> {
+        let header = self.u8()?;
+        let bits = u32::from(header & 31);
+        let count = self.count(header >> 6)?;
+        if count > max_count {
+            return Err(invalid("has too many elements"));
+        }
+
+        if header & 32 == 0 {
+            return self.unstuff(count, bits, version);
+        }
+        if bits == 0 {
+            return Err(invalid("has an empty lookup table"));
+        }
+        let lut_len = usize::from(self.u8()?)
+            .checked_sub(1)
+            .filter(|&len| len > 0)
+            .ok_or_else(|| invalid("has an empty lookup table"))?;
+        let mut lut = vec![0];
+        lut.extend(self.unstuff(lut_len, bits, version)?);
+        let index_bits = usize::BITS - lut_len.leading_zeros();
+        self.unstuff(count, index_bits, version)?
+            .into_iter()
+            .map(|index| lut.get(index as usize).copied())
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid("has an invalid lookup index"))
+    }
+}
+
+/// Reads the bits of little endian words, starting at the most significant bit of each.
+struct WordBits<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl WordBits<'_> {
+    fn bits(&mut self, len: u32) -> TiffResult<u32> {
+        let mut value = 0;
+        for _ in 0..len {
+            let start = self.position / 32 * 4;
+            let word = self
+                .data
+                .get(start..start + 4)
+                .ok_or_else(|| invalid("is truncated"))?;
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            value = value << 1 | (word >> (31 - self.position % 32)) & 1;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    /// The next `len` bits, between 1 and 32, without reading them. Bits past the end are zero.
+    fn peek(&self, len: u32) -> u32 {
+        let word = |index: usize| {
+            self.data.get(index * 4..index * 4 + 4).map_or(0, |w| {
+                u64::from(u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            })
+        };
+        let index = self.position / 32;
+        let pair = word(index) << 32 | word(index + 1);
+        (pair << (self.position % 32) >> (64 - len)) as u32
+    }
+
+    fn skip(&mut self, len: u32) -> TiffResult<()> {
+        self.position += len as usize;
+        if self.position > self.data.len() / 4 * 32 {
+            return Err(invalid("is truncated"));
+        }
+        Ok(())
+    }
+
+    /// The number of bytes of the words that have been read from.
+    fn byte_len(&self) -> usize {
+        (self.position + 31) / 32 * 4
+    }
+}
+
+/// The values of a blob that are being decoded, along with the pixels that hold values.
+struct RasterDecoder<'a> {
+    reader: BlobReader<'a>,
+    version: i32,
+    width: usize,
+    height: usize,
+    depth: usize,
+    block: usize,
+    data_type: DataType,
+    max_z_error: f64,
+    z_max: Vec<f64>,
+    mask: Option<Vec<u8>>,
+    values: Vec<f64>,
+}
+
+impl RasterDecoder<'_> {
+    fn is_valid(&self, pixel: usize) -> bool {
+        match &self.mask {
+            Some(mask) => mask[pixel / 8] & (0x80 >> (pixel % 8)) != 0,
+            None => true,
+        }
+    }
+
+    /// Reads the run length encoded bits that mark the valid pixels.
+    fn read_mask(&mut self, num_valid: usize) -> TiffResult<()> {
+        let len =
+            usize::try_from(self.reader.i32()?).map_err(|_| invalid("has an invalid mask"))?;
+        let mut reader = BlobReader {
+            data: self.reader.bytes(len)?,
+            position: 0,
+        };
+
+        let pixels = self.width * self.height;
+        let mask_len = (pixels + 7) / 8;
+        if num_valid == pixels {
+            return Ok(());
+        } else if num_valid == 0 {
+            self.mask = Some(vec![0; mask_len]);
+            return Ok(());
+        }
+
+        let mut mask = Vec::with_capacity(mask_len);
+        loop {
+            match reader.i16()? {
+                -32768 => break,
+                count if count > 0 => mask.extend_from_slice(reader.bytes(count as usize)?),
+                count => {
+                    let byte = reader.u8()?;
+                    mask.extend(std::iter::repeat(byte).take(usize::from(count.unsigned_abs())));
+                }
+            }
+            if mask.len() > mask_len {
+                break;
+            }
+        }
+        if mask.len() != mask_len {
+            return Err(invalid("has a mask of the wrong size"));
+        }
+        self.mask = Some(mask);
+        Ok(())
+    }
+
+    /// Sets the samples of all valid pixels to a constant for each sample.
+    fn fill(&mut self, z: &[f64]) {
+        for pixel in 0..self.width * self.height {
+            if self.is_valid(pixel) {
+                let start = pixel * self.depth;
+                self.values[start..start + self.depth].copy_from_slice(z);
+            }
+        }
+    }
+
+    fn read_values(&mut self, num_valid: usize, z_min: f64, z_max: f64) -> TiffResult<()> {
+        if num_valid == 0 {
+            return Ok(());
+        } else if z_min == z_max {
+            self.fill(&vec![z_min; self.depth]);
+            return Ok(());
+        }
+
+        if self.version >= 4 {
+            let mut ranges = Vec::with_capacity(2 * self.depth);
+            for _ in 0..2 * self.depth {
+                ranges.push(self.reader.value(self.data_type)?);
+            }
+            let (z_min, z_max) = ranges.split_at(self.depth);
+            if z_min == z_max {
+                self.fill(z_min);
+                return Ok(());
+            }
+            self.z_max = z_max.to_vec();
+        }
+
+        let one_sweep = self.reader.u8()? != 0;
+        if one_sweep {
+            for pixel in 0..self.width * self.height {
+                if self.is_valid(pixel) {
+                    for dim in 0..self.depth {
+                        self.values[pixel * self.depth + dim] =
+                            self.reader.value(self.data_type)?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        // Lossless 8-bit blobs may be Huffman coded instead of split into micro blocks.
+        if self.version > 1
+            && matches!(self.data_type, DataType::Char | DataType::Byte)
+            && self.max_z_error == 0.5
+        {
+            match self.reader.u8()? {
+                0 => {}
+                1 => return self.read_huffman(true),
+                2 if self.version >= 4 => return self.read_huffman(false),
+                _ => return Err(invalid("has an unknown encoding mode")),
+            }
+        }
+
+        for i0 in (0..self.height).step_by(self.block) {
+            let i1 = (i0 + self.block).min(self.height);
+            for j0 in (0..self.width).step_by(self.block) {
+                let j1 = (j0 + self.block).min(self.width);
+                for dim in 0..self.depth {
+                    self.read_tile(i0..i1, j0..j1, dim)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_tile(
+        &mut self,
+        rows: Range<usize>,
+        columns: Range<usize>,
+        dim: usize,
+    ) -> TiffResult<()> {
+        let flags = self.reader.u8()?;
+        // Bits 2 to 5 repeat part of the tile position as an integrity check.
+        if usize::from(flags >> 2 & 15) != (columns.start >> 3) & 15 {
+            return Err(invalid("has a corrupted micro block"));
+        }
+
+        let width = self.width;
+        let samples: Vec<usize> = rows
+            .flat_map(|row| columns.clone().map(move |column| row * width + column))
+            .filter(|&pixel| self.is_valid(pixel))
+            .map(|pixel| pixel * self.depth + dim)
+            .collect();
+
+        match flags & 3 {
+            // All samples are zero.
+            2 => samples.iter().for_each(|&i| self.values[i] = 0.0),
+            // All samples are stored without compression.
+            0 => {
+                for &i in &samples {
+                    self.values[i] = self.reader.value(self.data_type)?;
+                }
+            }
+            mode => {
+                let offset_type = self
+                    .data_type
+                    .reduced(flags >> 6)
+                    .ok_or_else(|| invalid("has an invalid micro block offset"))?;
+                let offset = self.reader.value(offset_type)?;
+                if mode == 3 {
+                    samples.iter().for_each(|&i| self.values[i] = offset);
+                    return Ok(());
+                }
+
+                let quantized = self.reader.bit_stuffed(samples.len(), self.version)?;
+                if quantized.len() != samples.len() {
+                    return Err(invalid("has a micro block of the wrong size"));
+                }
+                let (scale, z_max) = (2.0 * self.max_z_error, self.z_max[dim]);
+                for (&i, &q) in samples.iter().zip(&quantized) {
+                    let z = offset + f64::from(q) * scale;
+                    self.values[i] = if z_max < z { z_max } else { z };
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads 8-bit samples that are Huffman coded, either directly or as the difference to a
+    /// neighboring sample.
+    fn read_huffman(&mut self, delta: bool) -> TiffResult<()> {
+        let version = self.reader.i32()?;
+        let size = self.reader.i32()?;
+        let (i0, i1) = (self.reader.i32()?, self.reader.i32()?);
+        if version < 2 || size <= 0 || size > 1 << 15 || i0 < 0 || i0 >= i1 || i1 > 2 * size {
+            return Err(invalid("has an invalid Huffman code table"));
+        }
+
+        // Code lengths are bit stuffed, followed by the codes themselves.
+        let symbols = (i0 as usize..i1 as usize).map(|i| (i % size as usize) as u32);
+        let lengths = self.reader.bit_stuffed((i1 - i0) as usize, self.version)?;
+        if lengths.len() != (i1 - i0) as usize || lengths.iter().any(|&len| len > 32) {
+            return Err(invalid("has an invalid Huffman code table"));
+        }
+        let data = &self.reader.data[self.reader.position..];
+        let mut bits = WordBits { data, position: 0 };
+        // Codes of up to `HUFFMAN_TABLE_BITS` are looked up by the bits they start with, and
+        // the rare longer ones by their length and value.
+        let mut table = vec![(0, 0); 1 << HUFFMAN_TABLE_BITS];
+        let mut filled = 0;
+        let mut long_codes = HashMap::new();
+        for (symbol, &len) in symbols.zip(&lengths) {
+            if len == 0 {
+                continue;
+            }
+            let code = bits.bits(len)?;
+            if len > HUFFMAN_TABLE_BITS {
+                long_codes.insert((len, code), symbol);
+                continue;
+            }
+            let shift = HUFFMAN_TABLE_BITS - len;
+            let start = (code << shift) as usize;
+            filled += 1 << shift;
+            if filled > table.len() {
+                return Err(invalid("has an invalid Huffman code table"));
+            }
+            table[start..start + (1 << shift)].fill((len, symbol));
+        }
+        let mut bits = WordBits {
+            data: &data[bits.byte_len()..],
+            position: 0,
+        };
+        let mut next = || -> TiffResult<i32> {
+            let (len, symbol) = table[bits.peek(HUFFMAN_TABLE_BITS) as usize];
+            if len > 0 {
+                bits.skip(len)?;
+                return Ok(symbol as i32);
+            }
+            if !long_codes.is_empty() {
+                for len in HUFFMAN_TABLE_BITS + 1..=32 {
+                    if let Some(&symbol) = long_codes.get(&(len, bits.peek(len))) {
+                        bits.skip(len)?;
+                        return Ok(symbol as i32);
+                    }
+                }
+            }
+            Err(invalid("has an invalid Huffman code"))
+        };
+
+        // Samples wrap around like their 8-bit type.
+        let (offset, wrap): (i32, fn(i32) -> f64) = match self.data_type {
+            DataType::Char => (128, |v| f64::from(v as i8)),
+            _ => (0, |v| f64::from(v as u8)),
+        };
+        let (width, depth) = (self.width, self.depth);
+        if delta {
+            for dim in 0..depth {
+                let mut previous = 0;
+                for pixel in 0..width * self.height {
+                    if !self.is_valid(pixel) {
+                        continue;
+                    }
+                    let (row, column) = (pixel / width, pixel % width);
+                    let base = if column > 0 && self.is_valid(pixel - 1) {
+                        previous
+                    } else if row > 0 && self.is_valid(pixel - width) {
+                        self.values[(pixel - width) * depth + dim] as i32
+                    } else {
+                        previous
+                    };
+                    let value = wrap(next()? - offset + base);
+                    self.values[pixel * depth + dim] = value;
+                    previous = value as i32;
+                }
+            }
+        } else {
+            for pixel in 0..width * self.height {
+                if self.is_valid(pixel) {
+                    for dim in 0..depth {
+                        self.values[pixel * depth + dim] = wrap(next()? - offset);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a blob that holds at most `max_values` samples.
+pub(crate) fn decode(blob: &[u8], max_values: usize) -> TiffResult<Raster> {
+    let mut reader = BlobReader {
+        data: blob,
+        position: 0,
+    };
+    if reader.bytes(FILE_KEY.len()).ok() != Some(FILE_KEY) {
+        return Err(invalid("signature not found"));
+    }
+    let version = reader.i32()?;
+    if !(1..=MAX_VERSION).contains(&version) {
+        return Err(TiffError::UnsupportedError(
+            TiffUnsupportedError::UnsupportedLercVersion(version),
+        ));
+    }
+    let checksum = if version >= 3 {
+        Some(reader.u32()?)
+    } else {
+        None
+    };
+
+    let height = reader.i32()?;
+    let width = reader.i32()?;
+    let depth = if version >= 4 { reader.i32()? } else { 1 };
+    let num_valid = reader.i32()?;
+    let block = reader.i32()?;
+    let blob_size = reader.i32()?;
+    let data_type =
+        DataType::from_i32(reader.i32()?).ok_or_else(|| invalid("has an unknown data type"))?;
+    let max_z_error = reader.f64()?;
+    let z_min = reader.f64()?;
+    let z_max = reader.f64()?;
+
+    let positive = |n: i32| usize::try_from(n).ok().filter(|&n| n > 0);
+    let (width, height, depth, block) = match (
+        positive(width),
+        positive(height),
+        positive(depth),
+        positive(block),
+    ) {
+        (Some(width), Some(height), Some(depth), Some(block)) => (width, height, depth, block),
+        _ => return Err(invalid("has invalid dimensions")),
+    };
+    let pixels = width.checked_mul(height);
+    let len = pixels
+        .and_then(|pixels| pixels.checked_mul(depth))
+        .filter(|&len| len <= max_values)
+        .ok_or_else(|| invalid("is larger than its chunk"))?;
+    let num_valid = usize::try_from(num_valid)
+        .ok()
+        .filter(|&n| Some(n) <= pixels)
+        .ok_or_else(|| invalid("has an invalid number of valid pixels"))?;
+    let blob_size = usize::try_from(blob_size)
+        .ok()
+        .filter(|&size| size >= reader.position && size <= blob.len())
+        .ok_or_else(|| invalid("is truncated"))?;
+    if let Some(checksum) = checksum {
+        if fletcher32(&blob[CHECKSUM_START..blob_size]) != checksum {
+            return Err(invalid("checksum does not match"));
+        }
+    }
+    reader.data = &blob[..blob_size];
+
+    let mut decoder = RasterDecoder {
+        reader,
+        version,
+        width,
+        height,
+        depth,
+        block,
+        data_type,
+        max_z_error,
+        z_max: vec![z_max; depth],
+        mask: None,
+        values: vec![0.0; len],
+    };
+    decoder.read_mask(num_valid)?;
+    decoder.read_values(num_valid, z_min, z_max)?;
+
+    Ok(Raster {
+        data_type,
+        depth,
+        values: decoder.values,
+        mask: decoder.mask,
+    })
+}
+
+/// Appends values of at most 31 bits, packed starting at the least significant bit.
+fn write_bit_stuffed(blob: &mut Vec<u8>, values: &[u32]) {
+    let bits = u32::BITS - values.iter().max().map_or(0, |max| max.leading_zeros());
+    let (code, count_len) = match values.len() {
+        len if len < 1 << 8 => (2, 1),
+        len if len < 1 << 16 => (1, 2),
+        _ => (0, 4),
+    };
+    blob.push(bits as u8 | code << 6);
+    blob.extend_from_slice(&(values.len() as u32).to_le_bytes()[..count_len]);
+
+    let (mut acc, mut acc_bits) = (0u64, 0);
+    for &value in values {
+        acc |= u64::from(value) << acc_bits;
+        acc_bits += bits;
+        while acc_bits >= 8 {
+            blob.push(acc as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
+    }
+    if acc_bits > 0 {
+        blob.push(acc as u8);
+    }
+}
+
+/// Appends a micro block of the samples of one dimension, in the smallest of the encodings.
+fn write_tile(
+    blob: &mut Vec<u8>,
+    samples: &[f64],
+    column: usize,
+    data_type: DataType,
+    max_z_error: f64,
+) {
+    let check = ((column >> 3) & 15) as u8;
+    let has_nan = samples.iter().any(|z| z.is_nan());
+    let z_min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let z_max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    if !has_nan && z_min == 0.0 && z_max == 0.0 {
+        blob.push(2 | check << 2);
+        return;
+    }
+
+    let raw_len = 1 + samples.len() * data_type.byte_len();
+    let scale = 2.0 * max_z_error;
+    if !has_nan && max_z_error > 0.0 && (z_max - z_min) / scale <= data_type.max_quantized() {
+        let mut tile = Vec::new();
+        if z_min == z_max {
+            tile.push(3 | check << 2);
+            data_type.write(z_min, false, &mut tile);
+        } else {
+            tile.push(1 | check << 2);
+            data_type.write(z_min, false, &mut tile);
+            let quantized: Vec<u32> = samples
+                .iter()
+                .map(|z| ((z - z_min) / scale + 0.5) as u32)
+                .collect();
+            write_bit_stuffed(&mut tile, &quantized);
+        }
+
+        if tile.len() < raw_len {
+            blob.extend_from_slice(&tile);
+            return;
+        }
+    }
+
+    blob.push(check << 2);
+    for &z in samples {
+        data_type.write(z, false, blob);
+    }
+}
+
+/// Encodes a raster, with the samples of each pixel next to each other, such that each sample
+/// decodes to within `max_error` of its value.
+pub(crate) fn encode(
+    values: &[f64],
+    width: usize,
+    height: usize,
+    depth: usize,
+    data_type: DataType,
+    max_error: f64,
+) -> io::Result<Vec<u8>> {
+    let to_i32 = |n: usize| {
+        i32::try_from(n)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Raster too large for LERC"))
+    };
+    let pixels = width * height;
+    if values.len() != pixels * depth || depth == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Raster size does not match its values",
+        ));
+    }
+
+    // Integer samples are quantized in whole steps, where an error of 0.5 is lossless.
+    let max_z_error = if data_type.is_integer() {
+        max_error.floor().max(0.5)
+    } else {
+        max_error.max(0.0)
+    };
+    let version: i32 = if depth > 1 { 4 } else { 3 };
+
+    // A NaN maximum keeps decoders from treating the samples as constant, as every comparison
+    // with it fails. Samples with NaN are only stored without compression.
+    let mut z_min = vec![f64::INFINITY; depth];
+    let mut z_max = vec![f64::NEG_INFINITY; depth];
+    let mut has_nan = vec![false; depth];
+    for pixel in values.chunks(depth) {
+        for (dim, &z) in pixel.iter().enumerate() {
+            z_min[dim] = z_min[dim].min(z);
+            z_max[dim] = z_max[dim].max(z);
+            has_nan[dim] |= z.is_nan();
+        }
+    }
+    for (z_max, _) in z_max.iter_mut().zip(&has_nan).filter(|(_, &nan)| nan) {
+        *z_max = f64::NAN;
+    }
+    let total_min = z_min.iter().copied().fold(f64::INFINITY, f64::min);
+    let total_max = if has_nan.contains(&true) {
+        f64::NAN
+    } else {
+        z_max.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+    };
+
+    let mut blob = FILE_KEY.to_vec();
+    blob.extend_from_slice(&version.to_le_bytes());
+    blob.extend_from_slice(&0u32.to_le_bytes());
+    blob.extend_from_slice(&to_i32(height)?.to_le_bytes());
+    blob.extend_from_slice(&to_i32(width)?.to_le_bytes());
+    if version >= 4 {
+        blob.extend_from_slice(&to_i32(depth)?.to_le_bytes());
+    }
+    blob.extend_from_slice(&to_i32(pixels)?.to_le_bytes());
+    blob.extend_from_slice(&to_i32(MICRO_BLOCK_SIZE)?.to_le_bytes());
+    let blob_size_position = blob.len();
+    blob.extend_from_slice(&0i32.to_le_bytes());
+    blob.extend_from_slice(&data_type.to_i32().to_le_bytes());
+    for value in [max_z_error, total_min, total_max] {
+        blob.extend_from_slice(&value.to_le_bytes());
+    }
+    // All pixels are valid, so there is no mask.
+    blob.extend_from_slice(&0i32.to_le_bytes());
+
+    let write_values = |blob: &mut Vec<u8>| {
+        if total_min == total_max {
+            return;
+        }
+        if version >= 4 {
+            for &z in z_min.iter().chain(&z_max) {
+                data_type.write(z, false, blob);
+            }
+            if z_min == z_max {
+                return;
+            }
+        }
+
+        // The samples are split into micro blocks, rather than written in a single sweep.
+        blob.push(0);
+        if matches!(data_type, DataType::Char | DataType::Byte) && max_z_error == 0.5 {
+            // Micro blocks rather than Huffman coding.
+            blob.push(0);
+        }
+
+        let mut samples = Vec::with_capacity(MICRO_BLOCK_SIZE * MICRO_BLOCK_SIZE);
+        for i0 in (0..height).step_by(MICRO_BLOCK_SIZE) {
+            let i1 = (i0 + MICRO_BLOCK_SIZE).min(height);
+            for j0 in (0..width).step_by(MICRO_BLOCK_SIZE) {
+                let j1 = (j0 + MICRO_BLOCK_SIZE).min(width);
+                for dim in 0..depth {
+                    samples.clear();
+                    for row in i0..i1 {
+                        let start = (row * width + j0) * depth + dim;
+                        let end = (row * width + j1) * depth;
+                        samples.extend(values[start..end].iter().step_by(depth));
+                    }
+                    write_tile(blob, &samples, j0, data_type, max_z_error);
+                }
+            }
+        }
+    };
+    write_values(&mut blob);
+
+    let blob_size = to_i32(blob.len())?.to_le_bytes();
+    blob[blob_size_position..blob_size_position + 4].copy_from_slice(&blob_size);
+    let checksum = fletcher32(&blob[CHECKSUM_START..]).to_le_bytes();
+    blob[CHECKSUM_START - 4..CHECKSUM_START].copy_from_slice(&checksum);
+    Ok(blob)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a blob with a single sample per pixel from its header values and the rest.
+    fn blob(
+        version: i32,
+        size: (i32, i32, i32),
+        header: (DataType, [f64; 3]),
+        rest: &[u8],
+    ) -> Vec<u8> {
+        let (height, width, num_valid) = size;
+        let mut blob = FILE_KEY.to_vec();
+        blob.extend_from_slice(&version.to_le_bytes());
+        if version >= 3 {
+            blob.extend_from_slice(&0u32.to_le_bytes());
+        }
+        for n in [height, width, num_valid, 8, 0, header.0.to_i32()] {
+            blob.extend_from_slice(&n.to_le_bytes());
+        }
+        for z in header.1 {
+            blob.extend_from_slice(&z.to_le_bytes());
+        }
+        blob.extend_from_slice(rest);
+
+        let blob_size = (blob.len() as i32).to_le_bytes();
+        let position = blob.len() - rest.len() - 24 - 8;
+        blob[position..position + 4].copy_from_slice(&blob_size);
+        if version >= 3 {
+            let checksum = fletcher32(&blob[CHECKSUM_START..]).to_le_bytes();
+            blob[CHECKSUM_START - 4..CHECKSUM_START].copy_from_slice(&checksum);
+        }
+        blob
+    }
+
+    #[test]
+    fn test_masked_constant() {
+        // Only the first and third pixel are valid.
+        let mask = [5, 0, 0, 0, 1, 0, 0xa0, 0x00, 0x80];
+        let blob = blob(2, (1, 4, 2), (DataType::Byte, [0.5, 7.0, 7.0]), &mask);
+        assert_eq!(decode(&blob, 4).unwrap().values, [7.0, 0.0, 7.0, 0.0]);
+    }
+
+    #[test]
+    fn test_bit_stuffed_versions() {
+        // A micro block with an offset of 10 and the values 1, 2 and 3 in two bits each, which
+        // older versions pack starting at the most significant bit.
+        for (version, packed) in [(2, 0x6c), (3, 0x39)] {
+            let rest = [0, 0, 0, 0, 0, 0, 0x01, 10, 0x82, 3, packed];
+            let blob = blob(
+                version,
+                (1, 3, 3),
+                (DataType::Byte, [0.5, 11.0, 13.0]),
+                &rest,
+            );
+            assert_eq!(decode(&blob, 3).unwrap().values, [11.0, 12.0, 13.0]);
+        }
+    }
+
+    #[test]
+    fn test_delta_huffman() {
+        // The differences 10, 1, 1 and 1 coded as `1` and `0` respectively.
+        let mut rest = vec![0, 0, 0, 0, 0, 1];
+        for n in [2, 256, 1, 11] {
+            rest.extend_from_slice(&(n as i32).to_le_bytes());
+        }
+        rest.extend_from_slice(&[0x81, 10, 0x01, 0x02]);
+        rest.extend_from_slice(&[0, 0, 0, 0x40, 0, 0, 0, 0x80, 0, 0, 0, 0]);
+        let blob = blob(3, (2, 2, 4), (DataType::Byte, [0.5, 10.0, 12.0]), &rest);
+        assert_eq!(decode(&blob, 4).unwrap().values, [10.0, 11.0, 11.0, 12.0]);
+    }
+
+    #[test]
+    fn test_long_huffman_code() {
+        // The differences 10 and 1 coded as `1000000000000` and `0`, whose first is too long for
+        // the lookup table.
+        let mut rest = vec![0, 0, 0, 0, 0, 1];
+        for n in [2, 256, 1, 11] {
+            rest.extend_from_slice(&(n as i32).to_le_bytes());
+        }
+        rest.extend_from_slice(&[0x84, 10, 0x01, 0, 0, 0, 0xd0]);
+        rest.extend_from_slice(&[0, 0, 0, 0x40, 0, 0, 0, 0x80]);
+        let blob = blob(3, (1, 2, 2), (DataType::Byte, [0.5, 10.0, 11.0]), &rest);
+        assert_eq!(decode(&blob, 2).unwrap().values, [10.0, 11.0]);
+    }
+
+    #[test]
+    fn test_lossless_roundtrip() {
+        let (width, height, depth) = (13, 11, 3);
+        let values: Vec<f64> = (0..width * height * depth)
+            .map(|i| match i % depth {
+                0 => 0.0,
+                1 => 4000.0,
+                _ => ((i * 7919) % 65536) as f64,
+            })
+            .collect();
+        let blob = encode(&values, width, height, depth, DataType::UShort, 0.0).unwrap();
+        assert_eq!(decode(&blob, values.len()).unwrap().values, values);
+
+        let values: Vec<f64> = (0..width * height)
+            .map(|i| if i == 20 { f64::NAN } else { i as f64 * -0.125 })
+            .collect();
+        let blob = encode(&values, width, height, 1, DataType::Double, 0.0).unwrap();
+        let decoded = decode(&blob, values.len()).unwrap().values;
+        assert!(decoded[20].is_nan());
+        assert_eq!(decoded[..20], values[..20]);
+        assert_eq!(decoded[21..], values[21..]);
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let values = vec![3.0; 64];
+        let mut blob = encode(&values, 8, 8, 1, DataType::Int, 0.0).unwrap();
+        assert_eq!(decode(&blob, 64).unwrap().values, values);
+
+        let last = blob.len() - 1;
+        blob[last] ^= 1;
+        assert!(decode(&blob, 64).is_err());
+        assert!(decode(&blob, 63).is_err());
+    }
+}
@@ -13,9 +13,15 @@ mod bytecast;
 pub mod decoder;
 pub mod encoder;
 mod error;
+mod lerc;
+mod progress;
 pub mod tags;
 
-pub use self::error::{TiffError, TiffFormatError, TiffResult, TiffUnsupportedError, UsageError};
+pub use self::error::{
+    ChunkError, ChunkErrorKind, TiffError, TiffFormatError, TiffResult, TiffUnsupportedError,
+    UsageError,
+};
+pub use self::progress::Progress;
 
 /// An enumeration over supported color types and their bit depths
 #[derive(Copy, PartialEq, Eq, Debug, Clone, Hash)]
@@ -40,4 +46,13 @@ pub enum ColorType {
 
     /// Pixel is YCbCr
     YCbCr(u8),
+
+    /// Pixel is CIE L*a*b*, where a* and b* are offset by half the range of the samples
+    Lab(u8),
+
+    /// Pixel is CIE XYZ
+    XYZ(u8),
+
+    /// Pixel has color channels followed by extra samples, which do not fit any other color type
+    Multiband { bit_depth: u8, num_samples: u16 },
 }
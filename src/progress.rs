@@ -0,0 +1,36 @@
+//! Progress reports of the encoder and decoder.
+use std::fmt;
+
+/// The progress of encoding or decoding the chunks of an image.
+///
+/// It is reported after each chunk, see `TiffEncoder::with_progress` and
+/// `Decoder::with_progress`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The index of the chunk that was just written or read.
+    pub chunk_index: u64,
+    /// The number of chunks of the image.
+    pub chunk_count: u64,
+    /// The bytes of chunk data of the image that were written or read so far, as stored in the
+    /// file.
+    pub bytes: u64,
+}
+
+/// A callback that receives progress reports.
+pub(crate) struct ProgressCallback(Box<dyn FnMut(Progress) + Send>);
+
+impl ProgressCallback {
+    pub(crate) fn new(callback: impl FnMut(Progress) + Send + 'static) -> Self {
+        ProgressCallback(Box::new(callback))
+    }
+
+    pub(crate) fn report(&mut self, progress: Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
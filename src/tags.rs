@@ -80,8 +80,8 @@ pub enum Tag(u16) unknown("A private or extension tag") {
     Compression = 259, // TODO add support for 2 and 32773
     Copyright = 33_432,
     DateTime = 306,
-    ExtraSamples = 338, // TODO add support
-    FillOrder = 266, // TODO add support
+    ExtraSamples = 338,
+    FillOrder = 266,
     FreeByteCounts = 289, // TODO add support
     FreeOffsets = 288, // TODO add support
     GrayResponseCurve = 291, // TODO add support
@@ -91,8 +91,8 @@ pub enum Tag(u16) unknown("A private or extension tag") {
     ImageLength = 257,
     ImageWidth = 256,
     Make = 271,
-    MaxSampleValue = 281, // TODO add support
-    MinSampleValue = 280, // TODO add support
+    MaxSampleValue = 281,
+    MinSampleValue = 280,
     Model = 272,
     NewSubfileType = 254, // TODO add support
     Orientation = 274, // TODO add support
@@ -116,10 +116,27 @@ pub enum Tag(u16) unknown("A private or extension tag") {
     TileByteCounts = 325,
     // Data Sample Format
     SampleFormat = 339,
-    SMinSampleValue = 340, // TODO add support
-    SMaxSampleValue = 341, // TODO add support
+    SMinSampleValue = 340,
+    SMaxSampleValue = 341,
     // JPEG
     JPEGTables = 347,
+    // ITULab
+    Decode = 433,
+    // Old-style JPEG, which is no longer part of the specification
+    JPEGProc = 512,
+    JPEGInterchangeFormat = 513,
+    JPEGInterchangeFormatLength = 514,
+    JPEGRestartInterval = 515,
+    JPEGLosslessPredictors = 517,
+    JPEGPointTransforms = 518,
+    JPEGQTables = 519,
+    JPEGDCTables = 520,
+    JPEGACTables = 521,
+    // YCbCr
+    YCbCrCoefficients = 529,
+    YCbCrSubsampling = 530,
+    YCbCrPositioning = 531,
+    ReferenceBlackWhite = 532,
     // GeoTIFF
     ModelPixelScaleTag = 33550, // (SoftDesk)
     ModelTransformationTag = 34264, // (JPL Carto Group)
@@ -128,6 +145,8 @@ pub enum Tag(u16) unknown("A private or extension tag") {
     GeoDoubleParamsTag = 34736, // (SPOT)
     GeoAsciiParamsTag = 34737, // (SPOT)
     GdalNodata = 42113, // Contains areas with missing data
+    // LERC
+    LercParameters = 50674, // (Esri)
 }
 }
 
@@ -184,6 +203,20 @@ pub enum CompressionMethod(u16) unknown("A custom compression method") {
     Deflate = 8,
     OldDeflate = 0x80B2,
     PackBits = 0x8005,
+    ZSTD = 50000,
+    WebP = 50001,
+    Lerc = 34887,
+    SGILog = 34676,
+    SGILog24 = 34677,
+}
+}
+
+tags! {
+/// The compression of LERC blobs on top of LERC itself, the second value of `LercParameters`.
+pub enum LercCompression(u16) {
+    None = 0,
+    Deflate = 1,
+    Zstd = 2,
 }
 }
 
@@ -197,6 +230,32 @@ pub enum PhotometricInterpretation(u16) {
     CMYK = 5,
     YCbCr = 6,
     CIELab = 8,
+    ICCLab = 9,
+    ITULab = 10,
+    LogL = 32844,
+    LogLuv = 32845,
+}
+}
+
+tags! {
+/// The logical order of bits within a byte of the (compressed) image data.
+pub enum FillOrder(u16) {
+    /// Pixels with lower column values are stored in the higher-order bits of the byte.
+    MsbToLsb = 1,
+    /// Pixels with lower column values are stored in the lower-order bits of the byte.
+    LsbToMsb = 2,
+}
+}
+
+tags! {
+/// The meaning of a sample beyond the color channels, as given by the `ExtraSamples` tag.
+pub enum ExtraSample(u16) {
+    /// Unspecified data.
+    Unspecified = 0,
+    /// Alpha that has been premultiplied into the color channels.
+    AssociatedAlpha = 1,
+    /// Alpha that is independent of the color channels.
+    UnassociatedAlpha = 2,
 }
 }
 
@@ -215,6 +274,14 @@ pub enum Predictor(u16) {
 }
 }
 
+tags! {
+/// Position of subsampled chroma relative to the luma samples
+pub enum YCbCrPositioning(u16) {
+    Centered = 1,
+    Cosited = 2,
+}
+}
+
 tags! {
 /// Type to represent resolution units
 pub enum ResolutionUnit(u16) {
@@ -230,6 +297,10 @@ pub enum SampleFormat(u16) unknown("An unknown extension sample format") {
     Int = 2,
     IEEEFP = 3,
     Void = 4,
+    /// Pairs of signed integers, the real part followed by the imaginary part
+    ComplexInt = 5,
+    /// Pairs of IEEE floats, the real part followed by the imaginary part
+    ComplexIEEEFP = 6,
 }
 }
 
@@ -248,4 +319,4 @@ tags! {
         SinglePageOfMultiPageImage = 2,
         TransparencyMask = 4,
     }
-}
\ No newline at end of file
+}
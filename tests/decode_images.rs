@@ -1,7 +1,7 @@
 extern crate tiff;
 
 use tiff::decoder::{ifd, Decoder, DecodingResult};
-use tiff::ColorType;
+use tiff::{ColorType, TiffError};
 
 use std::fs::File;
 use std::path::PathBuf;
@@ -205,16 +205,16 @@ fn issue_69() {
     test_image_sum_u16("issue_69_packbits.tiff", ColorType::Gray(16), 1015486);
 }
 
-// TODO: GrayA support
-//#[test]
-//fn test_gray_alpha_u8()
-//{
-//let img_file = File::open("./tests/images/minisblack-2c-8b-alpha.tiff").expect("Cannot find test image!");
-//let mut decoder = Decoder::new(img_file).expect("Cannot create decoder");
-//assert_eq!(decoder.colortype().unwrap(), ColorType::GrayA(8));
-//let img_res = decoder.read_image();
-//assert!(img_res.is_ok());
-//}
+#[test]
+fn test_gray_alpha_u8() {
+    // The alpha samples of this image are stored in a separate plane.
+    let img_file =
+        File::open("./tests/images/minisblack-2c-8b-alpha.tiff").expect("Cannot find test image!");
+    let mut decoder = Decoder::new(img_file).expect("Cannot create decoder");
+    assert_eq!(decoder.colortype().unwrap(), ColorType::GrayA(8));
+    let img_res = decoder.read_image();
+    assert!(img_res.is_ok());
+}
 
 #[test]
 fn test_tiled_rgb_u8() {
@@ -477,3 +477,765 @@ fn test_predictor_3_rgb_f32() {
 fn test_predictor_3_gray_f32() {
     test_image_sum_f32("predictor-3-gray-f32.tif", ColorType::Gray(32), 20008.275);
 }
+
+/// Reads the packed rows of a binary (P4) PBM file.
+fn read_pbm_rows(file: &str) -> Vec<u8> {
+    let path = PathBuf::from(TEST_IMAGE_DIR).join(file);
+    let data = std::fs::read(path).expect("Cannot find test image!");
+    // The header has three whitespace terminated fields: magic, width and height.
+    let mut fields = 0;
+    let start = data
+        .iter()
+        .position(|&b| {
+            fields += (b == b'\n' || b == b' ') as usize;
+            fields == 3
+        })
+        .unwrap();
+    data[start + 1..].to_vec()
+}
+
+#[test]
+fn test_gray_u1() {
+    let path = PathBuf::from(TEST_IMAGE_DIR).join("miniswhite-1c-1b.tiff");
+    let img_file = File::open(path).expect("Cannot find test image!");
+    let mut decoder = Decoder::new(img_file).expect("Cannot create decoder");
+    assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(1));
+
+    // Rows are packed and start on a byte boundary. In the PBM, a set bit is black, which the
+    // decoder inverts for a WhiteIsZero image.
+    let expected: Vec<u8> = read_pbm_rows("miniswhite-1c-1b.pbm")
+        .into_iter()
+        .map(|b| !b)
+        .collect();
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(res) => assert_eq!(res, expected),
+        _ => panic!("Wrong bit depth"),
+    }
+}
+
+#[test]
+fn test_fill_order_lsb_to_msb() {
+    use std::convert::TryFrom;
+    use tiff::encoder::compression::{CompressionAlgorithm, Lzw, Uncompressed};
+    use tiff::encoder::TiffEncoder;
+    use tiff::tags::{CompressionMethod, FillOrder, PhotometricInterpretation, Tag};
+
+    let (width, height) = (157u32, 151u32);
+    let rows = read_pbm_rows("miniswhite-1c-1b.pbm");
+
+    for method in [CompressionMethod::None, CompressionMethod::LZW] {
+        let mut compressed = Vec::new();
+        match method {
+            CompressionMethod::LZW => Lzw.write_to(&mut compressed, &rows).unwrap(),
+            _ => Uncompressed.write_to(&mut compressed, &rows).unwrap(),
+        };
+        // FillOrder applies to the stored bytes, that is after compression.
+        let data: Vec<u8> = compressed.iter().map(|b| b.reverse_bits()).collect();
+
+        let mut file = std::io::Cursor::new(Vec::new());
+        {
+            let mut tiff = TiffEncoder::new(&mut file).unwrap();
+            let mut dir = tiff.new_directory().unwrap();
+            let offset = dir.write_data(&data[..]).unwrap();
+            dir.write_tag(Tag::ImageWidth, width).unwrap();
+            dir.write_tag(Tag::ImageLength, height).unwrap();
+            dir.write_tag(Tag::BitsPerSample, 1u16).unwrap();
+            dir.write_tag(Tag::Compression, method.to_u16()).unwrap();
+            dir.write_tag(
+                Tag::PhotometricInterpretation,
+                PhotometricInterpretation::BlackIsZero.to_u16(),
+            )
+            .unwrap();
+            dir.write_tag(Tag::FillOrder, FillOrder::LsbToMsb.to_u16())
+                .unwrap();
+            dir.write_tag(Tag::StripOffsets, u32::try_from(offset).unwrap())
+                .unwrap();
+            dir.write_tag(Tag::RowsPerStrip, height).unwrap();
+            dir.write_tag(Tag::StripByteCounts, data.len() as u32)
+                .unwrap();
+            dir.finish().unwrap();
+        }
+
+        file.set_position(0);
+        let mut decoder = Decoder::new(file).expect("Cannot create decoder");
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(1));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(res) => assert_eq!(res, rows, "{:?}", method),
+            _ => panic!("Wrong bit depth"),
+        }
+    }
+}
+
+/// The segments of a JPEG stream before its scan, and the scan from its header to the end.
+fn split_jpeg(stream: &[u8]) -> (Vec<(u8, &[u8])>, &[u8]) {
+    let mut segments = Vec::new();
+    let mut rest = &stream[2..];
+    while rest[1] != 0xda {
+        let length = 2 + usize::from(u16::from_be_bytes([rest[2], rest[3]]));
+        segments.push((rest[1], &rest[4..length]));
+        rest = &rest[length..];
+    }
+    (segments, rest)
+}
+
+/// Encodes an image as old-style JPEG, with an interchange stream of the whole image, with an
+/// interchange stream of only the headers, or with the tables stored in separate tags.
+fn encode_old_jpeg(rgb: &[u8], width: u16, height: u16, layout: u8) -> Vec<u8> {
+    use jpeg_encoder::{ColorType as JpegColorType, Encoder, SamplingFactor};
+    use tiff::encoder::TiffEncoder;
+    use tiff::tags::{CompressionMethod, PhotometricInterpretation, Tag};
+
+    let rows_per_strip = 16;
+    let encode = |rows: &[u8], height: u16| {
+        let mut stream = Vec::new();
+        let mut encoder = Encoder::new(&mut stream, 90);
+        encoder.set_sampling_factor(SamplingFactor::F_2_2);
+        encoder
+            .encode(rows, width, height, JpegColorType::Rgb)
+            .unwrap();
+        stream
+    };
+    let strips: Vec<Vec<u8>> = rgb
+        .chunks(usize::from(width) * 3 * rows_per_strip)
+        .map(|rows| encode(rows, (rows.len() / usize::from(width) / 3) as u16))
+        .collect();
+    let whole_image = encode(rgb, height);
+
+    let mut file = std::io::Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut dir = tiff.new_directory().unwrap();
+        let mut offsets = Vec::new();
+        let mut byte_counts = Vec::new();
+        match layout {
+            0 => {
+                let offset = dir.write_data(&whole_image[..]).unwrap() as u32;
+                dir.write_tag(Tag::JPEGInterchangeFormat, offset).unwrap();
+                dir.write_tag(Tag::JPEGInterchangeFormatLength, whole_image.len() as u32)
+                    .unwrap();
+                // The strips point into the stream, at the start of the scan.
+                let (_, scan) = split_jpeg(&whole_image);
+                offsets.push(offset + (whole_image.len() - scan.len()) as u32);
+                byte_counts.push(scan.len() as u32);
+                for _ in 1..strips.len() {
+                    offsets.push(offsets[0]);
+                    byte_counts.push(0);
+                }
+            }
+            1 => {
+                let (segments, _) = split_jpeg(&strips[0]);
+                let mut header = vec![0xff, 0xd8];
+                for (marker, segment) in segments {
+                    header.extend_from_slice(&[0xff, marker]);
+                    header.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+                    header.extend_from_slice(segment);
+                }
+                let offset = dir.write_data(&header[..]).unwrap() as u32;
+                dir.write_tag(Tag::JPEGInterchangeFormat, offset).unwrap();
+                // Leave out the length, to which the stream extends.
+                for strip in &strips {
+                    let (_, scan) = split_jpeg(strip);
+                    offsets.push(dir.write_data(scan).unwrap() as u32);
+                    byte_counts.push(scan.len() as u32);
+                }
+            }
+            _ => {
+                let (segments, _) = split_jpeg(&strips[0]);
+                let (mut q_tables, mut dc_tables, mut ac_tables) = (vec![], vec![], vec![]);
+                for (marker, segment) in segments {
+                    match marker {
+                        0xdb => {
+                            for table in segment.chunks(65) {
+                                q_tables.push(dir.write_data(&table[1..]).unwrap() as u32);
+                            }
+                        }
+                        0xc4 => {
+                            let offset = dir.write_data(&segment[1..]).unwrap() as u32;
+                            match segment[0] >> 4 {
+                                0 => dc_tables.push(offset),
+                                _ => ac_tables.push(offset),
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                dir.write_tag(Tag::JPEGProc, 1u16).unwrap();
+                dir.write_tag(Tag::JPEGQTables, &q_tables[..]).unwrap();
+                dir.write_tag(Tag::JPEGDCTables, &dc_tables[..]).unwrap();
+                dir.write_tag(Tag::JPEGACTables, &ac_tables[..]).unwrap();
+                // Strips only hold the entropy coded data.
+                for strip in &strips {
+                    let (_, scan) = split_jpeg(strip);
+                    let header_len = 2 + usize::from(u16::from_be_bytes([scan[2], scan[3]]));
+                    let data = &scan[header_len..scan.len() - 2];
+                    offsets.push(dir.write_data(data).unwrap() as u32);
+                    byte_counts.push(data.len() as u32);
+                }
+            }
+        }
+
+        dir.write_tag(Tag::ImageWidth, u32::from(width)).unwrap();
+        dir.write_tag(Tag::ImageLength, u32::from(height)).unwrap();
+        dir.write_tag(Tag::BitsPerSample, &[8u16, 8, 8][..])
+            .unwrap();
+        dir.write_tag(Tag::SamplesPerPixel, 3u16).unwrap();
+        dir.write_tag(Tag::Compression, CompressionMethod::JPEG.to_u16())
+            .unwrap();
+        dir.write_tag(
+            Tag::PhotometricInterpretation,
+            PhotometricInterpretation::YCbCr.to_u16(),
+        )
+        .unwrap();
+        dir.write_tag(Tag::YCbCrSubsampling, &[2u16, 2][..])
+            .unwrap();
+        dir.write_tag(Tag::RowsPerStrip, rows_per_strip as u32)
+            .unwrap();
+        dir.write_tag(Tag::StripOffsets, &offsets[..]).unwrap();
+        dir.write_tag(Tag::StripByteCounts, &byte_counts[..])
+            .unwrap();
+        dir.finish().unwrap();
+    }
+    file.into_inner()
+}
+
+#[test]
+fn test_old_jpeg() {
+    let (width, height) = (48u16, 40u16);
+    let rgb: Vec<u8> = (0..usize::from(width) * usize::from(height))
+        .flat_map(|i| {
+            let (x, y) = (i % 48, i / 48);
+            [(x * 5) as u8, (y * 6) as u8, 128]
+        })
+        .collect();
+
+    for layout in 0..3 {
+        let file = encode_old_jpeg(&rgb, width, height, layout);
+        let mut decoder = Decoder::new(std::io::Cursor::new(file)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::YCbCr(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(res) => {
+                assert_eq!(res.len(), rgb.len(), "layout {}", layout);
+                for (decoded, expected) in res.iter().zip(&rgb) {
+                    let error = (i16::from(*decoded) - i16::from(*expected)).abs();
+                    assert!(error <= 12, "layout {}", layout);
+                }
+            }
+            _ => panic!("Wrong bit depth"),
+        }
+    }
+}
+
+#[test]
+fn test_old_jpeg_malformed_tables() {
+    use tiff::encoder::TagEditor;
+    use tiff::tags::Tag;
+
+    let rgb = vec![0; 16 * 16 * 3];
+    let mut file = std::io::Cursor::new(encode_old_jpeg(&rgb, 16, 16, 1));
+    // An interchange stream without a length that starts after the strips.
+    let mut editor = TagEditor::new(&mut file, 0).unwrap();
+    editor
+        .write_tag(Tag::JPEGInterchangeFormat, u32::MAX)
+        .unwrap();
+    editor.finish().unwrap();
+
+    // The directory can be read, only decoding the image data fails.
+    file.set_position(0);
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(decoder.dimensions().unwrap(), (16, 16));
+    assert!(matches!(
+        decoder.read_image(),
+        Err(TiffError::FormatError(_))
+    ));
+}
+
+/// Encodes the samples of a YCbCr image as data units with the given chroma subsampling.
+fn encode_subsampled_ycbcr(
+    luma: &[u8],
+    chroma: impl Fn(usize, usize) -> [u8; 2],
+    (width, height): (usize, usize),
+    (h, v): (usize, usize),
+) -> Vec<u8> {
+    let mut units = Vec::new();
+    for unit_y in 0..(height + v - 1) / v {
+        for unit_x in 0..(width + h - 1) / h {
+            for y in unit_y * v..(unit_y + 1) * v {
+                for x in unit_x * h..(unit_x + 1) * h {
+                    // Samples past the edge of the image are padding.
+                    let inside = x < width && y < height;
+                    units.push(if inside { luma[y * width + x] } else { 0 });
+                }
+            }
+            units.extend_from_slice(&chroma(unit_x, unit_y));
+        }
+    }
+    units
+}
+
+#[test]
+fn test_ycbcr_subsampling() {
+    use std::convert::TryFrom;
+    use tiff::encoder::compression::{CompressionAlgorithm, Lzw, Uncompressed};
+    use tiff::encoder::{Rational, TiffEncoder};
+    use tiff::tags::{CompressionMethod, PhotometricInterpretation, Tag};
+
+    let (width, height) = (7usize, 5usize);
+    let luma: Vec<u8> = (0..width * height).map(|i| (16 + i * 6) as u8).collect();
+    let chroma = |x: usize, y: usize| [(100 + x * 10) as u8, (50 + y * 20) as u8];
+
+    for subsampling in [(2, 2), (2, 1), (4, 2)] {
+        for method in [CompressionMethod::None, CompressionMethod::LZW] {
+            let units = encode_subsampled_ycbcr(&luma, chroma, (width, height), subsampling);
+            let mut data = Vec::new();
+            match method {
+                CompressionMethod::LZW => Lzw.write_to(&mut data, &units).unwrap(),
+                _ => Uncompressed.write_to(&mut data, &units).unwrap(),
+            };
+
+            let mut file = std::io::Cursor::new(Vec::new());
+            {
+                let mut tiff = TiffEncoder::new(&mut file).unwrap();
+                let mut dir = tiff.new_directory().unwrap();
+                let offset = dir.write_data(&data[..]).unwrap();
+                dir.write_tag(Tag::ImageWidth, width as u32).unwrap();
+                dir.write_tag(Tag::ImageLength, height as u32).unwrap();
+                dir.write_tag(Tag::BitsPerSample, &[8u16, 8, 8][..])
+                    .unwrap();
+                dir.write_tag(Tag::SamplesPerPixel, 3u16).unwrap();
+                dir.write_tag(Tag::Compression, method.to_u16()).unwrap();
+                dir.write_tag(
+                    Tag::PhotometricInterpretation,
+                    PhotometricInterpretation::YCbCr.to_u16(),
+                )
+                .unwrap();
+                let (h, v) = subsampling;
+                dir.write_tag(Tag::YCbCrSubsampling, &[h as u16, v as u16][..])
+                    .unwrap();
+                let reference = [(16, 235), (128, 240), (128, 240)]
+                    .iter()
+                    .flat_map(|&(black, white)| [black, white])
+                    .map(|n| Rational { n, d: 1 })
+                    .collect::<Vec<_>>();
+                dir.write_tag(Tag::ReferenceBlackWhite, &reference[..])
+                    .unwrap();
+                dir.write_tag(Tag::StripOffsets, u32::try_from(offset).unwrap())
+                    .unwrap();
+                dir.write_tag(Tag::RowsPerStrip, height as u32).unwrap();
+                dir.write_tag(Tag::StripByteCounts, data.len() as u32)
+                    .unwrap();
+                dir.finish().unwrap();
+            }
+
+            file.set_position(0);
+            let mut decoder = Decoder::new(file.clone()).unwrap();
+            assert_eq!(decoder.colortype().unwrap(), ColorType::YCbCr(8));
+            let expected: Vec<u8> = (0..width * height)
+                .flat_map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    let [cb, cr] = chroma(x / subsampling.0, y / subsampling.1);
+                    [luma[i], cb, cr]
+                })
+                .collect();
+            match decoder.read_image().unwrap() {
+                DecodingResult::U8(res) => assert_eq!(res, expected, "{:?}", subsampling),
+                _ => panic!("Wrong bit depth"),
+            }
+
+            // Luma at the reference black, with Cb and Cr below their neutral value.
+            let mut decoder = Decoder::new(file).unwrap().with_ycbcr_to_rgb(true);
+            assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+            match decoder.read_image().unwrap() {
+                DecodingResult::U8(res) => {
+                    assert_eq!(res[..3], [0, 74, 0]);
+                    assert_eq!(res.len(), expected.len());
+                }
+                _ => panic!("Wrong bit depth"),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ycbcr_to_rgb() {
+    use std::convert::TryFrom;
+    use tiff::encoder::TiffEncoder;
+    use tiff::tags::{PhotometricInterpretation, Tag};
+
+    // Gray, red, green and blue with the default coefficients and reference.
+    let ycbcr: [u8; 12] = [128, 128, 128, 76, 85, 255, 150, 44, 21, 29, 255, 107];
+    let mut file = std::io::Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut dir = tiff.new_directory().unwrap();
+        let offset = dir.write_data(&ycbcr[..]).unwrap();
+        dir.write_tag(Tag::ImageWidth, 4u32).unwrap();
+        dir.write_tag(Tag::ImageLength, 1u32).unwrap();
+        dir.write_tag(Tag::BitsPerSample, &[8u16, 8, 8][..])
+            .unwrap();
+        dir.write_tag(Tag::SamplesPerPixel, 3u16).unwrap();
+        dir.write_tag(Tag::Compression, 1u16).unwrap();
+        dir.write_tag(
+            Tag::PhotometricInterpretation,
+            PhotometricInterpretation::YCbCr.to_u16(),
+        )
+        .unwrap();
+        dir.write_tag(Tag::YCbCrSubsampling, &[1u16, 1][..])
+            .unwrap();
+        dir.write_tag(Tag::StripOffsets, u32::try_from(offset).unwrap())
+            .unwrap();
+        dir.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+        dir.write_tag(Tag::StripByteCounts, 12u32).unwrap();
+        dir.finish().unwrap();
+    }
+
+    file.set_position(0);
+    let mut decoder = Decoder::new(file).unwrap().with_ycbcr_to_rgb(true);
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(res) => {
+            let expected = [128, 128, 128, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+            for (decoded, expected) in res.iter().zip(&expected) {
+                assert!((i16::from(*decoded) - expected).abs() <= 2, "{:?}", res);
+            }
+        }
+        _ => panic!("Wrong bit depth"),
+    }
+}
+
+#[test]
+fn test_lab_encodings() {
+    use std::convert::TryFrom;
+    use tiff::encoder::{SRational, TiffEncoder};
+    use tiff::tags::{PhotometricInterpretation, Tag};
+
+    // L* of 50, a* of -20 and b* of 40, in the encoding that every image is decoded to.
+    let expected = [128u8, 108, 168];
+    let cielab = [128u8, (-20i8) as u8, 40];
+    // a* spans [-85, 85] and b* spans [-75, 125] by default.
+    let itulab = [128u8, 98, 147];
+    let decode = [(0, 100), (-85, 85), (-75, 125)]
+        .iter()
+        .flat_map(|&(min, max)| [min, max])
+        .map(|n| SRational { n, d: 1 })
+        .collect::<Vec<_>>();
+
+    let cases = [
+        (PhotometricInterpretation::CIELab, cielab, false),
+        (PhotometricInterpretation::ITULab, itulab, false),
+        (PhotometricInterpretation::ITULab, itulab, true),
+    ];
+    for (photometric, samples, with_decode) in cases {
+        let mut file = std::io::Cursor::new(Vec::new());
+        {
+            let mut tiff = TiffEncoder::new(&mut file).unwrap();
+            let mut dir = tiff.new_directory().unwrap();
+            let offset = dir.write_data(&samples[..]).unwrap();
+            dir.write_tag(Tag::ImageWidth, 1u32).unwrap();
+            dir.write_tag(Tag::ImageLength, 1u32).unwrap();
+            dir.write_tag(Tag::BitsPerSample, &[8u16, 8, 8][..])
+                .unwrap();
+            dir.write_tag(Tag::SamplesPerPixel, 3u16).unwrap();
+            dir.write_tag(Tag::Compression, 1u16).unwrap();
+            dir.write_tag(Tag::PhotometricInterpretation, photometric.to_u16())
+                .unwrap();
+            if with_decode {
+                dir.write_tag(Tag::Decode, &decode[..]).unwrap();
+            }
+            dir.write_tag(Tag::StripOffsets, u32::try_from(offset).unwrap())
+                .unwrap();
+            dir.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+            dir.write_tag(Tag::StripByteCounts, 3u32).unwrap();
+            dir.finish().unwrap();
+        }
+
+        file.set_position(0);
+        let mut decoder = Decoder::new(file).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Lab(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(res) => {
+                for (decoded, expected) in res.iter().zip(&expected) {
+                    let error = (i16::from(*decoded) - i16::from(*expected)).abs();
+                    assert!(error <= 1, "{:?}: {:?}", photometric, res);
+                }
+            }
+            _ => panic!("Wrong bit depth"),
+        }
+    }
+}
+
+#[test]
+fn test_logluv() {
+    let path = PathBuf::from(TEST_IMAGE_DIR).join("logluv-3c-16b.tiff");
+    let mut decoder = Decoder::new(File::open(path).unwrap()).unwrap();
+    assert_eq!(decoder.colortype().unwrap(), ColorType::XYZ(32));
+    match decoder.read_image().unwrap() {
+        DecodingResult::F32(res) => assert_eq!(res, [0.0; 3]),
+        _ => panic!("Wrong bit depth"),
+    }
+}
+
+/// Encodes a row of four pixels with SGI log compression.
+fn encode_sgi_log(photometric: u16, compression: u16, data: &[u8]) -> std::io::Cursor<Vec<u8>> {
+    use std::convert::TryFrom;
+    use tiff::encoder::TiffEncoder;
+    use tiff::tags::Tag;
+
+    let samples = if photometric == 32844 { 1u16 } else { 3 };
+    let mut file = std::io::Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut dir = tiff.new_directory().unwrap();
+        let offset = dir.write_data(data).unwrap();
+        dir.write_tag(Tag::ImageWidth, 4u32).unwrap();
+        dir.write_tag(Tag::ImageLength, 1u32).unwrap();
+        dir.write_tag(Tag::BitsPerSample, &vec![16u16; samples.into()][..])
+            .unwrap();
+        dir.write_tag(Tag::SamplesPerPixel, samples).unwrap();
+        dir.write_tag(Tag::Compression, compression).unwrap();
+        dir.write_tag(Tag::PhotometricInterpretation, photometric)
+            .unwrap();
+        dir.write_tag(Tag::StripOffsets, u32::try_from(offset).unwrap())
+            .unwrap();
+        dir.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+        dir.write_tag(Tag::StripByteCounts, data.len() as u32)
+            .unwrap();
+        dir.finish().unwrap();
+    }
+    file.set_position(0);
+    file
+}
+
+#[test]
+fn test_sgi_log_encodings() {
+    // Each byte of the pixels is stored as a literal run, from the most significant one.
+    let runs = |pixels: &[u32], bytes: u32| -> Vec<u8> {
+        (0..bytes)
+            .rev()
+            .flat_map(|byte| {
+                let literals = pixels.iter().map(move |p| (p >> (8 * byte)) as u8);
+                std::iter::once(pixels.len() as u8).chain(literals)
+            })
+            .collect()
+    };
+    let luv24: Vec<u8> = [
+        0x200 << 14,
+        0x210 << 14 | 8000,
+        0x1f0 << 14 | 16288,
+        0x200 << 14 | 16383,
+    ]
+    .iter()
+    .flat_map(|p: &u32| [(p >> 16) as u8, (p >> 8) as u8, *p as u8])
+    .collect();
+
+    // The values that libtiff decodes these pixels to.
+    let cases: [(u16, u16, Vec<u8>, ColorType, &[f32]); 3] = [
+        (
+            32844,
+            34676,
+            runs(&[0x4000, 0x4800, 0xc000, 0x3000], 2),
+            ColorType::Gray(32),
+            &[1.0013547, 256.3468, -1.0013547, 1.527946e-5],
+        ),
+        (
+            32845,
+            34676,
+            runs(&[0x400056c2, 0x41007070, 0x3f80a050, 0], 4),
+            ColorType::XYZ(32),
+            &[
+                1.0019983, 1.0013547, 0.99170154, 4.506096, 2.0027094, 10.380711, 3.176396,
+                0.70806473, 6.2197547, 0.0, 0.0, 0.0,
+            ],
+        ),
+        (
+            32845,
+            34677,
+            luv24,
+            ColorType::XYZ(32),
+            &[
+                1.8867925,
+                0.062839366,
+                9.143449,
+                0.2144166,
+                0.07472903,
+                0.15161687,
+                0.019367713,
+                0.0528414,
+                -6.168616e-7,
+                0.062839366,
+                0.062839366,
+                0.062839366,
+            ],
+        ),
+    ];
+
+    for (photometric, compression, data, color_type, expected) in cases {
+        let mut decoder = Decoder::new(encode_sgi_log(photometric, compression, &data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), color_type);
+        match decoder.read_image().unwrap() {
+            DecodingResult::F32(res) => {
+                assert_eq!(res.len(), expected.len());
+                for (decoded, expected) in res.iter().zip(expected) {
+                    let error = (decoded - expected).abs();
+                    assert!(error <= expected.abs() * 1e-5 + 1e-7, "{:?}", res);
+                }
+            }
+            _ => panic!("Wrong bit depth"),
+        }
+    }
+
+    // Equal energy white has the same value in each channel of RGB.
+    let data = runs(&[0x4000_0000 | 86 << 8 | 194, 0, 0, 0], 4);
+    let mut decoder = Decoder::new(encode_sgi_log(32845, 34676, &data))
+        .unwrap()
+        .with_xyz_to_rgb(true);
+    assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(32));
+    match decoder.read_image().unwrap() {
+        DecodingResult::F32(res) => {
+            for channel in &res[..3] {
+                assert!((channel - 1.0).abs() < 0.01, "{:?}", res);
+            }
+        }
+        _ => panic!("Wrong bit depth"),
+    }
+}
+
+#[test]
+fn test_normalized_f32() {
+    use std::convert::TryFrom;
+    use tiff::encoder::TiffEncoder;
+    use tiff::tags::{PhotometricInterpretation, SampleFormat, Tag};
+
+    fn decode_normalized(
+        data: &[u8],
+        width: u32,
+        bits: u16,
+        format: SampleFormat,
+        extra_tags: &[(Tag, &[f32])],
+    ) -> Vec<f32> {
+        let mut file = std::io::Cursor::new(Vec::new());
+        {
+            let mut tiff = TiffEncoder::new(&mut file).unwrap();
+            let mut dir = tiff.new_directory().unwrap();
+            let offset = dir.write_data(data).unwrap();
+            dir.write_tag(Tag::ImageWidth, width).unwrap();
+            dir.write_tag(Tag::ImageLength, 1u32).unwrap();
+            dir.write_tag(Tag::BitsPerSample, bits).unwrap();
+            dir.write_tag(Tag::SampleFormat, format.to_u16()).unwrap();
+            dir.write_tag(
+                Tag::PhotometricInterpretation,
+                PhotometricInterpretation::BlackIsZero.to_u16(),
+            )
+            .unwrap();
+            for &(tag, value) in extra_tags {
+                match tag {
+                    Tag::MaxSampleValue => dir.write_tag(tag, value[0] as u16).unwrap(),
+                    _ => dir.write_tag(tag, value).unwrap(),
+                }
+            }
+            dir.write_tag(Tag::StripOffsets, u32::try_from(offset).unwrap())
+                .unwrap();
+            dir.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+            dir.write_tag(Tag::StripByteCounts, data.len() as u32)
+                .unwrap();
+            dir.finish().unwrap();
+        }
+
+        file.set_position(0);
+        let mut decoder = Decoder::new(file).unwrap().with_normalized_f32(true);
+        match decoder.read_image().unwrap() {
+            DecodingResult::F32(res) => res,
+            _ => panic!("Wrong data type"),
+        }
+    }
+
+    fn assert_close(decoded: &[f32], expected: &[f32]) {
+        assert_eq!(decoded.len(), expected.len(), "{:?}", decoded);
+        for (decoded, expected) in decoded.iter().zip(expected) {
+            assert!((decoded - expected).abs() < 1e-4, "{:?}", decoded);
+        }
+    }
+
+    // The range of the bit depth.
+    let data = decode_normalized(&[0, 51, 255], 3, 8, SampleFormat::Uint, &[]);
+    assert_close(&data, &[0.0, 0.2, 1.0]);
+    let data = decode_normalized(&[0x0f, 0x80], 3, 4, SampleFormat::Uint, &[]);
+    assert_close(&data, &[0.0, 1.0, 8.0 / 15.0]);
+    let samples: Vec<u8> = [i16::MIN, 0, i16::MAX]
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect();
+    let data = decode_normalized(&samples, 3, 16, SampleFormat::Int, &[]);
+    assert_close(&data, &[0.0, 32768.0 / 65535.0, 1.0]);
+
+    // A declared range, out of which samples are clamped.
+    let samples: Vec<u8> = [0u16, 500, 1000, 2000]
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect();
+    let max = [(Tag::MaxSampleValue, &[1000.0f32][..])];
+    let data = decode_normalized(&samples, 4, 16, SampleFormat::Uint, &max);
+    assert_close(&data, &[0.0, 0.5, 1.0, 1.0]);
+    let samples: Vec<u8> = [-1f32, 0.0, 0.5, 1.0]
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect();
+    let range = [
+        (Tag::SMinSampleValue, &[-1.0f32][..]),
+        (Tag::SMaxSampleValue, &[1.0f32][..]),
+    ];
+    let data = decode_normalized(&samples, 4, 32, SampleFormat::IEEEFP, &range);
+    assert_close(&data, &[0.0, 0.5, 0.75, 1.0]);
+}
+
+#[test]
+fn test_lerc_reference_files() {
+    // Written by libtiff 4.5 with liblerc 4.0. The blobs of the v2 and v3 files were encoded for
+    // their version by liblerc and stored as raw strips. All are lossless, so that 8-bit samples
+    // are Huffman coded, and mask out some pixels.
+    let (width, height) = (37, 23);
+    let valid =
+        |x: usize, y: usize| !((5..11).contains(&x) && (3..9).contains(&y)) && (x + y) % 17 != 0;
+    let gray = |x: usize, y: usize| ((x * 3 + y * 5) / 4 % 256) as u8;
+    let pixels = || (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)));
+    let open = |file: &str| {
+        let path = PathBuf::from(TEST_IMAGE_DIR).join(file);
+        Decoder::new(File::open(path).expect("Cannot find test image!")).unwrap()
+    };
+
+    let expected: Vec<u8> = pixels()
+        .map(|(x, y)| if valid(x, y) { gray(x, y) } else { 0 })
+        .collect();
+    for file in ["lerc-v2-gray-u8-mask.tif", "lerc-v3-gray-u8-mask.tif"] {
+        match open(file).read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, expected, "{}", file),
+            _ => panic!("Wrong data type"),
+        }
+    }
+
+    // The alpha sample is stored as the mask of the color samples.
+    let expected: Vec<u8> = pixels()
+        .flat_map(|(x, y)| match valid(x, y) {
+            true => [gray(x, y), (x * 7 % 256) as u8, (y * 11 % 256) as u8, 255],
+            false => [0; 4],
+        })
+        .collect();
+    let mut decoder = open("lerc-rgba-u8-alpha.tif");
+    assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(data) => assert_eq!(data, expected),
+        _ => panic!("Wrong data type"),
+    }
+
+    // Masked out floating point samples are NaN.
+    match open("lerc-deflate-gray-f32-nan.tif").read_image().unwrap() {
+        DecodingResult::F32(data) => {
+            for ((x, y), value) in pixels().zip(data) {
+                if valid(x, y) {
+                    assert_eq!(value, (x as f32 - y as f32) * 0.25);
+                } else {
+                    assert!(value.is_nan());
+                }
+            }
+        }
+        _ => panic!("Wrong data type"),
+    }
+}
@@ -1,13 +1,15 @@
 extern crate tiff;
 
-use tiff::decoder::{ifd, Decoder, DecodingResult};
+use tiff::decoder::{ifd, Decoder, DecodingResult, SampleDescriptor};
 use tiff::encoder::compression::{Deflate};
-use tiff::encoder::{colortype, Ifd, Ifd8, SRational, TiffEncoder};
-use tiff::tags::Tag;
-use tiff::ColorType;
+use tiff::encoder::{colortype, Ifd, Ifd8, SRational, TagEditor, TiffEncoder, TiffKind};
+use tiff::tags::{ExtraSample, SampleFormat, Tag};
+use tiff::{ColorType, TiffError, TiffUnsupportedError};
+
+use half::f16;
 
 use std::fs::File;
-use std::io::{Cursor, Seek, SeekFrom};
+use std::io::{Cursor, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 
 #[test]
@@ -641,3 +643,1076 @@ fn test_big_tiled_image_directory() {
         }
     }
 }
+
+#[test]
+fn test_rgba_extra_samples() {
+    let image_data: Vec<u8> = (0..4 * 4 * 4).map(|i| i as u8).collect();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::RGBA8>(4, 4, &image_data)
+            .unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    {
+        let mut decoder = Decoder::new(&mut file).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        assert_eq!(decoder.extra_samples(), &[ExtraSample::UnassociatedAlpha]);
+        assert_eq!(decoder.get_tag_u64(Tag::ExtraSamples).unwrap(), 2);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(img_res) => assert_eq!(image_data, img_res),
+            _ => panic!("Wrong data type"),
+        }
+    }
+}
+
+#[test]
+fn test_associated_alpha_extra_channel() {
+    // RGB with premultiplied alpha and an unspecified fifth (e.g. depth) channel.
+    let pixels: [u8; 10] = [100, 50, 0, 128, 7, 255, 255, 255, 255, 9];
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut encoder = tiff.new_directory().unwrap();
+        let offset = encoder.write_data(&pixels[..]).unwrap();
+        encoder.write_tag(Tag::ImageWidth, 2u32).unwrap();
+        encoder.write_tag(Tag::ImageLength, 1u32).unwrap();
+        encoder
+            .write_tag(Tag::BitsPerSample, &[8u16; 5][..])
+            .unwrap();
+        encoder.write_tag(Tag::SamplesPerPixel, 5u16).unwrap();
+        encoder
+            .write_tag(Tag::PhotometricInterpretation, 2u16)
+            .unwrap();
+        encoder
+            .write_tag(Tag::ExtraSamples, &[1u16, 0][..])
+            .unwrap();
+        encoder.write_tag(Tag::StripOffsets, offset as u32).unwrap();
+        encoder.write_tag(Tag::StripByteCounts, 10u32).unwrap();
+        encoder.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(
+        decoder.colortype().unwrap(),
+        ColorType::Multiband {
+            bit_depth: 8,
+            num_samples: 5
+        }
+    );
+    assert_eq!(
+        decoder.extra_samples(),
+        &[ExtraSample::AssociatedAlpha, ExtraSample::Unspecified]
+    );
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(img_res) => assert_eq!(&pixels[..], &img_res[..]),
+        _ => panic!("Wrong data type"),
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file)
+        .unwrap()
+        .with_unassociated_alpha(true);
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(img_res) => assert_eq!(
+            &img_res[..],
+            &[199, 100, 0, 128, 7, 255, 255, 255, 255, 9][..]
+        ),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn test_associated_alpha_u64() {
+    // Products of samples near `u64::MAX` do not fit into an i128.
+    let max = u64::MAX;
+    let pixels: [u64; 8] = [
+        max - 1,
+        max - 2,
+        1,
+        max - 1,
+        1 << 62,
+        1 << 61,
+        max / 2,
+        max / 2,
+    ];
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut encoder = tiff.new_directory().unwrap();
+        let offset = encoder.write_data(&pixels[..]).unwrap();
+        encoder.write_tag(Tag::ImageWidth, 2u32).unwrap();
+        encoder.write_tag(Tag::ImageLength, 1u32).unwrap();
+        encoder
+            .write_tag(Tag::BitsPerSample, &[64u16; 4][..])
+            .unwrap();
+        encoder.write_tag(Tag::SamplesPerPixel, 4u16).unwrap();
+        encoder
+            .write_tag(Tag::PhotometricInterpretation, 2u16)
+            .unwrap();
+        encoder.write_tag(Tag::ExtraSamples, 1u16).unwrap();
+        encoder.write_tag(Tag::StripOffsets, offset as u32).unwrap();
+        encoder.write_tag(Tag::StripByteCounts, 64u32).unwrap();
+        encoder.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file)
+        .unwrap()
+        .with_unassociated_alpha(true);
+    match decoder.read_image().unwrap() {
+        DecodingResult::U64(img_res) => assert_eq!(
+            &img_res[..],
+            &[
+                max,
+                max - 1,
+                1,
+                max - 1,
+                (1 << 63) + 1,
+                1 << 62,
+                max,
+                max / 2
+            ][..]
+        ),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn test_multiband_roundtrip() {
+    let image_data: Vec<u16> = (0..7 * 5 * 9).map(|i| i * 100).collect();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::MultiBand<colortype::Gray16, 9>>(7, 5, &image_data)
+            .unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    {
+        let mut decoder = Decoder::new(&mut file).unwrap();
+        assert_eq!(
+            decoder.colortype().unwrap(),
+            ColorType::Multiband {
+                bit_depth: 16,
+                num_samples: 9
+            }
+        );
+        assert_eq!(decoder.extra_samples(), &[ExtraSample::Unspecified; 8]);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(img_res) => assert_eq!(image_data, img_res),
+            _ => panic!("Wrong data type"),
+        }
+    }
+}
+
+#[test]
+fn test_planar_multiband() {
+    // Three bands of a 3x2 image, each band stored in two strips of one row.
+    let bands: [[u8; 6]; 3] = [
+        [0, 1, 2, 3, 4, 5],
+        [10, 11, 12, 13, 14, 15],
+        [20, 21, 22, 23, 24, 25],
+    ];
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut encoder = tiff.new_directory().unwrap();
+        let mut offsets = Vec::new();
+        for band in &bands {
+            for row in band.chunks(3) {
+                offsets.push(encoder.write_data(row).unwrap() as u32);
+            }
+        }
+        encoder.write_tag(Tag::ImageWidth, 3u32).unwrap();
+        encoder.write_tag(Tag::ImageLength, 2u32).unwrap();
+        encoder
+            .write_tag(Tag::BitsPerSample, &[8u16; 3][..])
+            .unwrap();
+        encoder.write_tag(Tag::SamplesPerPixel, 3u16).unwrap();
+        encoder
+            .write_tag(Tag::PhotometricInterpretation, 1u16)
+            .unwrap();
+        encoder
+            .write_tag(Tag::ExtraSamples, &[0u16, 0][..])
+            .unwrap();
+        encoder.write_tag(Tag::PlanarConfiguration, 2u16).unwrap();
+        encoder.write_tag(Tag::StripOffsets, &offsets[..]).unwrap();
+        encoder
+            .write_tag(Tag::StripByteCounts, &[3u32; 6][..])
+            .unwrap();
+        encoder.write_tag(Tag::RowsPerStrip, 1u32).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(
+        decoder.colortype().unwrap(),
+        ColorType::Multiband {
+            bit_depth: 8,
+            num_samples: 3
+        }
+    );
+    assert_eq!(decoder.strip_count().unwrap(), 6);
+    match decoder.read_chunk(3).unwrap() {
+        DecodingResult::U8(chunk) => assert_eq!(&chunk[..], &[13, 14, 15][..]),
+        _ => panic!("Wrong data type"),
+    }
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(img_res) => assert_eq!(
+            &img_res[..],
+            &[0, 10, 20, 1, 11, 21, 2, 12, 22, 3, 13, 23, 4, 14, 24, 5, 15, 25][..]
+        ),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn test_rgb565_channels() {
+    // Two pixels, (31, 0, 1) and (1, 63, 16), packed into 16 bits each.
+    let pixels: [u8; 4] = [0xf8, 0x01, 0x0f, 0xf0];
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut encoder = tiff.new_directory().unwrap();
+        let offset = encoder.write_data(&pixels[..]).unwrap();
+        encoder.write_tag(Tag::ImageWidth, 2u32).unwrap();
+        encoder.write_tag(Tag::ImageLength, 1u32).unwrap();
+        encoder
+            .write_tag(Tag::BitsPerSample, &[5u16, 6, 5][..])
+            .unwrap();
+        encoder.write_tag(Tag::SamplesPerPixel, 3u16).unwrap();
+        encoder
+            .write_tag(Tag::PhotometricInterpretation, 2u16)
+            .unwrap();
+        encoder.write_tag(Tag::StripOffsets, offset as u32).unwrap();
+        encoder.write_tag(Tag::StripByteCounts, 4u32).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(
+        decoder.sample_descriptors(),
+        vec![
+            SampleDescriptor {
+                bits: 5,
+                format: SampleFormat::Uint
+            },
+            SampleDescriptor {
+                bits: 6,
+                format: SampleFormat::Uint
+            },
+            SampleDescriptor {
+                bits: 5,
+                format: SampleFormat::Uint
+            },
+        ]
+    );
+    assert!(decoder.read_image().is_err());
+    match &decoder.read_image_channels().unwrap()[..] {
+        [DecodingResult::U8(r), DecodingResult::U8(g), DecodingResult::U8(b)] => {
+            assert_eq!(r, &[31, 1]);
+            assert_eq!(g, &[0, 63]);
+            assert_eq!(b, &[1, 16]);
+        }
+        other => panic!("Wrong data types {:?}", other),
+    }
+}
+
+#[test]
+fn test_mixed_sample_format_channels() {
+    // A float band next to an 8-bit integer mask.
+    let values = [1.5f32, -2.25, 0.0];
+    let mask = [255u8, 0, 7];
+    let mut pixels = Vec::new();
+    for (value, mask) in values.iter().zip(&mask) {
+        pixels.extend_from_slice(&value.to_ne_bytes());
+        pixels.push(*mask);
+    }
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut encoder = tiff.new_directory().unwrap();
+        let offset = encoder.write_data(&pixels[..]).unwrap();
+        encoder.write_tag(Tag::ImageWidth, 3u32).unwrap();
+        encoder.write_tag(Tag::ImageLength, 1u32).unwrap();
+        encoder
+            .write_tag(Tag::BitsPerSample, &[32u16, 8][..])
+            .unwrap();
+        encoder.write_tag(Tag::SamplesPerPixel, 2u16).unwrap();
+        encoder
+            .write_tag(Tag::SampleFormat, &[3u16, 1][..])
+            .unwrap();
+        encoder
+            .write_tag(Tag::PhotometricInterpretation, 1u16)
+            .unwrap();
+        encoder.write_tag(Tag::ExtraSamples, 0u16).unwrap();
+        encoder.write_tag(Tag::StripOffsets, offset as u32).unwrap();
+        encoder
+            .write_tag(Tag::StripByteCounts, pixels.len() as u32)
+            .unwrap();
+        encoder.finish().unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert!(decoder.read_image().is_err());
+    match &decoder.read_image_channels().unwrap()[..] {
+        [DecodingResult::F32(band), DecodingResult::U8(band_mask)] => {
+            assert_eq!(band, &values);
+            assert_eq!(band_mask, &mask);
+        }
+        other => panic!("Wrong data types {:?}", other),
+    }
+}
+
+#[test]
+fn test_gray_f16_roundtrip() {
+    let image_data: Vec<f16> = (0..64)
+        .map(|i| f16::from_f32(i as f32 / 8.0 - 2.0))
+        .collect();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::Gray16Float>(8, 8, &image_data)
+            .unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    {
+        let mut decoder = Decoder::new(&mut file).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(16));
+        match decoder.read_image().unwrap() {
+            DecodingResult::F16(img_res) => assert_eq!(image_data, img_res),
+            _ => panic!("Wrong data type"),
+        }
+    }
+}
+
+/// Shuffles the big endian bytes of each sample of a row into planes and takes their differences,
+/// as done by the floating point predictor.
+fn fp_predict(samples: &[&[u8]]) -> Vec<u8> {
+    let width = samples[0].len();
+    let mut planes: Vec<u8> = (0..width)
+        .flat_map(|byte| samples.iter().map(move |sample| sample[byte]))
+        .collect();
+    for i in (1..planes.len()).rev() {
+        planes[i] = planes[i].wrapping_sub(planes[i - 1]);
+    }
+    planes
+}
+
+fn write_float_image(bits: u16, predictor: u16, data: &[u8], width: u32) -> Cursor<Vec<u8>> {
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut encoder = tiff.new_directory().unwrap();
+        let offset = encoder.write_data(data).unwrap();
+        encoder.write_tag(Tag::ImageWidth, width).unwrap();
+        encoder.write_tag(Tag::ImageLength, 1u32).unwrap();
+        encoder.write_tag(Tag::BitsPerSample, bits).unwrap();
+        encoder.write_tag(Tag::SampleFormat, 3u16).unwrap();
+        encoder
+            .write_tag(Tag::PhotometricInterpretation, 1u16)
+            .unwrap();
+        encoder.write_tag(Tag::Predictor, predictor).unwrap();
+        encoder.write_tag(Tag::StripOffsets, offset as u32).unwrap();
+        encoder
+            .write_tag(Tag::StripByteCounts, data.len() as u32)
+            .unwrap();
+        encoder.finish().unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file
+}
+
+#[test]
+fn test_f16_floating_point_predictor() {
+    let values = [f16::from_f32(1.5), f16::from_f32(-2.25), f16::from_f32(0.0)];
+    let bytes: Vec<_> = values.iter().map(|v| v.to_be_bytes()).collect();
+    let bytes: Vec<&[u8]> = bytes.iter().map(|b| &b[..]).collect();
+    let data = fp_predict(&bytes);
+
+    let mut decoder = Decoder::new(write_float_image(16, 3, &data, 3)).unwrap();
+    match decoder.read_image().unwrap() {
+        DecodingResult::F16(img_res) => assert_eq!(&img_res[..], &values[..]),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn test_f24_widened_to_f32() {
+    // Sign, 7 exponent bits with a bias of 63 and 16 mantissa bits.
+    let values = [1.5f32, -2.25, 0.0];
+    let bits = [0x3f_8000u32, 0xc0_2000, 0];
+
+    let native: Vec<u8> = bits
+        .iter()
+        .flat_map(|b| {
+            let bytes = b.to_ne_bytes();
+            if cfg!(target_endian = "little") {
+                [bytes[0], bytes[1], bytes[2]]
+            } else {
+                [bytes[1], bytes[2], bytes[3]]
+            }
+        })
+        .collect();
+    let mut decoder = Decoder::new(write_float_image(24, 1, &native, 3)).unwrap();
+    match decoder.read_image().unwrap() {
+        DecodingResult::F32(img_res) => assert_eq!(&img_res[..], &values[..]),
+        _ => panic!("Wrong data type"),
+    }
+    match &decoder.read_image_channels().unwrap()[..] {
+        [DecodingResult::F32(img_res)] => assert_eq!(&img_res[..], &values[..]),
+        other => panic!("Wrong data types {:?}", other),
+    }
+
+    let bytes: Vec<_> = bits.iter().map(|b| b.to_be_bytes()).collect();
+    let bytes: Vec<&[u8]> = bytes.iter().map(|b| &b[1..]).collect();
+    let mut decoder = Decoder::new(write_float_image(24, 3, &fp_predict(&bytes), 3)).unwrap();
+    match decoder.read_image().unwrap() {
+        DecodingResult::F32(img_res) => assert_eq!(&img_res[..], &values[..]),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn test_complex_f32_roundtrip() {
+    let image_data: Vec<[f32; 2]> = (0..64)
+        .map(|i| [i as f32 / 4.0, -(i as f32) * 1.5])
+        .collect();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::Complex32Float>(8, 8, &image_data)
+            .unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    {
+        let mut decoder = Decoder::new(&mut file).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(64));
+        match decoder.read_image().unwrap() {
+            DecodingResult::ComplexF32(img_res) => assert_eq!(image_data, img_res),
+            _ => panic!("Wrong data type"),
+        }
+    }
+}
+
+#[test]
+fn test_complex_i16_channels() {
+    let image_data: Vec<[i16; 2]> = (0..48).map(|i| [i * 100 - 2000, 7 - i]).collect();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::ComplexI16>(8, 6, &image_data)
+            .unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    {
+        let mut decoder = Decoder::new(&mut file).unwrap();
+        assert_eq!(
+            decoder.sample_descriptors(),
+            vec![SampleDescriptor {
+                bits: 32,
+                format: SampleFormat::ComplexInt,
+            }]
+        );
+        match decoder.read_image().unwrap() {
+            DecodingResult::ComplexI16(img_res) => assert_eq!(image_data, img_res),
+            _ => panic!("Wrong data type"),
+        }
+        match &decoder.read_image_channels().unwrap()[..] {
+            [DecodingResult::ComplexI16(channel)] => assert_eq!(&image_data, channel),
+            _ => panic!("Wrong data type"),
+        }
+    }
+}
+
+#[test]
+fn test_complex_tag() {
+    let value = [[1.5f32, -2.0], [0.25, 8.0]];
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut image = tiff.new_image::<colortype::Gray8>(1, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Unknown(65000), &value[..])
+            .unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Unknown(65001), value[1])
+            .unwrap();
+        image.write_data(&[0]).unwrap();
+    }
+
+    // Complex values are stored as their bytes.
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    let bytes: Vec<u8> = value
+        .iter()
+        .flatten()
+        .flat_map(|part| part.to_ne_bytes())
+        .collect();
+    assert_eq!(decoder.get_tag_u8_vec(Tag::Unknown(65000)).unwrap(), bytes);
+    assert_eq!(
+        decoder.get_tag_u8_vec(Tag::Unknown(65001)).unwrap(),
+        &bytes[8..]
+    );
+}
+
+#[test]
+fn test_lab_roundtrip() {
+    // Black, white, mid gray and a saturated red in L*a*b*.
+    let lab: [u8; 12] = [0, 128, 128, 255, 128, 128, 128, 128, 128, 138, 209, 198];
+    let lab16: Vec<u16> = lab.iter().map(|&sample| u16::from(sample) << 8).collect();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::Lab8>(2, 2, &lab).unwrap();
+        tiff.write_image::<colortype::Lab16>(2, 2, &lab16).unwrap();
+    }
+    file.seek(SeekFrom::Start(0)).unwrap();
+    {
+        let mut decoder = Decoder::new(&mut file).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Lab(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(img_res) => assert_eq!(img_res, lab),
+            _ => panic!("Wrong data type"),
+        }
+        decoder.next_image().unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Lab(16));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(img_res) => assert_eq!(img_res, lab16),
+            _ => panic!("Wrong data type"),
+        }
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap().with_lab_to_srgb(true);
+    assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(img_res) => {
+            let expected = [0, 0, 0, 255, 255, 255, 119, 119, 119, 255, 0, 0];
+            for (decoded, expected) in img_res.iter().zip(&expected) {
+                assert!((i16::from(*decoded) - expected).abs() <= 2, "{:?}", img_res);
+            }
+        }
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn test_append_images() {
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::Gray8>(2, 2, &[1, 2, 3, 4])
+            .unwrap();
+        tiff.write_image::<colortype::Gray8>(1, 1, &[5]).unwrap();
+    }
+    for page in 0..2u8 {
+        let mut tiff = TiffEncoder::append(&mut file).unwrap();
+        tiff.write_image::<colortype::Gray16>(2, 1, &[u16::from(page), 300])
+            .unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![1, 2, 3, 4]
+    ));
+    decoder.next_image().unwrap();
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![5]
+    ));
+    for page in 0..2u16 {
+        decoder.next_image().unwrap();
+        assert!(matches!(
+            decoder.read_image().unwrap(),
+            DecodingResult::U16(data) if data == vec![page, 300]
+        ));
+    }
+    assert!(!decoder.more_images());
+}
+
+#[test]
+fn test_append_big_images() {
+    let mut file = Cursor::new(Vec::new());
+    TiffEncoder::new_big(&mut file)
+        .unwrap()
+        .write_image::<colortype::RGB8>(1, 1, &[1, 2, 3])
+        .unwrap();
+    // The kind of the encoder has to match the one of the file.
+    assert!(TiffEncoder::append(&mut file).is_err());
+    TiffEncoder::append_big(&mut file)
+        .unwrap()
+        .write_image::<colortype::RGB8>(1, 1, &[4, 5, 6])
+        .unwrap();
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![1, 2, 3]
+    ));
+    decoder.next_image().unwrap();
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![4, 5, 6]
+    ));
+    assert!(!decoder.more_images());
+}
+
+/// A file in the byte order that the encoder does not write, to test that it is rejected.
+fn non_native_byte_order_file() -> Cursor<Vec<u8>> {
+    let name = if cfg!(target_endian = "little") {
+        "minisblack-1c-8b.tiff"
+    } else {
+        "cmyk-3c-16b.tiff"
+    };
+    let path = PathBuf::from(TEST_IMAGE_DIR).join(name);
+    Cursor::new(std::fs::read(path).unwrap())
+}
+
+#[test]
+fn test_append_non_native_byte_order() {
+    let mut file = non_native_byte_order_file();
+    let original = file.get_ref().clone();
+    assert!(matches!(
+        TiffEncoder::append(&mut file),
+        Err(TiffError::UnsupportedError(
+            TiffUnsupportedError::NonNativeByteOrder
+        ))
+    ));
+    assert_eq!(file.into_inner(), original);
+}
+
+#[test]
+fn test_edit_tags() {
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        tiff.write_image::<colortype::Gray8>(2, 2, &[1, 2, 3, 4])
+            .unwrap();
+        let mut image = tiff.new_image::<colortype::Gray8>(1, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Artist, "A value that does not fit in an entry")
+            .unwrap();
+        image.write_data(&[5]).unwrap();
+    }
+    let len = file.get_ref().len();
+
+    let mut editor = TagEditor::new(&mut file, 1).unwrap();
+    editor.write_tag(Tag::Software, "image-tiff").unwrap();
+    assert!(editor.remove_tag(Tag::Artist));
+    assert!(!editor.remove_tag(Tag::Copyright));
+    editor.finish().unwrap();
+    // The original directories and data are left in place.
+    assert!(file.get_ref().len() > len);
+
+    let mut editor = TagEditor::new(&mut file, 0).unwrap();
+    editor.write_tag(Tag::ImageDescription, "first").unwrap();
+    editor.finish().unwrap();
+    assert!(TagEditor::new(&mut file, 2).is_err());
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(
+        decoder.get_tag_ascii_string(Tag::ImageDescription).unwrap(),
+        "first"
+    );
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![1, 2, 3, 4]
+    ));
+    decoder.next_image().unwrap();
+    assert_eq!(
+        decoder.get_tag_ascii_string(Tag::Software).unwrap(),
+        "image-tiff"
+    );
+    assert!(decoder.find_tag(Tag::Artist).unwrap().is_none());
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![5]
+    ));
+    assert!(!decoder.more_images());
+}
+
+#[test]
+fn test_edit_keeps_unknown_types() {
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut image = tiff.new_image::<colortype::Gray8>(1, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Unknown(65000), 0xdead_beef_u32)
+            .unwrap();
+        image.write_data(&[5]).unwrap();
+    }
+
+    // Turn the LONG entry into one of type 99, which the decoder does not know.
+    let entry = |type_: u16| {
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&65000u16.to_ne_bytes());
+        entry.extend_from_slice(&type_.to_ne_bytes());
+        entry.extend_from_slice(&1u32.to_ne_bytes());
+        entry.extend_from_slice(&0xdead_beef_u32.to_ne_bytes());
+        entry
+    };
+    let (long, unknown) = (entry(4), entry(99));
+    let bytes = file.get_mut();
+    let pos = bytes.windows(12).position(|w| w == &long[..]).unwrap();
+    bytes[pos..pos + 12].copy_from_slice(&unknown);
+    let len = bytes.len();
+
+    let mut editor = TagEditor::new(&mut file, 0).unwrap();
+    editor.write_tag(Tag::Software, "image-tiff").unwrap();
+    editor.finish().unwrap();
+
+    // The entry is copied to the new directory as it was.
+    let bytes = file.get_ref();
+    assert!(bytes[len..].windows(12).any(|w| w == &unknown[..]));
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(
+        decoder.get_tag_ascii_string(Tag::Software).unwrap(),
+        "image-tiff"
+    );
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![5]
+    ));
+}
+#[test]
+fn test_edit_big_tags() {
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new_big(&mut file).unwrap();
+        let mut image = tiff.new_image::<colortype::Gray8>(1, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Artist, "A value that does not fit in an entry")
+            .unwrap();
+        image.write_data(&[5]).unwrap();
+    }
+
+    // The kind of the editor has to match the one of the file.
+    assert!(TagEditor::new(&mut file, 0).is_err());
+    let mut editor = TagEditor::new_big(&mut file, 0).unwrap();
+    editor.write_tag(Tag::Software, "image-tiff").unwrap();
+    editor.finish().unwrap();
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(
+        decoder.get_tag_ascii_string(Tag::Artist).unwrap(),
+        "A value that does not fit in an entry"
+    );
+    assert_eq!(
+        decoder.get_tag_ascii_string(Tag::Software).unwrap(),
+        "image-tiff"
+    );
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![5]
+    ));
+}
+
+#[test]
+fn test_edit_non_native_byte_order() {
+    let mut file = non_native_byte_order_file();
+    let original = file.get_ref().clone();
+    assert!(matches!(
+        TagEditor::new(&mut file, 0),
+        Err(TiffError::UnsupportedError(
+            TiffUnsupportedError::NonNativeByteOrder
+        ))
+    ));
+    assert_eq!(file.into_inner(), original);
+}
+#[test]
+fn test_streaming_encoder() {
+    let image_data: Vec<u8> = (0..=255).cycle().take(64 * 64).collect();
+    fn encode<W: Write + Seek, K: TiffKind>(tiff: &mut TiffEncoder<W, K>, image_data: &[u8]) {
+        tiff.write_image::<colortype::Gray8>(64, 64, image_data)
+            .unwrap();
+        tiff.write_image_with_compression::<colortype::Gray8, _>(
+            64,
+            64,
+            Deflate::default(),
+            image_data,
+        )
+        .unwrap();
+    }
+
+    // A `Vec` can only be written to, and the output matches the one of a seekable writer.
+    let mut file = Cursor::new(Vec::new());
+    encode(&mut TiffEncoder::new(&mut file).unwrap(), &image_data);
+    let mut tiff = TiffEncoder::new_streaming(Vec::new()).unwrap();
+    encode(&mut tiff, &image_data);
+    let stream = tiff.into_inner().unwrap();
+    assert_eq!(stream, file.into_inner());
+
+    let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+    for _ in 0..2 {
+        assert!(matches!(
+            decoder.read_image().unwrap(),
+            DecodingResult::U8(ref data) if *data == image_data
+        ));
+        if decoder.more_images() {
+            decoder.next_image().unwrap();
+        }
+    }
+    assert!(!decoder.more_images());
+
+    let mut file = Cursor::new(Vec::new());
+    encode(&mut TiffEncoder::new_big(&mut file).unwrap(), &image_data);
+    let mut tiff = TiffEncoder::new_big_streaming(Vec::new()).unwrap();
+    encode(&mut tiff, &image_data);
+    assert_eq!(tiff.into_inner().unwrap(), file.into_inner());
+}
+#[test]
+fn test_encode_progress() {
+    use std::sync::{Arc, Mutex};
+    use tiff::Progress;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&reports);
+    let mut file = Cursor::new(Vec::new());
+    let mut tiff = TiffEncoder::new(&mut file)
+        .unwrap()
+        .with_progress(move |progress| sink.lock().unwrap().push(progress));
+    let mut image = tiff.new_image::<colortype::Gray8>(4, 3).unwrap();
+    image.rows_per_strip(2).unwrap();
+    image.write_data(&[0; 12]).unwrap();
+
+    assert_eq!(
+        *reports.lock().unwrap(),
+        vec![
+            Progress {
+                chunk_index: 0,
+                chunk_count: 2,
+                bytes: 8
+            },
+            Progress {
+                chunk_index: 1,
+                chunk_count: 2,
+                bytes: 12
+            },
+        ]
+    );
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&reports);
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file)
+        .unwrap()
+        .with_progress(move |progress| sink.lock().unwrap().push(progress.chunk_index));
+    decoder.read_image().unwrap();
+    decoder.read_image_channels().unwrap();
+    assert_eq!(*reports.lock().unwrap(), vec![0, 1, 0, 1]);
+}
+#[test]
+#[cfg(feature = "async")]
+fn test_stream_error_abandons_image() {
+    use tiff::{ChunkError, ChunkErrorKind, TiffError};
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut image = tiff.new_image::<colortype::Gray8>(4, 3).unwrap();
+        image.rows_per_strip(1).unwrap();
+        let chunks: Vec<Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>> =
+            vec![Ok(vec![1; 4]), Err("broken".into()), Ok(vec![3; 4])];
+        let result =
+            runtime.block_on(image.write_chunks_from_stream(futures::stream::iter(chunks)));
+        match result {
+            Err(TiffError::ChunkError(ChunkError {
+                index: 1,
+                kind: ChunkErrorKind::Stream(e),
+            })) => assert_eq!(e.to_string(), "broken"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert!(image.finish().is_err());
+
+        // The next image takes the place of the abandoned one.
+        tiff.write_image::<colortype::Gray8>(1, 1, &[5]).unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == vec![5]
+    ));
+    assert!(!decoder.more_images());
+}
+#[test]
+#[cfg(feature = "async")]
+fn test_write_chunks_from_stream() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let image_data: Vec<u8> = (0..20 * 50).map(|i| (i % 7) as u8).collect();
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut image = tiff
+            .new_image_with_compression::<colortype::Gray8, _>(20, 50, Deflate::default())
+            .unwrap();
+        image.rows_per_strip(2).unwrap();
+        image.concurrency(3);
+        let chunks = image_data
+            .chunks(40)
+            .map(|chunk| Ok::<_, Box<dyn std::error::Error + Send + Sync>>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        runtime
+            .block_on(image.write_chunks_from_stream(futures::stream::iter(chunks)))
+            .unwrap();
+        image.finish().unwrap();
+    }
+
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(decoder.strip_count().unwrap(), 25);
+    assert!(matches!(
+        decoder.read_image().unwrap(),
+        DecodingResult::U8(data) if data == image_data
+    ));
+}
+#[test]
+#[cfg(feature = "async")]
+fn test_stream_chunk_validation() {
+    use tiff::{ChunkError, ChunkErrorKind, TiffError};
+
+    type Chunk = Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let write = |tiled: bool, chunks: Vec<Chunk>| {
+        let mut file = Cursor::new(Vec::new());
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut image = if tiled {
+            tiff.new_image_with_type::<colortype::Gray16>(
+                20,
+                20,
+                tiff::decoder::ChunkType::Tile,
+                Some((16, 16)),
+            )
+            .unwrap()
+        } else {
+            let mut image = tiff.new_image::<colortype::Gray16>(20, 20).unwrap();
+            image.rows_per_strip(16).unwrap();
+            image
+        };
+        runtime.block_on(image.write_chunks_from_stream(futures::stream::iter(chunks)))
+    };
+
+    // Tiles at the edge are padded to the full tile size.
+    let tiles = vec![Ok(vec![0; 512]), Ok(vec![0; 128])];
+    match write(true, tiles) {
+        Err(TiffError::ChunkError(ChunkError {
+            index: 1,
+            kind:
+                ChunkErrorKind::InvalidSize {
+                    expected: 512,
+                    actual: 128,
+                },
+        })) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(write(true, (0..4).map(|_| Ok(vec![0; 512])).collect()).is_ok());
+
+    // The last strip only has the remaining rows.
+    assert!(write(false, vec![Ok(vec![0; 640]), Ok(vec![0; 160])]).is_ok());
+    match write(false, vec![Ok(vec![0; 640])]) {
+        Err(TiffError::ChunkError(ChunkError {
+            index: 1,
+            kind: ChunkErrorKind::Missing,
+        })) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    let strips = vec![Ok(vec![0; 640]), Ok(vec![0; 160]), Ok(vec![0; 160])];
+    match write(false, strips) {
+        Err(TiffError::ChunkError(ChunkError {
+            index: 2,
+            kind: ChunkErrorKind::Unexpected,
+        })) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+#[test]
+fn test_write_data_in_parallel() {
+    let image_data: Vec<u16> = (0..50 * 40 * 3).map(|i| (i * 7 % 1000) as u16).collect();
+    // Tiles are passed one after another, with zeros past the right and bottom edge.
+    let mut tiles = Vec::new();
+    for (tile_y, tile_x) in (0..3).flat_map(|y| (0..4).map(move |x| (y * 16, x * 16))) {
+        for y in tile_y..tile_y + 16 {
+            for x in tile_x..tile_x + 16 {
+                match (x < 50 && y < 40).then(|| (y * 50 + x) * 3) {
+                    Some(start) => tiles.extend_from_slice(&image_data[start..start + 3]),
+                    None => tiles.extend_from_slice(&[0; 3]),
+                }
+            }
+        }
+    }
+
+    for (chunk_type, chunk_dims, data) in [
+        (tiff::decoder::ChunkType::Strip, None, &image_data),
+        (tiff::decoder::ChunkType::Tile, Some((16, 16)), &tiles),
+    ] {
+        for concurrency in [1, 4] {
+            let mut file = Cursor::new(Vec::new());
+            {
+                let mut tiff = TiffEncoder::new(&mut file).unwrap();
+                // The second image reuses the threads of the first.
+                for _ in 0..2 {
+                    let mut image = tiff
+                        .new_image_with_compression_with_type::<colortype::RGB16, _>(
+                            50,
+                            40,
+                            Deflate::default(),
+                            chunk_type,
+                            chunk_dims,
+                        )
+                        .unwrap();
+                    if chunk_dims.is_none() {
+                        image.rows_per_strip(3).unwrap();
+                    }
+                    image.concurrency(concurrency);
+                    image.write_data(data).unwrap();
+                }
+            }
+
+            file.seek(SeekFrom::Start(0)).unwrap();
+            let mut decoder = Decoder::new(&mut file).unwrap();
+            for i in 0..2 {
+                if i > 0 {
+                    decoder.next_image().unwrap();
+                }
+                assert_eq!(decoder.get_chunk_type(), chunk_type);
+                assert!(matches!(
+                    decoder.read_image().unwrap(),
+                    DecodingResult::U16(ref data) if *data == image_data
+                ));
+            }
+            assert!(!decoder.more_images());
+        }
+    }
+}
@@ -2,12 +2,13 @@ extern crate tiff;
 
 use std::io::{Cursor, Seek, Write};
 use tiff::{
-    decoder::{Decoder, DecodingResult},
+    decoder::{ChunkType, Decoder, DecodingResult},
     encoder::{
         colortype::{self, ColorType},
         compression::*,
         TiffEncoder, TiffValue,
     },
+    tags::{LercCompression, PhotometricInterpretation, Tag},
 };
 
 trait TestImage<const NUM_CHANNELS: usize>: From<Vec<<Self::Color as ColorType>::Inner>> {
@@ -155,3 +156,554 @@ fn encode_decode_with_deflate() {
 fn encode_decode_with_packbits() {
     encode_decode_with_compression(Packbits::default());
 }
+
+#[test]
+#[cfg(feature = "zstd")]
+fn encode_decode_with_zstd() {
+    encode_decode_with_compression(Zstd::default());
+    encode_decode_with_compression(Zstd::with_level(1));
+    encode_decode_with_compression(Zstd::with_level(19));
+}
+
+#[test]
+fn encode_decode_with_lerc() {
+    encode_decode_with_compression(Lerc::default());
+    encode_decode_with_compression(Lerc::default().with_compression(LercCompression::Deflate));
+    #[cfg(feature = "zstd")]
+    encode_decode_with_compression(Lerc::default().with_compression(LercCompression::Zstd));
+}
+
+#[test]
+fn encode_decode_with_lerc_max_error() {
+    let (width, height) = (37, 19);
+    let image_data: Vec<f32> = (0..width * height)
+        .map(|i| (i as f32 * 0.05).sin() * 300.0)
+        .collect();
+
+    let mut data = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        encoder
+            .write_image_with_compression::<colortype::Gray32Float, _>(
+                width,
+                height,
+                Lerc::with_max_error(0.5),
+                &image_data,
+            )
+            .unwrap();
+    }
+
+    data.set_position(0);
+    let mut decoder = Decoder::new(data).unwrap();
+    match decoder.read_image() {
+        Ok(DecodingResult::F32(decoded)) => {
+            for (decoded, expected) in decoded.iter().zip(&image_data) {
+                assert!((decoded - expected).abs() <= 0.5 + 1e-3);
+            }
+        }
+        unexpected => panic!("Decoding LERC failed: {:?}", unexpected),
+    }
+}
+
+#[cfg(feature = "webp")]
+fn encode_decode_with_webp<C: ColorType<Inner = u8>>(compression: WebP) -> (Vec<u8>, Vec<u8>) {
+    let (width, height) = (45, 29);
+    let samples = C::BITS_PER_SAMPLE.len();
+    let image_data: Vec<u8> = (0..width * height * samples as u32)
+        .map(|i| (i % 251) as u8)
+        .collect();
+
+    let mut data = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        let mut image = encoder
+            .new_image_with_compression::<C, _>(width, height, compression)
+            .unwrap();
+        image.rows_per_strip(8).unwrap();
+        image.write_data(&image_data).unwrap();
+    }
+
+    data.set_position(0);
+    let mut decoder = Decoder::new(data).unwrap();
+    match decoder.read_image() {
+        Ok(DecodingResult::U8(decoded)) => (image_data, decoded),
+        unexpected => panic!("Decoding WebP failed: {:?}", unexpected),
+    }
+}
+
+#[test]
+#[cfg(feature = "webp")]
+fn encode_decode_with_webp_lossless() {
+    let (expected, decoded) = encode_decode_with_webp::<colortype::RGB8>(WebP::lossless());
+    assert_eq!(expected, decoded);
+    let (expected, decoded) = encode_decode_with_webp::<colortype::RGBA8>(WebP::lossless());
+    assert_eq!(expected, decoded);
+}
+
+#[test]
+#[cfg(feature = "webp")]
+fn encode_decode_with_webp_lossy() {
+    let (expected, decoded) = encode_decode_with_webp::<colortype::RGB8>(WebP::with_quality(90.0));
+    assert_eq!(expected.len(), decoded.len());
+}
+
+#[test]
+#[cfg(feature = "webp")]
+fn encode_decode_tiles_with_webp() {
+    let tile: Vec<u8> = (0..16 * 16 * 4).map(|i| (i % 13 * 19) as u8).collect();
+
+    let mut data = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        let mut image = encoder
+            .new_image_with_compression_with_type::<colortype::RGBA8, _>(
+                32,
+                32,
+                WebP::lossless(),
+                ChunkType::Tile,
+                Some((16, 16)),
+            )
+            .unwrap();
+        for _ in 0..4 {
+            image.write_chunk_with_compression(&tile).unwrap();
+        }
+        image.finish().unwrap();
+    }
+
+    data.set_position(0);
+    let mut decoder = Decoder::new(data).unwrap();
+    for chunk in 0..4 {
+        match decoder.read_chunk(chunk).unwrap() {
+            DecodingResult::U8(decoded) => assert_eq!(tile, decoded),
+            unexpected => panic!("Decoding WebP failed: {:?}", unexpected),
+        }
+    }
+}
+
+fn encode_decode_with_jpeg<C: ColorType<Inner = u8>>(
+    compression: Jpeg,
+) -> Decoder<Cursor<Vec<u8>>> {
+    let (width, height) = (67, 41);
+    let samples = C::BITS_PER_SAMPLE.len();
+    let image_data: Vec<u8> = (0..width * height * samples)
+        .map(|i| (i / samples % width * 3 + i % samples * 20) as u8)
+        .collect();
+
+    let mut data = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        encoder
+            .write_image_with_compression::<C, _>(
+                width as u32,
+                height as u32,
+                compression,
+                &image_data,
+            )
+            .unwrap();
+    }
+
+    data.set_position(0);
+    let mut decoder = Decoder::new(data).unwrap();
+    match decoder.read_image() {
+        Ok(DecodingResult::U8(decoded)) => {
+            assert_eq!(image_data.len(), decoded.len());
+            for (decoded, expected) in decoded.iter().zip(&image_data) {
+                assert!((i16::from(*decoded) - i16::from(*expected)).abs() <= 8);
+            }
+        }
+        unexpected => panic!("Decoding JPEG failed: {:?}", unexpected),
+    }
+    decoder
+}
+
+#[test]
+fn encode_decode_with_jpeg_rgb() {
+    let mut decoder = encode_decode_with_jpeg::<colortype::RGB8>(Jpeg::with_quality(95));
+    assert_eq!(
+        decoder.get_tag_u32(Tag::PhotometricInterpretation).unwrap(),
+        u32::from(PhotometricInterpretation::RGB.to_u16())
+    );
+    assert!(decoder.get_tag_u8_vec(Tag::JPEGTables).is_ok());
+
+    let mut decoder =
+        encode_decode_with_jpeg::<colortype::RGB8>(Jpeg::with_quality(95).with_ycbcr(true));
+    assert_eq!(
+        decoder.get_tag_u32(Tag::PhotometricInterpretation).unwrap(),
+        u32::from(PhotometricInterpretation::YCbCr.to_u16())
+    );
+    assert_eq!(
+        decoder.get_tag_u16_vec(Tag::YCbCrSubsampling).unwrap(),
+        [2, 2]
+    );
+}
+
+#[test]
+fn encode_decode_with_jpeg_gray() {
+    encode_decode_with_jpeg::<colortype::Gray8>(Jpeg::with_quality(95));
+}
+
+#[test]
+fn encode_decode_tiles_with_jpeg() {
+    let tile: Vec<u8> = (0..16 * 16 * 3).map(|i| (i / 3 % 16 * 12) as u8).collect();
+
+    let mut data = Cursor::new(Vec::new());
+    {
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        let mut image = encoder
+            .new_image_with_compression_with_type::<colortype::RGB8, _>(
+                32,
+                16,
+                Jpeg::with_quality(95).with_ycbcr(true),
+                ChunkType::Tile,
+                Some((16, 16)),
+            )
+            .unwrap();
+        for _ in 0..2 {
+            image.write_chunk_with_compression(&tile).unwrap();
+        }
+        image.finish().unwrap();
+    }
+
+    data.set_position(0);
+    let mut decoder = Decoder::new(data).unwrap();
+    for chunk in 0..2 {
+        match decoder.read_chunk(chunk).unwrap() {
+            DecodingResult::U8(decoded) => {
+                for (decoded, expected) in decoded.iter().zip(&tile) {
+                    assert!((i16::from(*decoded) - i16::from(*expected)).abs() <= 8);
+                }
+            }
+            unexpected => panic!("Decoding JPEG failed: {:?}", unexpected),
+        }
+    }
+}
+
+#[test]
+fn jpeg_chunks_cover_whole_blocks() {
+    let mut data = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut data).unwrap();
+    for (ycbcr, rows, valid) in [
+        (false, 8, true),
+        (false, 12, false),
+        (true, 8, false),
+        (true, 16, true),
+        // A single strip covers the image.
+        (true, 41, true),
+    ] {
+        let mut image = encoder
+            .new_image_with_compression::<colortype::RGB8, _>(
+                67,
+                41,
+                Jpeg::with_quality(95).with_ycbcr(ycbcr),
+            )
+            .unwrap();
+        assert_eq!(image.rows_per_strip(rows).is_ok(), valid);
+    }
+
+    for (ycbcr, tile, valid) in [
+        (false, (8, 8), true),
+        (false, (8, 12), false),
+        (true, (8, 16), false),
+        (true, (16, 32), true),
+    ] {
+        let image = encoder.new_image_with_compression_with_type::<colortype::RGB8, _>(
+            67,
+            41,
+            Jpeg::with_quality(95).with_ycbcr(ycbcr),
+            ChunkType::Tile,
+            Some(tile),
+        );
+        assert_eq!(image.is_ok(), valid);
+    }
+}
+
+#[test]
+fn transcode_to_tiles_with_predictor() {
+    use tiff::encoder::Transcoder;
+    use tiff::tags::Predictor;
+
+    let rgb: Vec<u8> = (0..37 * 29 * 3).map(|i| (i % 251) as u8).collect();
+    let gray: Vec<u16> = (0..20 * 10).map(|i| i * 300).collect();
+    let mut source = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut source).unwrap();
+        let mut image = tiff
+            .new_image_with_compression::<colortype::RGB8, _>(37, 29, Packbits)
+            .unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Artist, "image-tiff")
+            .unwrap();
+        image.write_data(&rgb).unwrap();
+        tiff.write_image::<colortype::Gray16>(20, 10, &gray)
+            .unwrap();
+    }
+
+    source.set_position(0);
+    let mut file = Cursor::new(Vec::new());
+    {
+        let decoder = Decoder::new(&mut source).unwrap();
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        Transcoder::new(decoder, Deflate::default())
+            .unwrap()
+            .with_predictor(Predictor::Horizontal)
+            .with_tiles(16, 16)
+            .transcode(&mut tiff)
+            .unwrap();
+    }
+
+    file.set_position(0);
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(decoder.get_chunk_type(), ChunkType::Tile);
+    assert_eq!(decoder.get_tag_u32(Tag::Compression).unwrap(), 8);
+    assert_eq!(decoder.get_tag_u32(Tag::Predictor).unwrap(), 2);
+    assert_eq!(
+        decoder.get_tag_ascii_string(Tag::Artist).unwrap(),
+        "image-tiff"
+    );
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(data) => assert_eq!(data, rgb),
+        _ => panic!("Wrong data type"),
+    }
+    decoder.next_image().unwrap();
+    assert_eq!(decoder.get_chunk_type(), ChunkType::Tile);
+    match decoder.read_image().unwrap() {
+        DecodingResult::U16(data) => assert_eq!(data, gray),
+        _ => panic!("Wrong data type"),
+    }
+    assert!(!decoder.more_images());
+}
+
+#[test]
+fn transcode_floats_to_strips() {
+    use tiff::encoder::Transcoder;
+    use tiff::tags::Predictor;
+
+    let gray: Vec<f32> = (0..16 * 16).map(|i| i as f32 / 7.0).collect();
+    let mut source = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut source).unwrap();
+        let image = tiff
+            .new_image_with_compression_with_type::<colortype::Gray32Float, _>(
+                16,
+                16,
+                Lzw::default(),
+                ChunkType::Tile,
+                Some((16, 16)),
+            )
+            .unwrap();
+        image.write_data(&gray).unwrap();
+    }
+
+    // Integer predictors do not apply to floats.
+    source.set_position(0);
+    let decoder = Decoder::new(&mut source).unwrap();
+    let mut tiff = TiffEncoder::new(Cursor::new(Vec::new())).unwrap();
+    assert!(Transcoder::new(decoder, Deflate::default())
+        .unwrap()
+        .with_predictor(Predictor::Horizontal)
+        .transcode(&mut tiff)
+        .is_err());
+
+    source.set_position(0);
+    let mut file = Cursor::new(Vec::new());
+    {
+        let decoder = Decoder::new(&mut source).unwrap();
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        Transcoder::new(decoder, Deflate::default())
+            .unwrap()
+            .with_predictor(Predictor::FloatingPoint)
+            .with_strips(3)
+            .transcode(&mut tiff)
+            .unwrap();
+    }
+
+    file.set_position(0);
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(decoder.get_chunk_type(), ChunkType::Strip);
+    assert_eq!(decoder.strip_count().unwrap(), 6);
+    match decoder.read_image().unwrap() {
+        DecodingResult::F32(data) => assert_eq!(data, gray),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn transcode_samples_as_stored() {
+    use tiff::encoder::Transcoder;
+
+    // RGB with premultiplied alpha, and gray where white is zero.
+    let rgba: [u8; 8] = [100, 50, 0, 128, 7, 255, 255, 255];
+    let gray: [u8; 2] = [10, 200];
+    let mut source = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut source).unwrap();
+        let mut image = tiff.new_image::<colortype::RGBA8>(2, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::ExtraSamples, &[1u16][..])
+            .unwrap();
+        image.write_data(&rgba).unwrap();
+        let mut image = tiff.new_image::<colortype::Gray8>(2, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(
+                Tag::PhotometricInterpretation,
+                PhotometricInterpretation::WhiteIsZero.to_u16(),
+            )
+            .unwrap();
+        image.write_data(&gray).unwrap();
+    }
+
+    source.set_position(0);
+    let mut file = Cursor::new(Vec::new());
+    {
+        let decoder = Decoder::new(&mut source)
+            .unwrap()
+            .with_unassociated_alpha(true);
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        Transcoder::new(decoder, Deflate::default())
+            .unwrap()
+            .transcode(&mut tiff)
+            .unwrap();
+    }
+
+    file.set_position(0);
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(decoder.get_tag_u32_vec(Tag::ExtraSamples).unwrap(), [1]);
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(data) => assert_eq!(data, rgba),
+        _ => panic!("Wrong data type"),
+    }
+    decoder.next_image().unwrap();
+    assert_eq!(
+        decoder.get_tag_u32(Tag::PhotometricInterpretation).unwrap(),
+        u32::from(PhotometricInterpretation::BlackIsZero.to_u16())
+    );
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(data) => assert_eq!(data, [245, 55]),
+        _ => panic!("Wrong data type"),
+    }
+}
+
+#[test]
+fn copy_raw_chunks() {
+    let gray: Vec<u8> = (0..32 * 32).map(|i| (i % 7) as u8).collect();
+    let mut source = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut source).unwrap();
+        tiff.write_image::<colortype::Gray8>(1, 1, &[0]).unwrap();
+        let mut image = tiff
+            .new_image_with_compression_with_type::<colortype::Gray8, _>(
+                32,
+                32,
+                Deflate::default(),
+                ChunkType::Tile,
+                Some((16, 16)),
+            )
+            .unwrap();
+        for tile in 0..4 {
+            let (x, y) = (tile % 2 * 16, tile / 2 * 16);
+            let data: Vec<u8> = (y..y + 16)
+                .flat_map(|row| gray[row * 32 + x..row * 32 + x + 16].to_vec())
+                .collect();
+            image.write_chunk_with_compression(&data).unwrap();
+        }
+        image.finish().unwrap();
+    }
+
+    // Extract the second page by copying its tiles.
+    source.set_position(0);
+    let mut decoder = Decoder::new(&mut source).unwrap();
+    decoder.next_image().unwrap();
+    let chunks: Vec<Vec<u8>> = (0..decoder.tile_count().unwrap())
+        .map(|tile| decoder.read_chunk_raw(tile).unwrap())
+        .collect();
+    assert_eq!(chunks.len(), 4);
+    let (_, len) = decoder.chunk_file_range(0).unwrap();
+    assert_eq!(chunks[0].len() as u64, len);
+    let byte_order = decoder.byte_order();
+
+    let mut file = Cursor::new(Vec::new());
+    {
+        let mut tiff = TiffEncoder::new(&mut file).unwrap();
+        let mut image = tiff
+            .new_image_with_compression_with_type::<colortype::Gray8, _>(
+                32,
+                32,
+                Deflate::default(),
+                ChunkType::Tile,
+                Some((16, 16)),
+            )
+            .unwrap();
+        for chunk in &chunks {
+            image.write_raw_chunk(chunk, byte_order).unwrap();
+        }
+        assert!(image.write_raw_chunk(&chunks[0], byte_order).is_err());
+        image.finish().unwrap();
+    }
+
+    file.set_position(0);
+    let mut decoder = Decoder::new(&mut file).unwrap();
+    assert_eq!(decoder.read_chunk_raw(3).unwrap(), chunks[3]);
+    match decoder.read_image().unwrap() {
+        DecodingResult::U8(data) => assert_eq!(data, gray),
+        _ => panic!("Wrong data type"),
+    }
+    assert!(!decoder.more_images());
+}
+
+#[test]
+fn copy_raw_chunks_of_other_byte_order() {
+    use tiff::decoder::ByteOrder;
+    use tiff::{TiffError, TiffUnsupportedError};
+
+    let other = match ByteOrder::native() {
+        ByteOrder::LittleEndian => ByteOrder::BigEndian,
+        ByteOrder::BigEndian => ByteOrder::LittleEndian,
+    };
+    let mut tiff = TiffEncoder::new(Cursor::new(Vec::new())).unwrap();
+
+    // The byte order does not matter for samples of a single byte.
+    let mut image = tiff.new_image::<colortype::Gray8>(2, 1).unwrap();
+    image.write_raw_chunk(&[1, 2], other).unwrap();
+    image.finish().unwrap();
+
+    let mut image = tiff.new_image::<colortype::Gray16>(1, 1).unwrap();
+    assert!(matches!(
+        image.write_raw_chunk(&[1, 2], other),
+        Err(TiffError::UnsupportedError(
+            TiffUnsupportedError::NonNativeByteOrder
+        ))
+    ));
+    image.write_raw_chunk(&[1, 2], ByteOrder::native()).unwrap();
+    image.finish().unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "webp"))]
+fn webp_requires_feature() {
+    use tiff::encoder::TagEditor;
+    use tiff::tags::CompressionMethod;
+    use tiff::{TiffError, TiffUnsupportedError};
+
+    let mut data = Cursor::new(Vec::new());
+    TiffEncoder::new(&mut data)
+        .unwrap()
+        .write_image::<colortype::RGB8>(1, 1, &[1, 2, 3])
+        .unwrap();
+    let mut editor = TagEditor::new(&mut data, 0).unwrap();
+    editor
+        .write_tag(Tag::Compression, CompressionMethod::WebP.to_u16())
+        .unwrap();
+    editor.finish().unwrap();
+
+    data.set_position(0);
+    let mut decoder = Decoder::new(data).unwrap();
+    match decoder.read_image() {
+        Err(TiffError::UnsupportedError(TiffUnsupportedError::UnsupportedCompressionMethod(
+            CompressionMethod::WebP,
+        ))) => {}
+        unexpected => panic!("Unexpected result {:?}", unexpected),
+    }
+}